@@ -1,6 +1,13 @@
 //! AppContext manager of edge applications in an ETSI MEC system.
 
-use crate::messages::{AppContext, UserAppInstanceInfo};
+use crate::error::LcmpError;
+use crate::faas::Completion;
+use crate::journal::JournalAppContextServer;
+use crate::kvs::SharedKvs;
+use crate::meo::{MeoAppContextServer, OptimizationConf};
+use crate::messages::{AppContext, TimeStamp};
+use crate::rediskvs::RedisAppContextServer;
+use crate::sqlite::SqliteAppContextServer;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
@@ -9,25 +16,210 @@ use uuid::Uuid;
 pub trait AppContextServer {
     /// Create a new application context.
     /// Upon success the passed argument is modified with filled values, as needed.
-    fn new_context(&mut self, app_context: &mut AppContext) -> Result<(), String>;
+    fn new_context(&mut self, app_context: &mut AppContext) -> Result<(), LcmpError>;
+    /// Start the creation of a new application context, as new_context, but
+    /// without waiting for the operations that do not need to be performed
+    /// while holding the locks of the caller, e.g., the deployment of the app.
+    /// Return the outcome of such operations, if any: if it is a failure,
+    /// then the caller must delete the context.
+    /// The context is only created if admitted by admit, called with the
+    /// reference URI that would be assigned to it, if known in advance.
+    fn start_context(
+        &mut self,
+        app_context: &mut AppContext,
+        admit: &dyn Fn(&str) -> Result<(), LcmpError>,
+    ) -> Result<Option<Completion>, LcmpError> {
+        if let Some(reference_uri) = self.reference_uri(app_context)? {
+            admit(&reference_uri)?;
+        }
+        self.new_context(app_context)?;
+        Ok(None)
+    }
+    /// Return the reference URI that would be assigned to a new application
+    /// context, if it does not depend on the selection of a platform.
+    fn reference_uri(&mut self, app_context: &AppContext) -> Result<Option<String>, LcmpError> {
+        let _ = app_context;
+        Ok(None)
+    }
     /// Delete an active context.
-    fn del_context(&mut self, context_id: &str) -> Result<(), String>;
+    fn del_context(&mut self, context_id: &str) -> Result<(), LcmpError>;
     /// Get an active context.
-    fn get_context(&mut self, context_id: &str) -> Result<&AppContext, String>;
+    fn get_context(&mut self, context_id: &str) -> Result<&AppContext, LcmpError>;
     /// Update an active context.
     /// Only the callbackReference is allowed to be updated. If the other
     /// fields do not match exactly, then the command is denied.
-    fn update_context(&mut self, app_context: &mut AppContext) -> Result<(), String>;
+    fn update_context(&mut self, app_context: &mut AppContext) -> Result<(), LcmpError>;
     /// Return all active contexts.
-    fn list_contexts(&mut self) -> Result<Vec<String>, String>;
+    fn list_contexts(&mut self) -> Result<Vec<String>, LcmpError>;
     /// Return the status of the server.
-    fn status(&self) -> Result<(), String>;
+    fn status(&self) -> Result<(), LcmpError>;
+    /// Change the reference URI of an active context.
+    /// Return true if the reference URI has actually changed.
+    fn update_reference_uri(
+        &mut self,
+        context_id: &str,
+        reference_uri: &str,
+    ) -> Result<bool, LcmpError> {
+        let _ = (context_id, reference_uri);
+        Err(LcmpError::Internal(
+            "update of the reference URI not supported".to_string(),
+        ))
+    }
+    /// Set the IDs of the QKD keys of the user application instances of an
+    /// active context, in the same order, leaving unchanged those for which
+    /// none are given.
+    fn set_qkd_key_ids(
+        &mut self,
+        context_id: &str,
+        key_ids: &[Option<Vec<String>>],
+    ) -> Result<(), LcmpError> {
+        let _ = (context_id, key_ids);
+        Err(LcmpError::Internal(
+            "update of the QKD key IDs not supported".to_string(),
+        ))
+    }
+    /// Set the expiration time of the lease of an active context, if any.
+    fn set_expires_at(
+        &mut self,
+        context_id: &str,
+        expires_at: Option<TimeStamp>,
+    ) -> Result<(), LcmpError> {
+        let _ = (context_id, expires_at);
+        Err(LcmpError::Internal(
+            "update of the expiration time not supported".to_string(),
+        ))
+    }
+    /// Perform a round of optimization of the assignment of the active contexts.
+    /// Return the identifiers of the contexts whose reference URI has changed.
+    fn optimize(&mut self, conf: &OptimizationConf) -> Result<Vec<String>, LcmpError> {
+        let _ = conf;
+        Ok(vec![])
+    }
+    /// Move the active contexts assigned to a platform that has been removed
+    /// from the KVS to other platforms.
+    /// Return the identifiers of the contexts whose reference URI has changed.
+    fn evacuate_platform(&mut self, name: &str) -> Result<Vec<String>, LcmpError> {
+        let _ = name;
+        Ok(vec![])
+    }
+    /// Return the active contexts of the given device application.
+    fn contexts_by_associate_dev_app_id(
+        &mut self,
+        associate_dev_app_id: &str,
+    ) -> Result<Vec<String>, LcmpError> {
+        self.filter_contexts(&|x| x.associate_dev_app_id() == associate_dev_app_id)
+    }
+    /// Return the active contexts of the application with the given appDId.
+    fn contexts_by_appdid(&mut self, appdid: &str) -> Result<Vec<String>, LcmpError> {
+        self.filter_contexts(&|x| x.appInfo.appDId.as_deref() == Some(appdid))
+    }
+    /// Return the active contexts owned by the given device application.
+    fn contexts_by_owner(&mut self, owner: &str) -> Result<Vec<String>, LcmpError> {
+        self.filter_contexts(&|x| x.owner.as_deref() == Some(owner))
+    }
+    /// Return the active contexts with a user application instance with the given reference URI.
+    fn contexts_by_reference_uri(&mut self, reference_uri: &str) -> Result<Vec<String>, LcmpError> {
+        self.filter_contexts(&|x| {
+            x.appInfo
+                .userAppInstanceInfo
+                .iter()
+                .any(|x| x.referenceURI.as_deref() == Some(reference_uri))
+        })
+    }
+    /// Return the active contexts satisfying a predicate, by scanning all of them.
+    fn filter_contexts(
+        &mut self,
+        predicate: &dyn Fn(&AppContext) -> bool,
+    ) -> Result<Vec<String>, LcmpError> {
+        let mut ret = vec![];
+        for context_id in self.list_contexts()? {
+            if predicate(self.get_context(&context_id)?) {
+                ret.push(context_id);
+            }
+        }
+        Ok(ret)
+    }
+}
+
+/// Attribute of the application contexts on which a quota is enforced.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum QuotaKey {
+    associateDevAppId,
+    appDId,
+    appProvider,
+}
+
+impl QuotaKey {
+    /// Return the value of the attribute in an application context, if present.
+    fn value<'a>(&self, app_context: &'a AppContext) -> Option<&'a str> {
+        match self {
+            QuotaKey::associateDevAppId => Some(app_context.associate_dev_app_id()),
+            QuotaKey::appDId => app_context.appInfo.appDId.as_deref(),
+            QuotaKey::appProvider => Some(app_context.app_provider()),
+        }
+    }
+}
+
+impl std::fmt::Display for QuotaKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Maximum number of active contexts with the same value of an attribute.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Quota {
+    /// Attribute of the contexts.
+    pub key: QuotaKey,
+    /// Value of the attribute to which the quota applies.
+    /// If absent, the quota applies separately to every value, unless another
+    /// quota on the same attribute specifies the value of the request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// Maximum number of active contexts.
+    pub max_contexts: usize,
+}
+
+/// Return an error naming the quota exceeded, if any, by accepting a new
+/// context with the given active contexts.
+fn check_quotas<'a>(
+    quotas: &[Quota],
+    active: impl Iterator<Item = &'a AppContext> + Clone,
+    request: &AppContext,
+) -> Result<(), LcmpError> {
+    for quota in quotas {
+        let value = match quota.key.value(request) {
+            Some(x) => x,
+            None => continue,
+        };
+        let overridden = quota.value.is_none()
+            && quotas
+                .iter()
+                .any(|x| x.key == quota.key && x.value.as_deref() == Some(value));
+        if overridden || quota.value.as_deref().is_some_and(|x| x != value) {
+            continue;
+        }
+        let count = active
+            .clone()
+            .filter(|x| quota.key.value(x) == Some(value))
+            .count();
+        if count >= quota.max_contexts {
+            return Err(LcmpError::QuotaExceeded(format!(
+                "Quota exceeded: maximum number of active contexts with {} {} reached {}",
+                quota.key, value, quota.max_contexts
+            )));
+        }
+    }
+    Ok(())
 }
 
 /// Accepts new contexts up to a maximum and always return the same referenceURI.
-struct SimpleAppContextServer {
+pub(crate) struct SimpleAppContextServer {
     /// Maximum number of active contexts.
     max_contexts: usize,
+    /// Quotas on the number of active contexts with the same attributes.
+    quotas: Vec<Quota>,
     /// Default reference URI to be assigned to all application contexts if a specific one does not match.
     reference_uri_default: Option<String>,
     /// Map of reference URI by appDId
@@ -38,69 +230,87 @@ struct SimpleAppContextServer {
 
 impl SimpleAppContextServer {
     /// Create a SimpleAppContextServer that is empty upon construction and only uses the default reference URI.
-    fn default_empty(max_contexts: usize, reference_uri: &str) -> Self {
+    pub(crate) fn default_empty(max_contexts: usize, reference_uri: &str) -> Self {
         Self {
             max_contexts,
+            quotas: vec![],
             reference_uri_default: Some(reference_uri.to_string()),
             reference_uri_by_appdid: HashMap::new(),
             app_contexts: HashMap::new(),
         }
     }
     /// Create a SimpleAppContextServer that is empty upon construction and uses only reference URIs by AppDId.
-    fn appdid_empty(max_contexts: usize, reference_uri_by_appdid: HashMap<String, String>) -> Self {
+    fn appdid_empty(
+        max_contexts: usize,
+        reference_uri_by_appdid: HashMap<String, String>,
+        quotas: Vec<Quota>,
+    ) -> Self {
         Self {
             max_contexts,
+            quotas,
             reference_uri_default: None,
             reference_uri_by_appdid,
             app_contexts: HashMap::new(),
         }
     }
+
+    /// Return the reference URI of the appDId of a context, if mapped,
+    /// otherwise the default one, if defined.
+    fn find_reference_uri(&self, app_context: &AppContext) -> Option<String> {
+        app_context
+            .appInfo
+            .appDId
+            .as_ref()
+            .and_then(|x| self.reference_uri_by_appdid.get(x))
+            .or(self.reference_uri_default.as_ref())
+            .cloned()
+    }
+
+    /// Add or replace an active context, without any check.
+    pub(crate) fn put_context(&mut self, app_context: AppContext) {
+        if let Some(context_id) = &app_context.contextId {
+            self.app_contexts.insert(context_id.clone(), app_context);
+        }
+    }
 }
 
 impl AppContextServer for SimpleAppContextServer {
     /// If the maximum number of contexts is exceeded, the command is rejected.
     /// Otherwise the static reference URI is returned upon accepting the next context.
-    fn new_context(&mut self, app_context: &mut AppContext) -> Result<(), String> {
+    fn new_context(&mut self, app_context: &mut AppContext) -> Result<(), LcmpError> {
         // Maximum number of contexts: error
         if self.app_contexts.len() == self.max_contexts {
-            return Err(format!(
+            return Err(LcmpError::QuotaExceeded(format!(
                 "Maximum number of active contexts reached {}",
                 self.max_contexts
-            ));
+            )));
         }
 
         // Invalid context as a request: error
-        if let Err(x) = app_context.valid_request() {
-            return Err(x);
-        }
+        app_context
+            .valid_request()
+            .map_err(LcmpError::InvalidRequest)?;
+
+        // Quota exceeded: error
+        check_quotas(&self.quotas, self.app_contexts.values(), app_context)?;
 
         //
         // Accept the incoming request
         //
 
         // Find the reference URI for this request.
-        let mut reference_uri = None;
-        if let Some(appdid) = &app_context.appInfo.appDId {
-            if let Some(uri) = self.reference_uri_by_appdid.get(appdid) {
-                reference_uri = Some(uri.clone());
-            }
-        }
-
-        // If no match found, use the default reference URI, if defined.
-        if reference_uri.is_none() && self.reference_uri_default.is_some() {
-            reference_uri = Some(self.reference_uri_default.clone().unwrap());
-        }
+        let reference_uri = self.find_reference_uri(app_context);
 
         // Return an error if it was not possible to return a reference URI.
         if reference_uri.is_none() {
-            return Err(format!(
+            return Err(LcmpError::NotFound(format!(
                 "It was not possible to find a matching reference URI for AppDId: {}",
                 app_context
                     .appInfo
                     .appDId
                     .clone()
                     .unwrap_or("unspecified".to_string())
-            ));
+            )));
         }
 
         // Assign a new random context id.
@@ -108,12 +318,7 @@ impl AppContextServer for SimpleAppContextServer {
 
         // Assign the app instance id and the reference URI.
 
-        app_context
-            .appInfo
-            .userAppInstanceInfo
-            .push(UserAppInstanceInfo::from_reference_uri(
-                &reference_uri.unwrap(),
-            ));
+        app_context.assign_reference_uri(&reference_uri.unwrap());
 
         // Add to the list of active contexts.
         self.app_contexts
@@ -123,55 +328,131 @@ impl AppContextServer for SimpleAppContextServer {
     }
 
     /// Delete an active context.
-    fn del_context(&mut self, context_id: &str) -> Result<(), String> {
+    fn del_context(&mut self, context_id: &str) -> Result<(), LcmpError> {
         match self.app_contexts.remove(context_id) {
             Some(_) => Ok(()),
-            None => Err(format!("context ID not found: {}", context_id)),
+            None => Err(LcmpError::NotFound(format!(
+                "context ID not found: {}",
+                context_id
+            ))),
         }
     }
 
     /// Get an active context.
-    fn get_context(&mut self, context_id: &str) -> Result<&AppContext, String> {
+    fn get_context(&mut self, context_id: &str) -> Result<&AppContext, LcmpError> {
         match self.app_contexts.get(context_id) {
             Some(x) => Ok(x),
-            None => Err(format!("context ID not found: {}", context_id)),
+            None => Err(LcmpError::NotFound(format!(
+                "context ID not found: {}",
+                context_id
+            ))),
         }
     }
 
     /// Update an active context.
     /// Only the callbackReference is allowed to be updated. If the other
     /// fields do not match exactly, then the command is denied.
-    fn update_context(&mut self, app_context: &mut AppContext) -> Result<(), String> {
+    fn update_context(&mut self, app_context: &mut AppContext) -> Result<(), LcmpError> {
         if let Some(context_id) = &app_context.contextId {
             match self.app_contexts.get_mut(context_id.as_str()) {
                 Some(x) => {
-                    match x.identical_except_callback_reference(&app_context) {
+                    match x.identical_except_callback_reference(app_context) {
                         true => {
                             x.callbackReference = app_context.callbackReference.clone();
                             return Ok(());
                         }
                         false => {
-                            return Err(format!(
+                            return Err(LcmpError::Conflict(
                                 "AppContext in the request does not match that in the server"
+                                    .to_string(),
                             ));
                         }
                     };
                 }
-                None => return Err(format!("context ID not found: {}", context_id)),
+                None => {
+                    return Err(LcmpError::NotFound(format!(
+                        "context ID not found: {}",
+                        context_id
+                    )))
+                }
             }
         }
-        Err("context ID not specified in the request".to_string())
+        Err(LcmpError::InvalidRequest(
+            "context ID not specified in the request".to_string(),
+        ))
     }
 
     /// Return all active contexts.
-    fn list_contexts(&mut self) -> Result<Vec<String>, String> {
+    fn list_contexts(&mut self) -> Result<Vec<String>, LcmpError> {
         Ok(self.app_contexts.iter().map(|x| x.0.to_string()).collect())
     }
 
     /// Always return good health.
-    fn status(&self) -> Result<(), String> {
+    fn status(&self) -> Result<(), LcmpError> {
         Ok(())
     }
+
+    fn reference_uri(&mut self, app_context: &AppContext) -> Result<Option<String>, LcmpError> {
+        Ok(self.find_reference_uri(app_context))
+    }
+
+    /// Change the reference URI of all the user application instances of the context.
+    fn update_reference_uri(
+        &mut self,
+        context_id: &str,
+        reference_uri: &str,
+    ) -> Result<bool, LcmpError> {
+        match self.app_contexts.get_mut(context_id) {
+            Some(x) => {
+                let mut changed = false;
+                for info in &mut x.appInfo.userAppInstanceInfo {
+                    if info.referenceURI.as_deref() != Some(reference_uri) {
+                        info.referenceURI = Some(reference_uri.to_string());
+                        changed = true;
+                    }
+                }
+                Ok(changed)
+            }
+            None => Err(LcmpError::NotFound(format!(
+                "context ID not found: {}",
+                context_id
+            ))),
+        }
+    }
+
+    fn set_qkd_key_ids(
+        &mut self,
+        context_id: &str,
+        key_ids: &[Option<Vec<String>>],
+    ) -> Result<(), LcmpError> {
+        match self.app_contexts.get_mut(context_id) {
+            Some(x) => {
+                x.assign_qkd_key_ids(key_ids);
+                Ok(())
+            }
+            None => Err(LcmpError::NotFound(format!(
+                "context ID not found: {}",
+                context_id
+            ))),
+        }
+    }
+
+    fn set_expires_at(
+        &mut self,
+        context_id: &str,
+        expires_at: Option<TimeStamp>,
+    ) -> Result<(), LcmpError> {
+        match self.app_contexts.get_mut(context_id) {
+            Some(x) => {
+                x.expiresAt = expires_at;
+                Ok(())
+            }
+            None => Err(LcmpError::NotFound(format!(
+                "context ID not found: {}",
+                context_id
+            ))),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -184,11 +465,15 @@ pub struct ReferenceUriMapByAppDIdElem {
 pub struct SimpleAppContextServerConf {
     max_contexts: usize,
     mapping: Vec<ReferenceUriMapByAppDIdElem>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    quotas: Vec<Quota>,
 }
 
-/// Factory to build ApplicationListServer objects from a string
+/// Factory to build AppContextServer objects from a string
+/// The KVS is used only by the AppContextServer of type meo.
 pub fn build_app_context_server(
     value: &str,
+    kvs: &SharedKvs,
 ) -> Result<Box<dyn AppContextServer + Send + Sync>, String> {
     if let Some(x) = value.find("single;") {
         if x == 0 {
@@ -222,6 +507,7 @@ pub fn build_app_context_server(
                         return Ok(Box::new(SimpleAppContextServer::appdid_empty(
                             conf.max_contexts,
                             reference_uri_by_appdid,
+                            conf.quotas,
                         )));
                     } else {
                         return Err(format!("invalid input file: {}", &filename));
@@ -232,6 +518,17 @@ pub fn build_app_context_server(
                 }
             }
         }
+    } else if let Some(params) = value.strip_prefix("journal;") {
+        return Ok(Box::new(JournalAppContextServer::from_params(params)?));
+    } else if let Some(params) = value.strip_prefix("sqlite;") {
+        return Ok(Box::new(SqliteAppContextServer::from_params(params)?));
+    } else if let Some(params) = value.strip_prefix("redis;") {
+        return Ok(Box::new(RedisAppContextServer::from_params(params)?));
+    } else if value == "meo" || value.starts_with("meo;") {
+        return Ok(Box::new(MeoAppContextServer::from_params(
+            kvs.clone(),
+            value.strip_prefix("meo").unwrap_or_default(),
+        )?));
     }
     Err("could not create the AppContextServer".to_string())
 }
@@ -241,18 +538,43 @@ mod tests {
     use std::collections::HashSet;
 
     use super::*;
+    use crate::kvs::Kvs;
 
     #[test]
     fn test_build_app_context_server() {
-        assert!(build_app_context_server("non-existing-type").is_err());
+        let kvs = Kvs::shared();
 
-        assert!(build_app_context_server("single;not-number,URI").is_err());
+        assert!(build_app_context_server("non-existing-type", &kvs).is_err());
 
-        assert!(build_app_context_server("single;10").is_err());
+        assert!(build_app_context_server("single;not-number,URI", &kvs).is_err());
 
-        assert!(build_app_context_server("single;10,").is_err());
+        assert!(build_app_context_server("single;10", &kvs).is_err());
 
-        assert!(build_app_context_server("single;1,2,3").is_err());
+        assert!(build_app_context_server("single;10,", &kvs).is_err());
+
+        assert!(build_app_context_server("single;1,2,3", &kvs).is_err());
+
+        assert!(build_app_context_server("meo", &kvs).is_ok());
+
+        assert!(build_app_context_server("meo;max_load=0.9;seed=1", &kvs).is_ok());
+
+        assert!(build_app_context_server("meo;max_load=x", &kvs).is_err());
+
+        assert!(build_app_context_server("meoo", &kvs).is_err());
+
+        let dir = std::env::temp_dir().join(format!("lcmp-build-{}", Uuid::new_v4().simple()));
+        assert!(build_app_context_server(
+            &format!("journal;dir={};max=10;uri=URI", dir.display()),
+            &kvs
+        )
+        .is_ok());
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert!(build_app_context_server("journal;max=10;uri=URI", &kvs).is_err());
+
+        assert!(build_app_context_server("sqlite;path=:memory:;max=10;uri=URI", &kvs).is_ok());
+
+        assert!(build_app_context_server("sqlite;max=10;uri=URI", &kvs).is_err());
     }
 
     #[test]
@@ -261,6 +583,7 @@ mod tests {
         let mut conf = SimpleAppContextServerConf {
             max_contexts: 10,
             mapping: vec![],
+            quotas: vec![],
         };
         conf.mapping.push(ReferenceUriMapByAppDIdElem {
             appdid: "1".to_string(),
@@ -313,10 +636,8 @@ mod tests {
         assert!(s.list_contexts().unwrap().len() == 10);
 
         // get the app contexts one by one
-        for elem in &all_contexts {
-            if let Some(context_id) = elem {
-                s.get_context(context_id.as_str())?;
-            }
+        for context_id in all_contexts.iter().flatten() {
+            s.get_context(context_id.as_str())?;
         }
 
         // fail to get a non-existing app context
@@ -370,6 +691,52 @@ mod tests {
             assert!(&s.update_context(&mut b).is_err());
         }
 
+        // change the reference URI
+        if let Some(context_id) = &a.contextId {
+            assert!(s.update_reference_uri(context_id, "newReferenceURI")?);
+            assert!(!s.update_reference_uri(context_id, "newReferenceURI")?);
+            assert_eq!(
+                Some("newReferenceURI".to_string()),
+                s.get_context(context_id)?.appInfo.userAppInstanceInfo[0]
+                    .referenceURI
+                    .clone()
+            );
+        }
+        assert!(s
+            .update_reference_uri("not-a-valid-context-id", "newReferenceURI")
+            .is_err());
+
+        // set the QKD key IDs, which are ignored in the updates
+        if let Some(context_id) = &a.contextId {
+            s.set_qkd_key_ids(context_id, &[Some(vec!["key1".to_string()])])?;
+            let mut stored = s.get_context(context_id)?.clone();
+            assert_eq!(
+                Some(vec!["key1".to_string()]),
+                stored.appInfo.userAppInstanceInfo[0].qkdKeyIds
+            );
+            stored.appInfo.userAppInstanceInfo[0].qkdKeyIds = None;
+            s.update_context(&mut stored)?;
+            assert!(s.get_context(context_id)?.appInfo.userAppInstanceInfo[0]
+                .qkdKeyIds
+                .is_some());
+        }
+        assert!(s
+            .set_qkd_key_ids("not-a-valid-context-id", &[None])
+            .is_err());
+
+        // set the expiration time of the lease
+        let expires_at = TimeStamp {
+            seconds: 10,
+            nanoSeconds: 0,
+        };
+        if let Some(context_id) = &a.contextId {
+            s.set_expires_at(context_id, Some(expires_at))?;
+            assert_eq!(Some(expires_at), s.get_context(context_id)?.expiresAt);
+        }
+        assert!(s
+            .set_expires_at("not-a-valid-context-id", Some(expires_at))
+            .is_err());
+
         // cannot add another context
         a.contextId = None;
         a.appInfo.userAppInstanceInfo.clear();
@@ -377,4 +744,132 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_simple_app_context_server_quotas() -> Result<(), String> {
+        let filename =
+            std::env::temp_dir().join(format!("lcmp-quotas-{}.json", Uuid::new_v4().simple()));
+        std::fs::write(
+            &filename,
+            r#"{
+            "max_contexts": 100,
+            "mapping": [
+                { "appdid": "app1", "reference_uri": "uri1" },
+                { "appdid": "app2", "reference_uri": "uri2" }
+            ],
+            "quotas": [
+                { "key": "associateDevAppId", "max_contexts": 2 },
+                { "key": "associateDevAppId", "value": "gateway", "max_contexts": 3 },
+                { "key": "appDId", "value": "app2", "max_contexts": 1 },
+                { "key": "appProvider", "max_contexts": 4 }
+            ]
+        }"#,
+        )
+        .map_err(|x| x.to_string())?;
+        let mut s =
+            build_app_context_server(&format!("file;{}", filename.display()), &Kvs::shared())?;
+        let _ = std::fs::remove_file(&filename);
+
+        let request = |dev_app: &str, appdid: &str, provider: &str| -> AppContext {
+            let mut a = serde_json::to_value(AppContext::request_from_name_provider(
+                "my_app_name",
+                provider,
+            ))
+            .unwrap();
+            a["associateDevAppId"] = serde_json::json!(dev_app);
+            a["appInfo"]["appDId"] = serde_json::json!(appdid);
+            serde_json::from_value(a).unwrap()
+        };
+
+        // per device app, with an override for a specific one
+        s.new_context(&mut request("dev1", "app1", "provider1"))?;
+        s.new_context(&mut request("dev1", "app1", "provider1"))?;
+        let err = s
+            .new_context(&mut request("dev1", "app1", "provider1"))
+            .unwrap_err();
+        assert!(
+            matches!(&err, LcmpError::QuotaExceeded(x) if x.contains("associateDevAppId dev1")),
+            "{}",
+            err
+        );
+        for _ in 0..3 {
+            s.new_context(&mut request("gateway", "app1", "provider2"))?;
+        }
+        let err = s
+            .new_context(&mut request("gateway", "app1", "provider3"))
+            .unwrap_err();
+        assert!(
+            matches!(&err, LcmpError::QuotaExceeded(x) if x.contains("associateDevAppId gateway")),
+            "{}",
+            err
+        );
+
+        // per appDId, only for a specific one
+        s.new_context(&mut request("dev2", "app2", "provider3"))?;
+        let err = s
+            .new_context(&mut request("dev3", "app2", "provider3"))
+            .unwrap_err();
+        assert!(
+            matches!(&err, LcmpError::QuotaExceeded(x) if x.contains("appDId app2")),
+            "{}",
+            err
+        );
+
+        // per provider
+        s.new_context(&mut request("dev3", "app1", "provider2"))?;
+        let err = s
+            .new_context(&mut request("dev4", "app1", "provider2"))
+            .unwrap_err();
+        assert!(
+            matches!(&err, LcmpError::QuotaExceeded(x) if x.contains("appProvider provider2")),
+            "{}",
+            err
+        );
+
+        // a slot is freed upon deletion
+        let context_id = s.contexts_by_associate_dev_app_id("dev1")?[0].clone();
+        s.del_context(&context_id)?;
+        s.new_context(&mut request("dev1", "app1", "provider1"))?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_simple_app_context_server_errors() -> Result<(), String> {
+        let mut s = SimpleAppContextServer::default_empty(1, "referenceURI");
+        let mut a = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        a.contextId = Some("not-empty-context-id".to_string());
+        assert!(matches!(
+            s.new_context(&mut a),
+            Err(LcmpError::InvalidRequest(_))
+        ));
+
+        a.contextId = None;
+        s.new_context(&mut a)?;
+        let mut b = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        assert!(matches!(
+            s.new_context(&mut b),
+            Err(LcmpError::QuotaExceeded(_))
+        ));
+
+        assert!(matches!(
+            s.get_context("non-existing"),
+            Err(LcmpError::NotFound(_))
+        ));
+        assert!(matches!(
+            s.del_context("non-existing"),
+            Err(LcmpError::NotFound(_))
+        ));
+        assert!(matches!(
+            s.update_context(&mut b),
+            Err(LcmpError::InvalidRequest(_))
+        ));
+        b.contextId = a.contextId.clone();
+        assert!(matches!(
+            s.update_context(&mut b),
+            Err(LcmpError::Conflict(_))
+        ));
+
+        Ok(())
+    }
 }
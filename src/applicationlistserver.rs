@@ -1,15 +1,38 @@
 //! Directory of ETSI MEC applications.
 
-use crate::messages::{application_list_from_file, ApplicationList, ApplicationListInfo};
+use crate::error::LcmpError;
+use crate::kvs::SharedKvs;
+use crate::messages::{
+    application_list_from_file, AppList, ApplicationList, ApplicationListInfo,
+    ApplicationLocationAvailability, Validate,
+};
+use crate::rediskvs::RedisApplicationListServer;
+use log::{info, warn};
+use signal_hook::SigId;
+use std::collections::hash_map::DefaultHasher;
 use std::fs::File;
-use std::io::Write;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock, Weak};
+use std::time::{Duration, SystemTime};
 
 /// Interface of an ApplicationListServer
 pub trait ApplicationListServer {
     /// Return the ApplicationList filtered through the given ApplicationListInfo
-    fn application_list(&self, info: ApplicationListInfo) -> Result<ApplicationList, String>;
+    fn application_list(&self, info: ApplicationListInfo) -> Result<ApplicationList, LcmpError>;
     /// Return the status of the server
-    fn status(&self) -> Result<(), String>;
+    fn status(&self) -> Result<(), LcmpError>;
+    /// Return the response to an ApplicationLocationAvailability request, i.e.,
+    /// the request with the locations where the application is available
+    fn app_location_availability(
+        &self,
+        request: &ApplicationLocationAvailability,
+    ) -> Result<ApplicationLocationAvailability, LcmpError> {
+        request.valid_request().map_err(LcmpError::InvalidRequest)?;
+        self.application_list(ApplicationListInfo::empty())?
+            .location_availability(request)
+            .ok_or_else(|| LcmpError::NotFound(format!("no app matching: {}", request)))
+    }
 }
 
 /// Static ApplicationList store.
@@ -49,9 +72,9 @@ impl StaticApplicationListServer {
 
 impl ApplicationListServer for StaticApplicationListServer {
     /// Return an ApplicationList message containing only the matching query.
-    fn application_list(&self, info: ApplicationListInfo) -> Result<ApplicationList, String> {
+    fn application_list(&self, info: ApplicationListInfo) -> Result<ApplicationList, LcmpError> {
         match &self.last_err {
-            Some(err) => Err(err.clone()),
+            Some(err) => Err(LcmpError::BackendUnavailable(err.clone())),
             None => match &self.app_list {
                 Some(x) => Ok(ApplicationList {
                     appList: x.matching_info(&info),
@@ -62,23 +85,265 @@ impl ApplicationListServer for StaticApplicationListServer {
     }
 
     /// Return the status based on the last apps configuration.
-    fn status(&self) -> Result<(), String> {
+    fn status(&self) -> Result<(), LcmpError> {
         match &self.last_err {
-            Some(x) => Err(x.clone()),
+            Some(x) => Err(LcmpError::BackendUnavailable(x.clone())),
             None => Ok(()),
         }
     }
 }
 
+/// Period used to check for changes of the file of a WatchedApplicationListServer.
+const WATCH_PERIOD: Duration = Duration::from_secs(1);
+
+/// Read an ApplicationList from a file and validate it.
+fn load_application_list(filename: &str) -> Result<ApplicationList, String> {
+    let mut file = File::open(filename).map_err(|x| x.to_string())?;
+    let app_list = application_list_from_file(&mut file).map_err(|x| x.to_string())?;
+    app_list.validate()?;
+    Ok(app_list)
+}
+
+/// Modification time, size, and hash of the content of a file.
+type Fingerprint = (SystemTime, u64, u64);
+
+/// Return the fingerprint of a file, used to detect changes, including those
+/// that do not alter its modification time and size.
+fn file_fingerprint(filename: &str) -> Option<Fingerprint> {
+    let metadata = std::fs::metadata(filename).ok()?;
+    let mut hasher = DefaultHasher::new();
+    std::fs::read(filename).ok()?.hash(&mut hasher);
+    Some((metadata.modified().ok()?, metadata.len(), hasher.finish()))
+}
+
+/// State of a WatchedApplicationListServer, shared with the watcher thread.
+struct WatchedState {
+    app_list: Option<ApplicationList>,
+    last_err: Option<String>,
+}
+
+/// Number of reloads performed by the watcher thread, which can be waited for.
+type Reloads = Arc<(Mutex<u64>, Condvar)>;
+
+/// ApplicationList store that is reloaded from file when the latter changes
+/// or upon reception of a SIGHUP.
+/// The new list replaces the current one only if it is valid, otherwise
+/// the previous one keeps being served and the error is reported via status().
+struct WatchedApplicationListServer {
+    state: Arc<RwLock<WatchedState>>,
+    /// Number of reloads performed, which the tests wait for.
+    #[cfg_attr(not(test), allow(dead_code))]
+    reloads: Reloads,
+    /// Registration of the SIGHUP handler, removed when the server is dropped.
+    sighup_id: Option<SigId>,
+}
+
+impl WatchedApplicationListServer {
+    /// Load the ApplicationList from file and start a thread that checks the file
+    /// for changes with the given period. The thread terminates when the server is dropped.
+    fn from_file(filename: &str, period: Duration) -> Self {
+        let fingerprint = file_fingerprint(filename);
+        let mut state = WatchedState {
+            app_list: None,
+            last_err: None,
+        };
+        match load_application_list(filename) {
+            Ok(a) => state.app_list = Some(a),
+            Err(err) => state.last_err = Some(err),
+        }
+        let state = Arc::new(RwLock::new(state));
+
+        let reloads = Reloads::default();
+
+        let sighup = Arc::new(AtomicBool::new(false));
+        let sighup_id =
+            match signal_hook::flag::register(signal_hook::consts::SIGHUP, sighup.clone()) {
+                Ok(x) => Some(x),
+                Err(err) => {
+                    warn!("could not register SIGHUP handler: {}", err);
+                    None
+                }
+            };
+        let weak_state = Arc::downgrade(&state);
+        let filename = filename.to_string();
+        let thread_reloads = reloads.clone();
+        std::thread::spawn(move || {
+            Self::watch(
+                weak_state,
+                filename,
+                fingerprint,
+                period,
+                sighup,
+                thread_reloads,
+            )
+        });
+
+        Self {
+            state,
+            reloads,
+            sighup_id,
+        }
+    }
+
+    /// Return the number of reloads performed so far.
+    #[cfg(test)]
+    fn reloads(&self) -> u64 {
+        *self.reloads.0.lock().unwrap()
+    }
+
+    /// Wait until more than the given number of reloads have been performed.
+    /// Return false if the timeout expires.
+    #[cfg(test)]
+    fn wait_reload(&self, reloads: u64, timeout: Duration) -> bool {
+        let (count, cvar) = self.reloads.as_ref();
+        !cvar
+            .wait_timeout_while(count.lock().unwrap(), timeout, |x| *x <= reloads)
+            .unwrap()
+            .1
+            .timed_out()
+    }
+
+    /// Body of the watcher thread.
+    fn watch(
+        state: Weak<RwLock<WatchedState>>,
+        filename: String,
+        mut fingerprint: Option<Fingerprint>,
+        period: Duration,
+        sighup: Arc<AtomicBool>,
+        reloads: Reloads,
+    ) {
+        loop {
+            std::thread::sleep(period);
+            let state = match state.upgrade() {
+                Some(x) => x,
+                None => break,
+            };
+            let new_fingerprint = file_fingerprint(&filename);
+            if sighup.swap(false, Ordering::Relaxed) || new_fingerprint != fingerprint {
+                fingerprint = new_fingerprint;
+                let res = load_application_list(&filename);
+                let mut state = state.write().unwrap();
+                match res {
+                    Ok(a) => {
+                        info!("reloaded application list from {}", filename);
+                        state.app_list = Some(a);
+                        state.last_err = None;
+                    }
+                    Err(err) => {
+                        warn!(
+                            "could not reload application list from {}: {}",
+                            filename, err
+                        );
+                        state.last_err = Some(err);
+                    }
+                }
+                let (count, cvar) = reloads.as_ref();
+                *count.lock().unwrap() += 1;
+                cvar.notify_all();
+            }
+        }
+    }
+}
+
+impl Drop for WatchedApplicationListServer {
+    fn drop(&mut self) {
+        if let Some(sighup_id) = self.sighup_id.take() {
+            signal_hook::low_level::unregister(sighup_id);
+        }
+    }
+}
+
+impl ApplicationListServer for WatchedApplicationListServer {
+    /// Return an ApplicationList message containing only the matching query.
+    /// The last valid list is used even if the last reload failed.
+    fn application_list(&self, info: ApplicationListInfo) -> Result<ApplicationList, LcmpError> {
+        let state = self.state.read().unwrap();
+        match &state.app_list {
+            Some(x) => Ok(ApplicationList {
+                appList: x.matching_info(&info),
+            }),
+            None => match &state.last_err {
+                Some(err) => Err(LcmpError::BackendUnavailable(err.clone())),
+                None => Ok(ApplicationList::empty()),
+            },
+        }
+    }
+
+    /// Return the status based on the last (re)load of the apps configuration.
+    fn status(&self) -> Result<(), LcmpError> {
+        match &self.state.read().unwrap().last_err {
+            Some(x) => Err(LcmpError::BackendUnavailable(x.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// ApplicationList store serving the apps currently registered in the KVS.
+struct KvsApplicationListServer {
+    kvs: SharedKvs,
+    /// Last ApplicationList built, together with the value of the apps' operations
+    /// counter in the KVS at the time.
+    cache: Mutex<Option<(u64, ApplicationList)>>,
+}
+
+impl KvsApplicationListServer {
+    fn new(kvs: SharedKvs) -> Self {
+        Self {
+            kvs,
+            cache: Mutex::new(None),
+        }
+    }
+}
+
+impl ApplicationListServer for KvsApplicationListServer {
+    /// Return an ApplicationList message containing only the matching query.
+    /// The list of apps is rebuilt only if changed in the KVS since the last call.
+    fn application_list(&self, info: ApplicationListInfo) -> Result<ApplicationList, LcmpError> {
+        let mut cache = self.cache.lock().unwrap();
+        let kvs = self.kvs.lock().unwrap();
+        let operations = kvs.apps_operations();
+        let up_to_date = matches!(&*cache, Some((x, _)) if *x == operations);
+        if !up_to_date {
+            let app_list = ApplicationList {
+                appList: kvs
+                    .apps()
+                    .iter()
+                    .map(|x| AppList::from_app_info(x.appInfo.clone()))
+                    .collect(),
+            };
+            *cache = Some((operations, app_list));
+        }
+        match &*cache {
+            Some((_, x)) => Ok(ApplicationList {
+                appList: x.matching_info(&info),
+            }),
+            None => Ok(ApplicationList::empty()),
+        }
+    }
+
+    /// Always return good health.
+    fn status(&self) -> Result<(), LcmpError> {
+        Ok(())
+    }
+}
+
 /// Factory to build ApplicationListServer objects from a string
+/// The KVS is used only by the ApplicationListServer of type kvs.
 pub fn build_application_list_server(
     value: &str,
+    kvs: &SharedKvs,
 ) -> Result<Box<dyn ApplicationListServer + Send + Sync>, String> {
     if let Some(x) = value.find("static;") {
         if x == 0 {
             let rhs = &value[7..];
             if let Some(x) = rhs.find("file=") {
                 if x == 0 {
+                    if let Some(filename) = rhs[5..].strip_suffix(";watch") {
+                        return Ok(Box::new(WatchedApplicationListServer::from_file(
+                            filename,
+                            WATCH_PERIOD,
+                        )));
+                    }
                     return Ok(Box::new(StaticApplicationListServer::from_file(
                         &value[12..],
                     )));
@@ -87,6 +352,10 @@ pub fn build_application_list_server(
         }
     } else if value == "empty" {
         return Ok(Box::new(StaticApplicationListServer::empty()));
+    } else if value == "kvs" {
+        return Ok(Box::new(KvsApplicationListServer::new(kvs.clone())));
+    } else if let Some(params) = value.strip_prefix("redis;") {
+        return Ok(Box::new(RedisApplicationListServer::from_params(params)?));
     }
     Err("could not create the ApplicationListServer".to_string())
 }
@@ -94,13 +363,12 @@ pub fn build_application_list_server(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::kvs::{App, Kvs};
+    use std::io::Write;
 
     const APP_LIST_JSON_FILE: &str = "to_remove.json";
 
-    fn write_example_application_list_to_file() -> Result<(), std::io::Error> {
-        let mut f = File::create(APP_LIST_JSON_FILE)?;
-        f.write(
-            r#"
+    const EXAMPLE_APPLICATION_LIST: &str = r#"
         {
             "appList": [
                 {
@@ -116,26 +384,38 @@ mod tests {
                     "vendorSpecificExt": null
                 }
             ]
-        }"#
-            .as_bytes(),
-        )?;
+        }"#;
+
+    fn write_example_application_list_to_file(filename: &str) -> Result<(), std::io::Error> {
+        let mut f = File::create(filename)?;
+        f.write_all(EXAMPLE_APPLICATION_LIST.as_bytes())?;
         Ok(())
     }
 
     #[test]
     fn test_build_application_list_server() {
-        let a = build_application_list_server("non-existing-type");
+        let kvs = Kvs::shared();
+
+        let a = build_application_list_server("non-existing-type", &kvs);
         assert!(a.is_err());
 
-        let a = build_application_list_server("static;aaa");
+        let a = build_application_list_server("static;aaa", &kvs);
         assert!(a.is_err());
 
-        let a = build_application_list_server("static;file");
+        let a = build_application_list_server("static;file", &kvs);
         assert!(a.is_err());
 
-        let a = build_application_list_server("static;file=non-existing");
+        let a = build_application_list_server("static;file=non-existing", &kvs);
+        assert!(a.is_ok());
+        assert!(a.unwrap().status().is_err());
+
+        let a = build_application_list_server("static;file=non-existing;watch", &kvs);
         assert!(a.is_ok());
         assert!(a.unwrap().status().is_err());
+
+        let a = build_application_list_server("kvs", &kvs);
+        assert!(a.is_ok());
+        assert!(a.unwrap().status().is_ok());
     }
 
     #[test]
@@ -144,13 +424,152 @@ mod tests {
         let a = s.application_list(ApplicationListInfo::empty())?;
         assert!(a.appList.is_empty());
 
-        write_example_application_list_to_file().expect("could not write file");
+        write_example_application_list_to_file(APP_LIST_JSON_FILE).expect("could not write file");
         let s = StaticApplicationListServer::from_file(APP_LIST_JSON_FILE);
         let a = s.application_list(ApplicationListInfo::empty())?;
         assert_eq!(1, a.appList.len());
         println!("{}", a.appList[0]);
+
+        let req = ApplicationLocationAvailability::request_from_name_provider_version(
+            "test_appName",
+            "test_appProvider",
+            "test_appDVersion",
+        );
+        let res = s.app_location_availability(&req)?;
+        assert!(res.appInfo.availableLocations.is_empty());
+        let req = ApplicationLocationAvailability::request_from_name_provider_version(
+            "test_appName",
+            "test_appProvider",
+            "another_appDVersion",
+        );
+        assert!(matches!(
+            s.app_location_availability(&req),
+            Err(LcmpError::NotFound(_))
+        ));
         std::fs::remove_file(APP_LIST_JSON_FILE).expect("could not remove file");
 
         Ok(())
     }
+
+    #[test]
+    fn test_kvs_application_list_server() -> Result<(), String> {
+        let kvs = Kvs::shared();
+        let s = KvsApplicationListServer::new(kvs.clone());
+        assert!(s
+            .application_list(ApplicationListInfo::empty())?
+            .appList
+            .is_empty());
+
+        // apps added to the KVS are visible without re-creating the server
+        kvs.lock().unwrap().add_app(App::example("app1"))?;
+        kvs.lock().unwrap().add_app(App::example("app2"))?;
+        let a = s.application_list(ApplicationListInfo::empty())?;
+        assert_eq!(2, a.appList.len());
+
+        // apps removed from the KVS are not visible anymore
+        let key = App::example("app1").key();
+        kvs.lock().unwrap().del_app(&key)?;
+        let a = s.application_list(ApplicationListInfo::empty())?;
+        assert_eq!(1, a.appList.len());
+        println!("{}", a.appList[0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_watched_application_list_server() -> Result<(), String> {
+        let filename = "to_remove_watched.json";
+        let period = Duration::from_millis(10);
+        let timeout = Duration::from_secs(5);
+        // the file is replaced atomically, so that it is never reloaded partially written
+        let replace = |content: &str| {
+            let tmp = format!("{}.tmp", filename);
+            std::fs::write(&tmp, content).expect("could not write file");
+            std::fs::rename(&tmp, filename).expect("could not rename file");
+        };
+
+        write_example_application_list_to_file(filename).expect("could not write file");
+        let s = WatchedApplicationListServer::from_file(filename, period);
+        assert!(s.status().is_ok());
+        assert_eq!(
+            1,
+            s.application_list(ApplicationListInfo::empty())?
+                .appList
+                .len()
+        );
+
+        // invalid content: the previous list is kept, but the status is not good
+        let reloads = s.reloads();
+        replace("{ invalid json");
+        assert!(s.wait_reload(reloads, timeout));
+        assert!(s.status().is_err());
+        assert_eq!(
+            1,
+            s.application_list(ApplicationListInfo::empty())?
+                .appList
+                .len()
+        );
+
+        // valid JSON that does not pass validation: same as above
+        let mut long = "".to_string();
+        (0..33).for_each(|_| long.push('a'));
+        let content = EXAMPLE_APPLICATION_LIST.replace("test_appName", &long);
+        let reloads = s.reloads();
+        replace(&content);
+        assert!(s.wait_reload(reloads, timeout));
+        assert!(s.status().is_err());
+        assert_eq!(
+            1,
+            s.application_list(ApplicationListInfo::empty())?
+                .appList
+                .len()
+        );
+
+        // valid content again: the new list is served
+        let reloads = s.reloads();
+        replace(r#"{ "appList": [] }"#);
+        assert!(s.wait_reload(reloads, timeout));
+        assert!(s.status().is_ok());
+        assert!(s
+            .application_list(ApplicationListInfo::empty())?
+            .appList
+            .is_empty());
+
+        // same modification time and size, different content: reloaded
+        let modified = std::fs::metadata(filename)
+            .and_then(|x| x.modified())
+            .expect("could not read metadata");
+        let reloads = s.reloads();
+        std::fs::write(filename, r#"{ "appList": {} }"#).expect("could not write file");
+        File::options()
+            .write(true)
+            .open(filename)
+            .and_then(|x| x.set_modified(modified))
+            .expect("could not set modification time");
+        assert!(s.wait_reload(reloads, timeout));
+        assert!(s.status().is_err());
+
+        std::fs::remove_file(filename).expect("could not remove file");
+        Ok(())
+    }
+
+    #[test]
+    fn test_watched_application_list_server_sighup() -> Result<(), String> {
+        let filename = "to_remove_watched_sighup.json";
+        write_example_application_list_to_file(filename).expect("could not write file");
+        let s = WatchedApplicationListServer::from_file(filename, Duration::from_millis(10));
+        assert!(s.sighup_id.is_some());
+
+        // the file is reloaded upon SIGHUP, even if not changed
+        let reloads = s.reloads();
+        signal_hook::low_level::raise(signal_hook::consts::SIGHUP).expect("could not raise");
+        assert!(s.wait_reload(reloads, Duration::from_secs(5)));
+        assert!(s.status().is_ok());
+
+        // the handler is unregistered when the server is dropped
+        drop(s);
+
+        std::fs::remove_file(filename).expect("could not remove file");
+        Ok(())
+    }
 }
@@ -0,0 +1,705 @@
+//! Authentication and authorization (AAA) of the requests of the device apps.
+
+use crate::error::LcmpError;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::{hmac, signature};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Scope required to retrieve the application contexts.
+pub const SCOPE_READ: &str = "app_contexts:read";
+/// Scope required to create, update, or delete the application contexts.
+pub const SCOPE_WRITE: &str = "app_contexts:write";
+/// Scope required to access the application contexts owned by other callers.
+pub const SCOPE_ADMIN: &str = "app_contexts:admin";
+
+/// Operation on the application contexts subject to authorization.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+    NewContext,
+    GetContext,
+    UpdateContext,
+    DelContext,
+    ListContexts,
+}
+
+impl Operation {
+    /// Return the scope required to perform the operation.
+    pub fn scope(&self) -> &'static str {
+        match self {
+            Operation::GetContext | Operation::ListContexts => SCOPE_READ,
+            Operation::NewContext | Operation::UpdateContext | Operation::DelContext => SCOPE_WRITE,
+        }
+    }
+}
+
+/// Authenticated caller.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Identity {
+    /// Identifier of the caller, e.g., the sub claim of a JWT.
+    pub subject: String,
+    /// Scopes granted to the caller.
+    pub scopes: Vec<String>,
+}
+
+impl Identity {
+    /// Return the identity of the callers when the AAA is disabled, with all the scopes.
+    pub fn anonymous() -> Self {
+        Self {
+            subject: "anonymous".to_string(),
+            scopes: vec![
+                SCOPE_READ.to_string(),
+                SCOPE_WRITE.to_string(),
+                SCOPE_ADMIN.to_string(),
+            ],
+        }
+    }
+
+    /// Return true if the caller can access the contexts owned by other callers.
+    pub fn is_admin(&self) -> bool {
+        self.scopes.iter().any(|x| x == SCOPE_ADMIN)
+    }
+
+    /// Return the identity if it has the scope required by the operation.
+    fn authorize(self, operation: Operation) -> Result<Identity, LcmpError> {
+        match self.scopes.iter().any(|x| x == operation.scope()) {
+            true => Ok(self),
+            false => Err(LcmpError::Forbidden(format!(
+                "scope {} required by the operation",
+                operation.scope()
+            ))),
+        }
+    }
+}
+
+/// Interface of an Authorizer, consulted before the operations on the
+/// application contexts.
+pub trait Authorizer {
+    /// Authenticate the caller from the value of the Authorization header of
+    /// the request, if present, and check that it is authorized to perform
+    /// the operation. Return the identity of the caller.
+    fn authorize(
+        &self,
+        authorization: Option<&str>,
+        operation: Operation,
+    ) -> Result<Identity, LcmpError>;
+
+    /// Authenticate the caller from the subject of the client certificate
+    /// verified during the TLS handshake, used when the request has no
+    /// Authorization header, and check that it is authorized to perform the
+    /// operation. Return the identity of the caller.
+    /// By default, client certificates are not accepted as credentials.
+    fn authorize_client(
+        &self,
+        subject: &str,
+        _operation: Operation,
+    ) -> Result<Identity, LcmpError> {
+        Err(LcmpError::Unauthorized(format!(
+            "client certificates not accepted as credentials, subject {}",
+            subject
+        )))
+    }
+}
+
+/// Return the token in an Authorization header with the Bearer scheme.
+fn bearer_token(authorization: Option<&str>) -> Result<&str, LcmpError> {
+    let value = authorization
+        .ok_or_else(|| LcmpError::Unauthorized("missing Authorization header".to_string()))?;
+    match value.split_once(' ') {
+        Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") && !token.is_empty() => {
+            Ok(token.trim())
+        }
+        _ => Err(LcmpError::Unauthorized(
+            "invalid Authorization header, expected a Bearer token".to_string(),
+        )),
+    }
+}
+
+/// Authorizer accepting all the requests, used when the AAA is disabled.
+struct NoAuthorizer {}
+
+impl Authorizer for NoAuthorizer {
+    fn authorize(
+        &self,
+        _authorization: Option<&str>,
+        _operation: Operation,
+    ) -> Result<Identity, LcmpError> {
+        Ok(Identity::anonymous())
+    }
+
+    fn authorize_client(
+        &self,
+        _subject: &str,
+        _operation: Operation,
+    ) -> Result<Identity, LcmpError> {
+        Ok(Identity::anonymous())
+    }
+}
+
+/// Authorizer of the device apps authenticated by the client certificate
+/// verified during the TLS handshake, identified by its subject.
+/// All the device apps are granted the same scopes.
+struct ClientCertAuthorizer {
+    scopes: Vec<String>,
+}
+
+impl ClientCertAuthorizer {
+    /// Create an authorizer from a string of semicolon-separated key=value
+    /// parameters: scopes, comma-separated, by default read and write.
+    fn from_params(params: &str) -> Result<Self, String> {
+        let mut scopes = vec![SCOPE_READ.to_string(), SCOPE_WRITE.to_string()];
+        for param in params.split(';').filter(|x| !x.is_empty()) {
+            let (key, value) = param.split_once('=').ok_or(format!(
+                "invalid client-cert authorizer parameter: {}",
+                param
+            ))?;
+            match key {
+                "scopes" => {
+                    scopes = value
+                        .split(',')
+                        .filter(|x| !x.is_empty())
+                        .map(|x| x.to_string())
+                        .collect()
+                }
+                _ => return Err(format!("unknown client-cert authorizer parameter: {}", key)),
+            }
+        }
+        Ok(Self { scopes })
+    }
+}
+
+impl Authorizer for ClientCertAuthorizer {
+    fn authorize(
+        &self,
+        _authorization: Option<&str>,
+        _operation: Operation,
+    ) -> Result<Identity, LcmpError> {
+        Err(LcmpError::Unauthorized(
+            "missing client certificate".to_string(),
+        ))
+    }
+
+    fn authorize_client(&self, subject: &str, operation: Operation) -> Result<Identity, LcmpError> {
+        Identity {
+            subject: subject.to_string(),
+            scopes: self.scopes.clone(),
+        }
+        .authorize(operation)
+    }
+}
+
+/// Entry of the JSON file of a StaticApiKeysAuthorizer.
+#[derive(Deserialize)]
+struct ApiKey {
+    /// Secret sent by the device app as a Bearer token.
+    key: String,
+    /// Identifier of the device app.
+    subject: String,
+    /// Scopes granted to the device app.
+    scopes: Vec<String>,
+}
+
+/// Authorizer with a static set of API keys loaded from a JSON file, which
+/// the device apps send as Bearer tokens.
+struct StaticApiKeysAuthorizer {
+    /// Identities indexed by the API key.
+    keys: HashMap<String, Identity>,
+}
+
+impl StaticApiKeysAuthorizer {
+    fn from_file(filename: &str) -> Result<Self, String> {
+        let file = File::open(filename)
+            .map_err(|x| format!("could not open the API keys file {}: {}", filename, x))?;
+        let keys: Vec<ApiKey> = serde_json::from_reader(file)
+            .map_err(|x| format!("invalid API keys file {}: {}", filename, x))?;
+        let mut map = HashMap::new();
+        for x in keys {
+            if x.key.is_empty() {
+                return Err(format!("empty API key for {}", x.subject));
+            }
+            let identity = Identity {
+                subject: x.subject,
+                scopes: x.scopes,
+            };
+            if let Some(identity) = map.insert(x.key, identity) {
+                return Err(format!("duplicate API key for {}", identity.subject));
+            }
+        }
+        Ok(Self { keys: map })
+    }
+}
+
+impl Authorizer for StaticApiKeysAuthorizer {
+    fn authorize(
+        &self,
+        authorization: Option<&str>,
+        operation: Operation,
+    ) -> Result<Identity, LcmpError> {
+        let token = bearer_token(authorization)?;
+        match self.keys.get(token) {
+            Some(x) => x.clone().authorize(operation),
+            None => Err(LcmpError::Unauthorized("unknown API key".to_string())),
+        }
+    }
+}
+
+/// JSON Web Key, as defined in IETF RFC 7517, with the parameters of the key
+/// types supported: RSA (n, e), EC on curve P-256 (crv, x, y), and oct (k).
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    alg: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+    crv: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+    k: Option<String>,
+}
+
+impl Jwk {
+    /// Return true if the signature of the message is verified with this key
+    /// using the given JWS algorithm.
+    fn verify(&self, alg: &str, message: &[u8], sig: &[u8]) -> bool {
+        if self.alg.as_deref().is_some_and(|x| x != alg) {
+            return false;
+        }
+        let param = |x: &Option<String>| x.as_deref().and_then(|x| decode(x).ok());
+        match (alg, self.kty.as_str()) {
+            ("RS256", "RSA") => match (param(&self.n), param(&self.e)) {
+                (Some(n), Some(e)) => signature::RsaPublicKeyComponents { n, e }
+                    .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, sig)
+                    .is_ok(),
+                _ => false,
+            },
+            ("ES256", "EC") if self.crv.as_deref() == Some("P-256") => {
+                match (param(&self.x), param(&self.y)) {
+                    (Some(x), Some(y)) => {
+                        let public_key = [&[4u8][..], &x, &y].concat();
+                        signature::UnparsedPublicKey::new(
+                            &signature::ECDSA_P256_SHA256_FIXED,
+                            public_key,
+                        )
+                        .verify(message, sig)
+                        .is_ok()
+                    }
+                    _ => false,
+                }
+            }
+            ("HS256", "oct") => match param(&self.k) {
+                Some(k) => {
+                    hmac::verify(&hmac::Key::new(hmac::HMAC_SHA256, &k), message, sig).is_ok()
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+/// JSON Web Key Set, as defined in IETF RFC 7517.
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+/// JOSE header of a JWT.
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+/// Claims of a JWT used for authentication and authorization.
+#[derive(Deserialize)]
+struct JwtClaims {
+    sub: Option<String>,
+    iss: Option<String>,
+    /// Audience, either a single string or an array of strings.
+    #[serde(default)]
+    aud: serde_json::Value,
+    exp: Option<u64>,
+    nbf: Option<u64>,
+    /// Space-separated list of scopes, as defined in IETF RFC 8693.
+    #[serde(default)]
+    scope: String,
+}
+
+/// Decode a base64url-encoded value without padding.
+fn decode(value: &str) -> Result<Vec<u8>, LcmpError> {
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .map_err(|_| LcmpError::Unauthorized("invalid JWT encoding".to_string()))
+}
+
+/// Authorizer of OAuth2 bearer tokens in the form of JWTs, as defined in
+/// IETF RFC 7519, signed with one of the keys in a local JWKS file.
+/// The algorithms supported are RS256, ES256, and HS256.
+/// The tokens must have the exp and sub claims, the latter identifying the
+/// caller, while the scopes are taken from the scope claim.
+struct JwtAuthorizer {
+    keys: Vec<Jwk>,
+    /// Expected value of the iss claim, if any.
+    issuer: Option<String>,
+    /// Value expected among those in the aud claim, if any.
+    audience: Option<String>,
+}
+
+impl JwtAuthorizer {
+    /// Create an authorizer from a string of semicolon-separated key=value
+    /// parameters: jwks (mandatory), issuer, audience.
+    fn from_params(params: &str) -> Result<Self, String> {
+        let mut jwks = None;
+        let mut issuer = None;
+        let mut audience = None;
+        for param in params.split(';').filter(|x| !x.is_empty()) {
+            let (key, value) = param
+                .split_once('=')
+                .ok_or(format!("invalid JWT authorizer parameter: {}", param))?;
+            match key {
+                "jwks" => jwks = Some(value),
+                "issuer" => issuer = Some(value.to_string()),
+                "audience" => audience = Some(value.to_string()),
+                _ => return Err(format!("unknown JWT authorizer parameter: {}", key)),
+            }
+        }
+        let filename = jwks.ok_or("missing jwks parameter of the JWT authorizer")?;
+        let file = File::open(filename)
+            .map_err(|x| format!("could not open the JWKS file {}: {}", filename, x))?;
+        let jwks: JwkSet = serde_json::from_reader(file)
+            .map_err(|x| format!("invalid JWKS file {}: {}", filename, x))?;
+        Ok(Self {
+            keys: jwks.keys,
+            issuer,
+            audience,
+        })
+    }
+
+    /// Verify a JWT at the given time, in seconds since the Unix epoch, and
+    /// return the identity of the caller.
+    fn verify(&self, token: &str, now: u64) -> Result<Identity, LcmpError> {
+        let invalid = |x: &str| LcmpError::Unauthorized(x.to_string());
+        let (message, sig) = token
+            .rsplit_once('.')
+            .ok_or_else(|| invalid("malformed JWT"))?;
+        let (header, claims) = message
+            .split_once('.')
+            .ok_or_else(|| invalid("malformed JWT"))?;
+        let header: JwtHeader =
+            serde_json::from_slice(&decode(header)?).map_err(|_| invalid("invalid JWT header"))?;
+        let sig = decode(sig)?;
+        let verified = self
+            .keys
+            .iter()
+            .filter(|x| header.kid.is_none() || x.kid == header.kid)
+            .any(|x| x.verify(&header.alg, message.as_bytes(), &sig));
+        if !verified {
+            return Err(invalid("invalid JWT signature"));
+        }
+
+        let claims: JwtClaims =
+            serde_json::from_slice(&decode(claims)?).map_err(|_| invalid("invalid JWT claims"))?;
+        match claims.exp {
+            Some(x) if x > now => {}
+            Some(_) => return Err(invalid("expired JWT")),
+            None => return Err(invalid("JWT without exp claim")),
+        }
+        if claims.nbf.is_some_and(|x| x > now) {
+            return Err(invalid("JWT not yet valid"));
+        }
+        if self.issuer.is_some() && claims.iss != self.issuer {
+            return Err(invalid("invalid JWT issuer"));
+        }
+        if let Some(audience) = &self.audience {
+            let valid = match &claims.aud {
+                serde_json::Value::String(x) => x == audience,
+                serde_json::Value::Array(x) => x.iter().any(|x| x.as_str() == Some(audience)),
+                _ => false,
+            };
+            if !valid {
+                return Err(invalid("invalid JWT audience"));
+            }
+        }
+        Ok(Identity {
+            subject: claims.sub.ok_or_else(|| invalid("JWT without sub claim"))?,
+            scopes: claims
+                .scope
+                .split_whitespace()
+                .map(|x| x.to_string())
+                .collect(),
+        })
+    }
+}
+
+impl Authorizer for JwtAuthorizer {
+    fn authorize(
+        &self,
+        authorization: Option<&str>,
+        operation: Operation,
+    ) -> Result<Identity, LcmpError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.verify(bearer_token(authorization)?, now)?
+            .authorize(operation)
+    }
+}
+
+/// Factory to build Authorizer objects from a string:
+/// - none: all the requests are accepted
+/// - api-keys;file=FILE: static API keys in a JSON file
+/// - jwt;jwks=FILE[;issuer=ISS][;audience=AUD]: JWTs signed with the keys in a JWKS file
+/// - client-cert[;scopes=S1,S2]: subject of the client certificates verified with mutual TLS
+pub fn build_authorizer(value: &str) -> Result<Box<dyn Authorizer + Send + Sync>, String> {
+    if value == "none" {
+        return Ok(Box::new(NoAuthorizer {}));
+    } else if let Some(filename) = value.strip_prefix("api-keys;file=") {
+        return Ok(Box::new(StaticApiKeysAuthorizer::from_file(filename)?));
+    } else if let Some(params) = value.strip_prefix("jwt;") {
+        return Ok(Box::new(JwtAuthorizer::from_params(params)?));
+    } else if let Some(params) = value.strip_prefix("client-cert") {
+        if params.is_empty() || params.starts_with(';') {
+            return Ok(Box::new(ClientCertAuthorizer::from_params(params)?));
+        }
+    }
+    Err("could not create the Authorizer".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, RsaKeyPair};
+    use uuid::Uuid;
+
+    /// PKCS#1 DER of an RSA key used only to sign the test tokens.
+    const TEST_RSA_KEY: &str = "MIIEowIBAAKCAQEA20evcOwwcM6QOX28vY8PA8skltrQPrCz9LETU5nolWFL5tjcxnJNU+5rUTNYEseT9FBkXT0B5LD5Uh7uUXvkGDMxHYLhlkKafMrKa0Rkcj2mWckVxNPCr8iY7kjaTRivcMo1KZk7U9+JH/JS+hWVuLHQLoOiSB+2fIcU7GS9dmoGmmsmojbQARhKP6gBBDGc92+j5htled3ytgnfWwUtHlbhKy8ZV2q7rVQ5YpUyN7RhlfpMth4YcTMkNV1u3m79xVhzyHUgO39+1JU0eJb6/3MnirP9ijZ62OB1DSVsjoV4CbnejSNBDcMY/ufRvzMBUY32hYz47PaS3vJcJf1dyQIDAQABAoIBAA6GbJd70GegieAtColkm7eCHNMwp7Zcs8buddvF/aiWJdYi9ljfgxd0sGopZFj2HGsEv5Pc3IO+uf0tFwHnGtWu377XMm88ITSepZLpGF2S5N0yBehK/gHxNTl6MkUExhZKvAPHO9f983zeFeNX9loiI9VOReZ7fKCPaBzn6BRFqUBXs/xMa8ecGPhFlGWg7ZXZ9Zqzw1icH5CY42/ZgWFtuVN61X9ahudJRj+G00lGIw608uVpVVqtboiZqWEa2AgPECtdSNhs8yQ6btydz+8RzjfU6SL+unWcwvwSDtod4imVfhFGHHaOuPRgTLLdeCs5sgieQ8jAEffViEMKwRMCgYEA+RkwT35fcLUDElvYq6HSPQwn77xy+RjrqhDC2RhOXwP2NTB2Bi5XyLoHyVebWgb29J5V7Jemf2dEItIWYzFh0qFsggGUKEQ32MhqWT+Vese0DJ7iQCOdZ2t3amAGtNsSZ5+Cvjwi4SV5UV2ePUWBrEt+LrajWoQccEq08StdHp8CgYEA4VsABxKtNrRC3dIHBWN5TJb9PJdDFv9GEf8hnZgJp5boLi8FKuUPZUkC48svdaUm4mV0THr1WyOUI61aNlDCcfCfKVTIpAxxUSi5a+7UoZycZ2C/9lAMvTFTgIJEBVhB9HH6/z9yf2rEzQ8u68VuH6TgcQjaXG5EK6V/oEYi8pcCgYEAtsTqk4kv0eyeolvNsLD9J1nB+78TxP2p/JBrxwp0hnbLsd8q2gIq2tbUW8Dx9a5iiL2cYgorWhOVBM+NKZ7UHc3fZcxuvtS+/Uqf7Xgf34Sj8Raq/1Cib32EkwJ+KTkMWgfJ0HcjSHtMdozwTBixuo9xuCi91u5UR3gpJ0MgAhUCgYBZJUtkgOHeYv6UixHV8ty4DH6OqnOStFa+pcNheRRQpGSiaF1N86Dcez5WaDkU+8jfBC02V+mZLQDcACZQmC/jLMF5cx2Pr5Bf/5Eh/i9E4xQH3QDy6gWwDcQvcNiBmME2dqRItouPlefWCmyQPeBrSiiy7kKbD8+NyIEtILGQbwKBgHmOlknb6VfL8QB3y32IE89NDdE1HlgGdsm7XHdeSA+YTS2G0KeSu4nVDjDibsY03QkYxrtyGcR4KoH1O2ojsfMUvOUmMkdAfhp8rhToD2eo1StzcohLeFKfDVD3tvoKzl3Bw1K5skhlxuojEgNy0YjMJj78mvtGhjUJgUapmiou";
+
+    fn temp_file(content: &str) -> String {
+        let path = std::env::temp_dir().join(format!("lcmp-auth-{}", Uuid::new_v4().simple()));
+        std::fs::write(&path, content).expect("could not write file");
+        path.to_string_lossy().to_string()
+    }
+
+    fn encode(value: &[u8]) -> String {
+        URL_SAFE_NO_PAD.encode(value)
+    }
+
+    /// Return a signed JWT with the given header and claims.
+    fn jwt(header: &str, claims: &str, sign: &dyn Fn(&[u8]) -> Vec<u8>) -> String {
+        let message = format!(
+            "{}.{}",
+            encode(header.as_bytes()),
+            encode(claims.as_bytes())
+        );
+        let sig = sign(message.as_bytes());
+        format!("Bearer {}.{}", message, encode(&sig))
+    }
+
+    #[test]
+    fn test_api_keys_authorizer() {
+        let filename = temp_file(
+            r#"[
+                {"key":"secret1","subject":"app1","scopes":["app_contexts:read","app_contexts:write"]},
+                {"key":"secret2","subject":"app2","scopes":["app_contexts:read"]}
+            ]"#,
+        );
+        let authorizer = build_authorizer(&format!("api-keys;file={}", filename)).unwrap();
+
+        let identity = authorizer
+            .authorize(Some("Bearer secret1"), Operation::NewContext)
+            .unwrap();
+        assert_eq!("app1", identity.subject);
+        assert!(authorizer
+            .authorize(Some("bearer secret2"), Operation::GetContext)
+            .is_ok());
+        assert!(matches!(
+            authorizer.authorize(Some("Bearer secret2"), Operation::DelContext),
+            Err(LcmpError::Forbidden(_))
+        ));
+        for authorization in [None, Some("Bearer unknown"), Some("Basic secret1")] {
+            assert!(matches!(
+                authorizer.authorize(authorization, Operation::GetContext),
+                Err(LcmpError::Unauthorized(_))
+            ));
+        }
+
+        std::fs::write(&filename, r#"[{"key":"","subject":"app1","scopes":[]}]"#).unwrap();
+        assert!(build_authorizer(&format!("api-keys;file={}", filename)).is_err());
+        std::fs::remove_file(&filename).expect("could not remove file");
+        assert!(build_authorizer(&format!("api-keys;file={}", filename)).is_err());
+    }
+
+    #[test]
+    fn test_jwt_authorizer() {
+        let rng = SystemRandom::new();
+        let alg = &signature::ECDSA_P256_SHA256_FIXED_SIGNING;
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(alg, &rng).unwrap();
+        let ec = EcdsaKeyPair::from_pkcs8(alg, pkcs8.as_ref(), &rng).unwrap();
+        let ec_public = ec.public_key().as_ref();
+        let rsa = RsaKeyPair::from_der(
+            &base64::engine::general_purpose::STANDARD
+                .decode(TEST_RSA_KEY)
+                .unwrap(),
+        )
+        .unwrap();
+        let rsa_public = signature::RsaPublicKeyComponents::<Vec<u8>>::from(rsa.public());
+        let hs_key = b"0123456789abcdef0123456789abcdef";
+        let filename = temp_file(
+            &serde_json::json!({"keys": [
+                {"kty": "EC", "kid": "ec", "crv": "P-256",
+                 "x": encode(&ec_public[1..33]), "y": encode(&ec_public[33..])},
+                {"kty": "RSA", "kid": "rsa",
+                 "n": encode(&rsa_public.n),
+                 "e": encode(&rsa_public.e)},
+                {"kty": "oct", "kid": "hs", "alg": "HS256", "k": encode(hs_key)}
+            ]})
+            .to_string(),
+        );
+        let authorizer = JwtAuthorizer::from_params(&format!(
+            "jwks={};issuer=https://aaa;audience=lcmp",
+            filename
+        ))
+        .unwrap();
+        std::fs::remove_file(&filename).expect("could not remove file");
+
+        let sign_ec = |x: &[u8]| ec.sign(&rng, x).unwrap().as_ref().to_vec();
+        let sign_rsa = |x: &[u8]| {
+            let mut sig = vec![0; rsa.public().modulus_len()];
+            rsa.sign(&signature::RSA_PKCS1_SHA256, &rng, x, &mut sig)
+                .unwrap();
+            sig
+        };
+        let sign_hs = |x: &[u8]| {
+            hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, hs_key), x)
+                .as_ref()
+                .to_vec()
+        };
+        let claims = |exp: u64, aud: &str, scope: &str| {
+            format!(
+                r#"{{"sub":"app1","iss":"https://aaa","aud":{},"exp":{},"scope":"{}"}}"#,
+                aud, exp, scope
+            )
+        };
+        let valid = claims(2000, r#"["other","lcmp"]"#, "app_contexts:write");
+        let verify = |token: &str| authorizer.verify(token.strip_prefix("Bearer ").unwrap(), 1000);
+
+        // valid tokens signed with all the keys
+        for (header, sign) in [
+            (
+                r#"{"alg":"ES256","kid":"ec"}"#,
+                &sign_ec as &dyn Fn(&[u8]) -> Vec<u8>,
+            ),
+            (r#"{"alg":"RS256","kid":"rsa"}"#, &sign_rsa),
+            (r#"{"alg":"HS256"}"#, &sign_hs),
+        ] {
+            let identity = verify(&jwt(header, &valid, sign)).unwrap();
+            assert_eq!("app1", identity.subject);
+            assert_eq!(vec![SCOPE_WRITE.to_string()], identity.scopes);
+        }
+
+        // invalid tokens
+        let es256 = r#"{"alg":"ES256","kid":"ec"}"#;
+        for token in [
+            jwt(r#"{"alg":"ES256","kid":"rsa"}"#, &valid, &sign_ec),
+            jwt(r#"{"alg":"HS256","kid":"ec"}"#, &valid, &sign_hs),
+            jwt(r#"{"alg":"none"}"#, &valid, &|_| vec![]),
+            jwt(es256, &claims(1000, r#""lcmp""#, ""), &sign_ec),
+            jwt(es256, &claims(2000, r#""other""#, ""), &sign_ec),
+            jwt(es256, r#"{"sub":"app1","aud":"lcmp","exp":2000}"#, &sign_ec),
+            jwt(
+                es256,
+                r#"{"iss":"https://aaa","aud":"lcmp","exp":2000}"#,
+                &sign_ec,
+            ),
+            jwt(
+                es256,
+                r#"{"sub":"app1","iss":"https://aaa","aud":"lcmp","exp":2000,"nbf":1500}"#,
+                &sign_ec,
+            ),
+            "Bearer a.b".to_string(),
+            "Bearer not-a-jwt".to_string(),
+        ] {
+            assert!(matches!(verify(&token), Err(LcmpError::Unauthorized(_))));
+        }
+        let mut tampered = jwt(es256, &valid, &sign_ec);
+        tampered.insert(tampered.find('.').unwrap() + 1, 'e');
+        assert!(matches!(verify(&tampered), Err(LcmpError::Unauthorized(_))));
+
+        // scopes
+        let token = jwt(
+            es256,
+            &claims(u64::MAX, r#""lcmp""#, "app_contexts:read"),
+            &sign_ec,
+        );
+        assert!(authorizer
+            .authorize(Some(&token), Operation::GetContext)
+            .is_ok());
+        assert!(matches!(
+            authorizer.authorize(Some(&token), Operation::NewContext),
+            Err(LcmpError::Forbidden(_))
+        ));
+        assert!(matches!(
+            authorizer.authorize(None, Operation::GetContext),
+            Err(LcmpError::Unauthorized(_))
+        ));
+    }
+
+    #[test]
+    fn test_client_cert_authorizer() {
+        let authorizer = build_authorizer("client-cert").unwrap();
+        let identity = authorizer
+            .authorize_client("app1", Operation::NewContext)
+            .unwrap();
+        assert_eq!("app1", identity.subject);
+        assert!(!identity.is_admin());
+        assert!(matches!(
+            authorizer.authorize(Some("Bearer secret1"), Operation::GetContext),
+            Err(LcmpError::Unauthorized(_))
+        ));
+
+        let authorizer = build_authorizer("client-cert;scopes=app_contexts:read").unwrap();
+        assert!(authorizer
+            .authorize_client("app1", Operation::ListContexts)
+            .is_ok());
+        assert!(matches!(
+            authorizer.authorize_client("app1", Operation::DelContext),
+            Err(LcmpError::Forbidden(_))
+        ));
+
+        // client certificates are not accepted by the other authorizers
+        let filename = temp_file(r#"[{"key":"secret1","subject":"app1","scopes":[]}]"#);
+        let authorizer = build_authorizer(&format!("api-keys;file={}", filename)).unwrap();
+        std::fs::remove_file(&filename).expect("could not remove file");
+        assert!(matches!(
+            authorizer.authorize_client("app1", Operation::GetContext),
+            Err(LcmpError::Unauthorized(_))
+        ));
+        assert!(build_authorizer("none")
+            .unwrap()
+            .authorize_client("app1", Operation::DelContext)
+            .unwrap()
+            .is_admin());
+    }
+
+    #[test]
+    fn test_build_authorizer() {
+        let authorizer = build_authorizer("none").unwrap();
+        assert!(authorizer
+            .authorize(None, Operation::DelContext)
+            .unwrap()
+            .is_admin());
+        assert!(build_authorizer("unknown").is_err());
+        assert!(build_authorizer("jwt;").is_err());
+        assert!(build_authorizer("jwt;jwks=non-existing").is_err());
+        assert!(build_authorizer("jwt;jwks=file;unknown=1").is_err());
+        assert!(build_authorizer("api-keys;file=non-existing").is_err());
+        assert!(build_authorizer("client-certificate").is_err());
+        assert!(build_authorizer("client-cert;unknown=1").is_err());
+    }
+}
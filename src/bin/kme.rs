@@ -0,0 +1,251 @@
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{
+    middleware::Logger, web, App, HttpRequest, HttpResponse, HttpResponseBuilder, HttpServer,
+};
+use clap::Parser;
+use etsi_mec_qkd::kme::{KmeConf, KmeError, MockKme, SAE_ID_HEADER};
+use etsi_mec_qkd::qkd014::{Error, KeyContainer, KeyIds, KeyRequest};
+use log::info;
+use std::sync::Mutex;
+
+#[derive(Debug, Parser)]
+#[command(long_about = None)]
+struct Args {
+    /// Address and port of the HTTP server
+    #[arg(long, default_value_t = String::from("0.0.0.0:8443"))]
+    address: String,
+
+    /// KME ID of this KME
+    #[arg(long, default_value_t = String::from("kme1"))]
+    kme_id: String,
+
+    /// KME ID of the target KME
+    #[arg(long, default_value_t = String::from("kme2"))]
+    target_kme_id: String,
+
+    /// SAE ID assumed for the callers without the X-SAE-ID header
+    #[arg(long, default_value_t = String::from("sae1"))]
+    default_sae_id: String,
+
+    /// Default size of the keys, in bits
+    #[arg(long, default_value_t = 256)]
+    key_size: u32,
+
+    /// Minimum size of the keys, in bits
+    #[arg(long, default_value_t = 64)]
+    min_key_size: u32,
+
+    /// Maximum size of the keys, in bits
+    #[arg(long, default_value_t = 1024)]
+    max_key_size: u32,
+
+    /// Maximum number of keys stored for every pair of SAEs
+    #[arg(long, default_value_t = 1000)]
+    max_key_count: u32,
+
+    /// Number of keys stored for a new pair of SAEs
+    #[arg(long, default_value_t = 1000)]
+    initial_key_count: u32,
+
+    /// Maximum number of keys per request
+    #[arg(long, default_value_t = 10)]
+    max_key_per_request: u32,
+
+    /// Number of keys generated per second for every pair of SAEs
+    #[arg(long, default_value_t = 10.0)]
+    generation_rate: f64,
+
+    /// Time after which the keys not retrieved by the slave SAE are discarded, in s
+    #[arg(long, default_value_t = 60)]
+    delivered_key_ttl: u64,
+
+    /// Maximum number of keys not yet retrieved by the slave SAEs
+    #[arg(long, default_value_t = 10000)]
+    max_delivered_keys: usize,
+}
+
+/// A mock ETSI GS QKD 014 KME
+struct KmeState {
+    kme: Mutex<MockKme>,
+}
+
+/// Query parameters of GET enc_keys
+#[derive(serde::Deserialize)]
+struct EncKeysQuery {
+    number: Option<u32>,
+    size: Option<u32>,
+}
+
+/// Query parameters of GET dec_keys
+#[derive(serde::Deserialize)]
+struct DecKeysQuery {
+    #[serde(rename = "key_ID")]
+    key_id: String,
+}
+
+/// Return an HTTP response with an ETSI GS QKD 014 Error body
+fn error_response(status_code: StatusCode, message: &str) -> HttpResponse {
+    let e = Error {
+        message: message.to_string(),
+        details: None,
+    };
+    HttpResponseBuilder::new(status_code)
+        .insert_header(ContentType::json())
+        .body(serde_json::to_string(&e).unwrap_or_default())
+}
+
+/// Return an HTTP OK response
+fn ok_response<T: serde::Serialize>(body: &T) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(ContentType::json())
+        .body(serde_json::to_string(&body).unwrap_or_default())
+}
+
+/// Return an HTTP response from the result of a Get key method:
+/// 400 if the request is invalid, 503 if not enough keys are available
+fn keys_response(res: Result<KeyContainer, KmeError>) -> HttpResponse {
+    match res {
+        Ok(x) => ok_response(&x),
+        Err(err) => error_response(
+            StatusCode::from_u16(err.status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            err.to_string().as_str(),
+        ),
+    }
+}
+
+/// Return the SAE ID of the caller
+fn caller(data: &KmeState, req: &HttpRequest) -> String {
+    data.kme.lock().unwrap().caller(
+        req.headers()
+            .get(SAE_ID_HEADER)
+            .and_then(|x| x.to_str().ok()),
+    )
+}
+
+/// Handler for GET /api/v1/keys/{slave_SAE_ID}/status
+async fn status(
+    data: web::Data<KmeState>,
+    req: HttpRequest,
+    info: web::Path<String>,
+) -> HttpResponse {
+    let master = caller(&data, &req);
+    ok_response(&data.kme.lock().unwrap().status(&master, &info))
+}
+
+/// Handler for POST /api/v1/keys/{slave_SAE_ID}/enc_keys
+async fn enc_keys_post(
+    data: web::Data<KmeState>,
+    req: HttpRequest,
+    info: web::Path<String>,
+    body: String,
+) -> HttpResponse {
+    let request = match body.is_empty() {
+        true => Ok(KeyRequest::default()),
+        false => serde_json::from_str::<KeyRequest>(&body),
+    };
+    match request {
+        Ok(request) => {
+            let master = caller(&data, &req);
+            keys_response(data.kme.lock().unwrap().enc_keys(&master, &info, &request))
+        }
+        Err(err) => error_response(StatusCode::BAD_REQUEST, err.to_string().as_str()),
+    }
+}
+
+/// Handler for GET /api/v1/keys/{slave_SAE_ID}/enc_keys
+async fn enc_keys_get(
+    data: web::Data<KmeState>,
+    req: HttpRequest,
+    info: web::Path<String>,
+    query: web::Query<EncKeysQuery>,
+) -> HttpResponse {
+    let request = KeyRequest {
+        number: query.number,
+        size: query.size,
+        ..Default::default()
+    };
+    let master = caller(&data, &req);
+    keys_response(data.kme.lock().unwrap().enc_keys(&master, &info, &request))
+}
+
+/// Handler for POST /api/v1/keys/{master_SAE_ID}/dec_keys
+async fn dec_keys_post(
+    data: web::Data<KmeState>,
+    req: HttpRequest,
+    info: web::Path<String>,
+    body: String,
+) -> HttpResponse {
+    match serde_json::from_str::<KeyIds>(&body) {
+        Ok(key_ids) => {
+            let slave = caller(&data, &req);
+            keys_response(data.kme.lock().unwrap().dec_keys(&slave, &info, &key_ids))
+        }
+        Err(err) => error_response(StatusCode::BAD_REQUEST, err.to_string().as_str()),
+    }
+}
+
+/// Handler for GET /api/v1/keys/{master_SAE_ID}/dec_keys
+async fn dec_keys_get(
+    data: web::Data<KmeState>,
+    req: HttpRequest,
+    info: web::Path<String>,
+    query: web::Query<DecKeysQuery>,
+) -> HttpResponse {
+    let key_ids = KeyIds::from_ids(std::slice::from_ref(&query.key_id));
+    let slave = caller(&data, &req);
+    keys_response(data.kme.lock().unwrap().dec_keys(&slave, &info, &key_ids))
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+
+    let conf = KmeConf {
+        kme_id: args.kme_id,
+        target_kme_id: args.target_kme_id,
+        default_sae_id: args.default_sae_id,
+        key_size: args.key_size,
+        min_key_size: args.min_key_size,
+        max_key_size: args.max_key_size,
+        max_key_count: args.max_key_count,
+        initial_key_count: args.initial_key_count,
+        max_key_per_request: args.max_key_per_request,
+        generation_rate: args.generation_rate,
+        delivered_key_ttl: std::time::Duration::from_secs(args.delivered_key_ttl),
+        max_delivered_keys: args.max_delivered_keys,
+    };
+    let kme = match MockKme::new(conf) {
+        Ok(x) => x,
+        Err(err) => {
+            println!("invalid configuration of the KME: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let state = web::Data::new(KmeState {
+        kme: Mutex::new(kme),
+    });
+
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+
+    info!("starting mock KME at {}", args.address);
+    HttpServer::new(move || {
+        App::new()
+            .wrap(Logger::default())
+            .app_data(state.clone())
+            .service(web::resource("/api/v1/keys/{SAE_ID}/status").route(web::get().to(status)))
+            .service(
+                web::resource("/api/v1/keys/{SAE_ID}/enc_keys")
+                    .route(web::get().to(enc_keys_get))
+                    .route(web::post().to(enc_keys_post)),
+            )
+            .service(
+                web::resource("/api/v1/keys/{SAE_ID}/dec_keys")
+                    .route(web::get().to(dec_keys_get))
+                    .route(web::post().to(dec_keys_post)),
+            )
+    })
+    .bind(args.address)?
+    .run()
+    .await
+}
@@ -1,26 +1,97 @@
-use actix_web::http::header::ContentType;
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::dev::Extensions;
+use actix_web::error::{InternalError, QueryPayloadError};
+use actix_web::http::header::{self, ContentType};
 use actix_web::http::StatusCode;
+use actix_web::rt::net::TcpStream;
 use actix_web::{
-    guard, middleware::Logger, web, App, HttpResponse, HttpResponseBuilder, HttpServer,
+    guard, middleware::Logger, web, App, HttpRequest, HttpResponse, HttpResponseBuilder, HttpServer,
 };
 use clap::Parser;
-use etsi_mec_qkd::applicationlistserver::{build_application_list_server, ApplicationListServer};
-use etsi_mec_qkd::lcmpserver::LcmpServer;
-use etsi_mec_qkd::messages::{AppContext, ApplicationListInfo, ProblemDetails, Validate};
-use log::info;
-use serde::__private::de::Content;
-use serde_json::json;
+use etsi_mec_qkd::auth::{build_authorizer, Authorizer, Identity, Operation};
+use etsi_mec_qkd::error::LcmpError;
+use etsi_mec_qkd::kvs::{App as KvsApp, Platform, TelemetryValue};
+use etsi_mec_qkd::lcmpserver::{update_key_telemetry, KeyAdmissionConf, LcmpServer, KME_TIMEOUT};
+use etsi_mec_qkd::meo::OptimizationConf;
+use etsi_mec_qkd::messages::{
+    AppContext, ApplicationListInfo, ApplicationLocationAvailability, Validate,
+};
+use etsi_mec_qkd::tls;
+use log::{info, warn};
+use std::any::Any;
 use std::sync::Mutex;
 
-/// Return an HTTP response with a Problem Details body
-fn problem_details_response(status_code: StatusCode, error: &str) -> HttpResponse {
-    let p = ProblemDetails {
-        status: status_code.as_u16().into(),
-        detail: error.to_string(),
-    };
-    HttpResponseBuilder::new(status_code)
-        .insert_header(ContentType::json())
-        .body(serde_json::to_string(&p).unwrap_or_default())
+/// Media type of the Problem Details bodies, as specified in IETF RFC 7807
+const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Return an HTTP response with a Problem Details body and the status code of the error,
+/// where the instance is the path of the request
+fn error_response(err: &LcmpError, req: &HttpRequest) -> HttpResponse {
+    let status_code =
+        StatusCode::from_u16(err.status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    let mut builder = HttpResponseBuilder::new(status_code);
+    if let LcmpError::Unauthorized(_) = err {
+        builder.insert_header((header::WWW_AUTHENTICATE, "Bearer"));
+    }
+    if let Some(retry_after) = err.retry_after() {
+        builder.insert_header((header::RETRY_AFTER, retry_after.to_string()));
+    }
+    builder
+        .content_type(PROBLEM_JSON)
+        .body(serde_json::to_string(&err.problem_details(Some(req.path()))).unwrap_or_default())
+}
+
+/// Handler for the requests not matching any route
+async fn not_found(req: HttpRequest) -> HttpResponse {
+    error_response(
+        &LcmpError::NotFound(format!("no resource at {} {}", req.method(), req.path())),
+        &req,
+    )
+}
+
+/// Return a Problem Details error for the query strings that cannot be parsed
+fn query_error_handler(err: QueryPayloadError, req: &HttpRequest) -> actix_web::Error {
+    let response = error_response(&LcmpError::InvalidRequest(err.to_string()), req);
+    InternalError::from_response(err, response).into()
+}
+
+/// Subject of the client certificate verified during the TLS handshake
+#[derive(Clone)]
+struct ClientSubject(String);
+
+/// Store the subject of the client certificate of a TLS connection, if any,
+/// in the data of the connection
+fn on_connect(conn: &dyn Any, data: &mut Extensions) {
+    if let Some(stream) = conn.downcast_ref::<TlsStream<TcpStream>>() {
+        let (_, session) = stream.get_ref();
+        if let Some(subject) = session
+            .peer_certificates()
+            .and_then(|x| x.first())
+            .and_then(|x| tls::client_subject(x))
+        {
+            data.insert(ClientSubject(subject));
+        }
+    }
+}
+
+/// Authenticate the caller of an operation from the Authorization header of
+/// the request or, if missing, from its client certificate, and check that
+/// it is authorized to perform it
+fn authorize(
+    req: &HttpRequest,
+    data: &AppState,
+    operation: Operation,
+) -> Result<Identity, LcmpError> {
+    let authorization = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|x| x.to_str().ok());
+    match (authorization, req.conn_data::<ClientSubject>()) {
+        (None, Some(ClientSubject(subject))) => {
+            data.authorizer.authorize_client(subject, operation)
+        }
+        _ => data.authorizer.authorize(authorization, operation),
+    }
 }
 
 /// Return an HTTP OK response
@@ -49,20 +120,82 @@ struct Args {
     /// Application context manager type
     #[arg(long, default_value_t = String::from("single;10,URI"))]
     app_context_type: String,
+
+    /// Address and port of the KVS admin HTTP server, disabled if empty
+    #[arg(long, default_value_t = String::from(""))]
+    kvs_address: String,
+
+    /// Period of the optimization of the active contexts, in s, disabled if zero
+    #[arg(long, default_value_t = 0)]
+    optimization_period: u64,
+
+    /// Contexts are migrated away from platforms with a higher load
+    #[arg(long, default_value_t = 0.9)]
+    optimization_max_load: f64,
+
+    /// Contexts are migrated away from platforms with a lower key availability
+    #[arg(long, default_value_t = 0.1)]
+    optimization_min_key: f64,
+
+    /// Hysteresis on the load and key availability to select the target platforms
+    #[arg(long, default_value_t = 0.1)]
+    optimization_hysteresis: f64,
+
+    /// Maximum number of contexts migrated in an optimization round
+    #[arg(long, default_value_t = 10)]
+    optimization_max_migrations: usize,
+
+    /// Period of the retrieval of the key availability from the platforms' KMEs, in s, disabled if zero
+    #[arg(long, default_value_t = 0)]
+    key_telemetry_period: u64,
+
+    /// Time-to-live of the contexts not retrieved or updated by the device apps, in s, disabled if zero
+    #[arg(long, default_value_t = 0)]
+    context_ttl: u64,
+
+    /// Period of the deletion of the expired contexts, in s
+    #[arg(long, default_value_t = 10)]
+    context_reaper_period: u64,
+
+    /// New contexts are rejected if the key availability of the platform in the KVS is lower, disabled if zero
+    #[arg(long, default_value_t = 0.0)]
+    admission_min_key: f64,
+
+    /// Time after which the device apps can retry the contexts rejected due to the key availability, in s
+    #[arg(long, default_value_t = 10)]
+    admission_retry_after: u64,
+
+    /// Authorizer of the operations on the contexts: none, api-keys;file=FILE, jwt;jwks=FILE[;issuer=ISS][;audience=AUD], client-cert[;scopes=S1,S2]
+    #[arg(long, default_value_t = String::from("none"))]
+    authorizer: String,
+
+    /// PEM file with the certificate chain of the HTTP server, TLS disabled if empty
+    #[arg(long, default_value_t = String::from(""))]
+    tls_cert: String,
+
+    /// PEM file with the private key of the HTTP server
+    #[arg(long, default_value_t = String::from(""))]
+    tls_key: String,
+
+    /// PEM file with the CA certificates to verify the optional client certificates, mutual TLS disabled if empty
+    #[arg(long, default_value_t = String::from(""))]
+    client_ca: String,
 }
 
 /// An ETSI MEC Life Cycle Management Proxy
 struct AppState {
     lcmp_server: Mutex<LcmpServer>,
+    authorizer: Box<dyn Authorizer + Send + Sync>,
 }
 
 /// Handler for GET /app_list
 async fn app_list(
+    req: HttpRequest,
     info: web::Query<ApplicationListInfo>,
     data: web::Data<AppState>,
 ) -> HttpResponse {
     match info.validate() {
-        Err(err) => problem_details_response(StatusCode::BAD_REQUEST, err.as_str()),
+        Err(err) => error_response(&LcmpError::InvalidRequest(err), &req),
         Ok(_) => match data
             .lcmp_server
             .lock()
@@ -71,28 +204,69 @@ async fn app_list(
             .application_list(info.0)
         {
             Ok(x) => ok_response(&x),
-            Err(err) => HttpResponse::InternalServerError().body(format!("{}", err)),
+            Err(err) => error_response(&err, &req),
         },
     }
 }
 
+/// Handler for POST /obtain_app_loc_availability
+async fn obtain_app_loc_availability(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: String,
+) -> HttpResponse {
+    let x: Result<ApplicationLocationAvailability, serde_json::Error> = serde_json::from_str(&body);
+    match x {
+        Ok(request) => match data
+            .lcmp_server
+            .lock()
+            .unwrap()
+            .application_list()
+            .app_location_availability(&request)
+        {
+            Ok(x) => ok_response(&x),
+            Err(err) => error_response(&err, &req),
+        },
+        Err(err) => error_response(&LcmpError::InvalidRequest(err.to_string()), &req),
+    }
+}
+
 /// Handler for POST /app_contexts
-async fn app_contexts(data: web::Data<AppState>, body: String) -> HttpResponse {
-    let mut x: Result<AppContext, serde_json::Error> = serde_json::from_str(&body);
-    match &mut x {
-        Ok(app_context) => {
-            match data
+async fn app_contexts(req: HttpRequest, data: web::Data<AppState>, body: String) -> HttpResponse {
+    let identity = match authorize(&req, &data, Operation::NewContext) {
+        Ok(x) => x,
+        Err(err) => return error_response(&err, &req),
+    };
+    let mut app_context: AppContext = match serde_json::from_str(&body) {
+        Ok(x) => x,
+        Err(err) => return error_response(&LcmpError::InvalidRequest(err.to_string()), &req),
+    };
+    let pending = data
+        .lcmp_server
+        .lock()
+        .unwrap()
+        .start_context(&identity, &mut app_context);
+    let res = match pending {
+        // wait for the deployment of the app and obtain the QKD key pairs, if
+        // needed, without holding the lock
+        Ok(mut pending) => match web::block(move || {
+            pending.run();
+            pending
+        })
+        .await
+        {
+            Ok(pending) => data
                 .lcmp_server
                 .lock()
                 .unwrap()
-                .app_context()
-                .new_context(app_context)
-            {
-                Ok(_) => ok_response(&app_context),
-                Err(err) => problem_details_response(StatusCode::FORBIDDEN, err.as_str()),
-            }
-        }
-        Err(err) => problem_details_response(StatusCode::BAD_REQUEST, err.to_string().as_str()),
+                .finish_context(pending, &mut app_context),
+            Err(err) => Err(LcmpError::Internal(err.to_string())),
+        },
+        Err(err) => Err(err),
+    };
+    match res {
+        Ok(_) => ok_response(&app_context),
+        Err(err) => error_response(&err, &req),
     }
 }
 
@@ -101,51 +275,93 @@ pub struct Contexts {
     contexts: Vec<String>,
 }
 
+/// Query parameters of GET /app_contexts, at most one can be specified
+/// This is *not* ETSI MEC standard
+#[derive(serde::Deserialize)]
+#[allow(non_snake_case)]
+struct ContextsQuery {
+    associateDevAppId: Option<String>,
+    appDId: Option<String>,
+    referenceURI: Option<String>,
+}
+
 /// Handler for GET /app_contexts
-async fn list_contexts(data: web::Data<AppState>) -> HttpResponse {
-    match data
-        .lcmp_server
-        .lock()
-        .unwrap()
-        .app_context()
-        .list_contexts()
-    {
+async fn list_contexts(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<ContextsQuery>,
+) -> HttpResponse {
+    let identity = match authorize(&req, &data, Operation::ListContexts) {
+        Ok(x) => x,
+        Err(err) => return error_response(&err, &req),
+    };
+    let mut lcmp_server = data.lcmp_server.lock().unwrap();
+    let app_context = lcmp_server.app_context();
+    let res =
+        match (&query.associateDevAppId, &query.appDId, &query.referenceURI) {
+            (None, None, None) => app_context.list_contexts(),
+            (Some(x), None, None) => app_context.contexts_by_associate_dev_app_id(x),
+            (None, Some(x), None) => app_context.contexts_by_appdid(x),
+            (None, None, Some(x)) => app_context.contexts_by_reference_uri(x),
+            _ => return error_response(
+                &LcmpError::InvalidRequest(
+                    "at most one of associateDevAppId, appDId, and referenceURI can be specified"
+                        .to_string(),
+                ),
+                &req,
+            ),
+        };
+    match res.and_then(|x| lcmp_server.retain_owned(&identity, x)) {
         Ok(contexts) => {
             let c = Contexts { contexts };
-            return ok_response(&c);
+            ok_response(&c)
         }
-        Err(err) => problem_details_response(StatusCode::INTERNAL_SERVER_ERROR, err.as_str()),
+        Err(err) => error_response(&err, &req),
     }
 }
 
 /// Handler for DELETE /app_contexts/{contextId}
-async fn delete_context(data: web::Data<AppState>, info: web::Path<String>) -> HttpResponse {
+async fn delete_context(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    info: web::Path<String>,
+) -> HttpResponse {
+    let identity = match authorize(&req, &data, Operation::DelContext) {
+        Ok(x) => x,
+        Err(err) => return error_response(&err, &req),
+    };
     match data
         .lcmp_server
         .lock()
         .unwrap()
-        .app_context()
-        .del_context(&info)
+        .del_context(&identity, &info)
     {
         Ok(_) => HttpResponse::NoContent().into(),
-        Err(err) => problem_details_response(StatusCode::NOT_FOUND, err.as_str()),
+        Err(err) => error_response(&err, &req),
     }
 }
 
 /// Handler for UPDATE /app_contexts/{contextId}
 async fn update_context(
+    req: HttpRequest,
     data: web::Data<AppState>,
     body: String,
     info: web::Path<String>,
 ) -> HttpResponse {
+    let identity = match authorize(&req, &data, Operation::UpdateContext) {
+        Ok(x) => x,
+        Err(err) => return error_response(&err, &req),
+    };
     let mut x: Result<AppContext, serde_json::Error> = serde_json::from_str(&body);
     match &mut x {
         Ok(app_context) => {
             if let Some(context_id) = &app_context.contextId {
                 if context_id != info.as_str() {
-                    return problem_details_response(
-                        StatusCode::BAD_REQUEST,
-                        "context ID in the request does not match the path",
+                    return error_response(
+                        &LcmpError::InvalidRequest(
+                            "context ID in the request does not match the path".to_string(),
+                        ),
+                        &req,
                     );
                 }
             }
@@ -153,29 +369,141 @@ async fn update_context(
                 .lcmp_server
                 .lock()
                 .unwrap()
-                .app_context()
-                .update_context(app_context)
+                .update_context(&identity, app_context)
             {
                 Ok(_) => HttpResponse::NoContent().into(),
-                Err(err) => problem_details_response(StatusCode::FORBIDDEN, err.as_str()),
+                Err(err) => error_response(&err, &req),
             }
         }
-        Err(err) => problem_details_response(StatusCode::BAD_REQUEST, err.to_string().as_str()),
+        Err(err) => error_response(&LcmpError::InvalidRequest(err.to_string()), &req),
     }
 }
 
 /// Handler for GET /app_contexts/{contextId}
 /// This method is *not* ETSI MEC standard
-async fn get_context(data: web::Data<AppState>, info: web::Path<String>) -> HttpResponse {
+async fn get_context(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    info: web::Path<String>,
+) -> HttpResponse {
+    let identity = match authorize(&req, &data, Operation::GetContext) {
+        Ok(x) => x,
+        Err(err) => return error_response(&err, &req),
+    };
     match data
         .lcmp_server
         .lock()
         .unwrap()
-        .app_context()
-        .get_context(&info)
+        .get_context(&identity, &info)
     {
         Ok(app_context) => ok_response(&app_context),
-        Err(err) => problem_details_response(StatusCode::NOT_FOUND, err.as_str()),
+        Err(err) => error_response(&err, &req),
+    }
+}
+
+/// Handler for POST /apps
+async fn kvs_add_app(req: HttpRequest, data: web::Data<AppState>, body: String) -> HttpResponse {
+    let x: Result<KvsApp, serde_json::Error> = serde_json::from_str(&body);
+    match x {
+        Ok(app) => {
+            if let Err(err) = app.validate() {
+                return error_response(&LcmpError::InvalidRequest(err), &req);
+            }
+            match data.lcmp_server.lock().unwrap().add_app(app) {
+                Ok(_) => HttpResponse::NoContent().into(),
+                Err(err) => error_response(&err, &req),
+            }
+        }
+        Err(err) => error_response(&LcmpError::InvalidRequest(err.to_string()), &req),
+    }
+}
+
+/// Handler for DELETE /apps/{appkey}
+async fn kvs_del_app(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    info: web::Path<String>,
+) -> HttpResponse {
+    match data.lcmp_server.lock().unwrap().del_app(&info) {
+        Ok(_) => HttpResponse::NoContent().into(),
+        Err(err) => error_response(&err, &req),
+    }
+}
+
+/// Handler for POST /platforms
+async fn kvs_add_platform(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: String,
+) -> HttpResponse {
+    let x: Result<Platform, serde_json::Error> = serde_json::from_str(&body);
+    match x {
+        Ok(platform) => {
+            if let Err(err) = platform.validate() {
+                return error_response(&LcmpError::InvalidRequest(err), &req);
+            }
+            match data.lcmp_server.lock().unwrap().add_platform(platform) {
+                Ok(_) => HttpResponse::NoContent().into(),
+                Err(err) => error_response(&err, &req),
+            }
+        }
+        Err(err) => error_response(&LcmpError::InvalidRequest(err.to_string()), &req),
+    }
+}
+
+/// Handler for DELETE /platforms/{name}
+async fn kvs_del_platform(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    info: web::Path<String>,
+) -> HttpResponse {
+    match data.lcmp_server.lock().unwrap().del_platform(&info) {
+        Ok(_) => HttpResponse::NoContent().into(),
+        Err(err) => error_response(&err, &req),
+    }
+}
+
+/// Handler for POST /platforms/{name}/load
+async fn kvs_platform_load(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: String,
+    info: web::Path<String>,
+) -> HttpResponse {
+    let x: Result<TelemetryValue, serde_json::Error> = serde_json::from_str(&body);
+    match x {
+        Ok(value) => {
+            if let Err(err) = value.validate() {
+                return error_response(&LcmpError::InvalidRequest(err), &req);
+            }
+            match data.lcmp_server.lock().unwrap().set_load(&info, &value) {
+                Ok(_) => HttpResponse::NoContent().into(),
+                Err(err) => error_response(&err, &req),
+            }
+        }
+        Err(err) => error_response(&LcmpError::InvalidRequest(err.to_string()), &req),
+    }
+}
+
+/// Handler for POST /platforms/{name}/key
+async fn kvs_platform_key(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: String,
+    info: web::Path<String>,
+) -> HttpResponse {
+    let x: Result<TelemetryValue, serde_json::Error> = serde_json::from_str(&body);
+    match x {
+        Ok(value) => {
+            if let Err(err) = value.validate() {
+                return error_response(&LcmpError::InvalidRequest(err), &req);
+            }
+            match data.lcmp_server.lock().unwrap().set_key(&info, &value) {
+                Ok(_) => HttpResponse::NoContent().into(),
+                Err(err) => error_response(&err, &req),
+            }
+        }
+        Err(err) => error_response(&LcmpError::InvalidRequest(err.to_string()), &req),
     }
 }
 
@@ -183,11 +511,53 @@ async fn get_context(data: web::Data<AppState>, info: web::Path<String>) -> Http
 async fn main() -> std::io::Result<()> {
     let args = Args::parse();
 
+    let mut lcmp_server = LcmpServer::build(&args.app_list_type, &args.app_context_type)
+        .expect("could not create the LCMP server");
+    if args.context_ttl > 0 {
+        lcmp_server =
+            lcmp_server.with_context_ttl(std::time::Duration::from_secs(args.context_ttl));
+    }
+    if args.admission_min_key > 0.0 {
+        lcmp_server = lcmp_server.with_key_admission(KeyAdmissionConf {
+            min_key: args.admission_min_key,
+            retry_after: args.admission_retry_after,
+        });
+    }
+    let authorizer = build_authorizer(&args.authorizer).unwrap_or_else(|x| {
+        println!(
+            "could not build the Authorizer with args {}: {}",
+            args.authorizer, x
+        );
+        std::process::exit(1);
+    });
+    if args.authorizer.starts_with("client-cert") && args.client_ca.is_empty() {
+        println!("the client-cert Authorizer requires --client-ca");
+        std::process::exit(1);
+    }
+    let tls_config = match (args.tls_cert.is_empty(), args.tls_key.is_empty()) {
+        (true, true) if args.client_ca.is_empty() => None,
+        (true, true) => {
+            println!("--client-ca requires --tls-cert and --tls-key");
+            std::process::exit(1);
+        }
+        (false, false) => {
+            let client_ca = Some(args.client_ca.as_str()).filter(|x| !x.is_empty());
+            match tls::server_config(&args.tls_cert, &args.tls_key, client_ca) {
+                Ok(x) => Some(x),
+                Err(x) => {
+                    println!("could not build the TLS configuration: {}", x);
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => {
+            println!("--tls-cert and --tls-key must be specified together");
+            std::process::exit(1);
+        }
+    };
     let state = web::Data::new(AppState {
-        lcmp_server: Mutex::new(
-            LcmpServer::build(&args.app_list_type, &args.app_context_type)
-                .expect("could not create the LCMP server"),
-        ),
+        lcmp_server: Mutex::new(lcmp_server),
+        authorizer,
     });
 
     {
@@ -210,19 +580,106 @@ async fn main() -> std::io::Result<()> {
 
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
 
+    if args.optimization_period > 0 {
+        let conf = OptimizationConf {
+            period: std::time::Duration::from_secs(args.optimization_period),
+            max_load: args.optimization_max_load,
+            min_key: args.optimization_min_key,
+            hysteresis: args.optimization_hysteresis,
+            max_migrations: args.optimization_max_migrations,
+        };
+        let state = state.clone();
+        info!("starting optimization every {:?}", conf.period);
+        std::thread::spawn(move || loop {
+            std::thread::sleep(conf.period);
+            match state.lcmp_server.lock().unwrap().optimize(&conf) {
+                Ok(0) => {}
+                Ok(x) => info!("optimization round completed, {} contexts migrated", x),
+                Err(err) => warn!("optimization round failed: {}", err),
+            }
+        });
+    }
+
+    if args.context_ttl > 0 {
+        let period = std::time::Duration::from_secs(args.context_reaper_period.max(1));
+        let state = state.clone();
+        info!(
+            "deleting the contexts expired after {} s every {:?}",
+            args.context_ttl, period
+        );
+        std::thread::spawn(move || loop {
+            std::thread::sleep(period);
+            if let Err(err) = state.lcmp_server.lock().unwrap().expire_contexts() {
+                warn!("could not delete the expired contexts: {}", err);
+            }
+        });
+    }
+
+    if args.key_telemetry_period > 0 {
+        let period = std::time::Duration::from_secs(args.key_telemetry_period);
+        let state = state.clone();
+        info!(
+            "retrieving the key availability from the KMEs every {:?}",
+            period
+        );
+        std::thread::spawn(move || loop {
+            if let Err(err) = update_key_telemetry(&state.lcmp_server, KME_TIMEOUT) {
+                warn!("could not update the key availability: {}", err);
+            }
+            std::thread::sleep(period);
+        });
+    }
+
+    if !args.kvs_address.is_empty() {
+        let kvs_state = state.clone();
+        info!("starting KVS admin HTTP server at {}", args.kvs_address);
+        let kvs_server = HttpServer::new(move || {
+            App::new()
+                .wrap(Logger::default())
+                .app_data(kvs_state.clone())
+                .default_service(web::to(not_found))
+                .service(web::resource("/apps").route(web::post().to(kvs_add_app)))
+                .service(web::resource("/apps/{appkey}").route(web::delete().to(kvs_del_app)))
+                .service(web::resource("/platforms").route(web::post().to(kvs_add_platform)))
+                .service(
+                    web::resource("/platforms/{name}").route(web::delete().to(kvs_del_platform)),
+                )
+                .service(
+                    web::resource("/platforms/{name}/load")
+                        .route(web::post().to(kvs_platform_load)),
+                )
+                .service(
+                    web::resource("/platforms/{name}/key").route(web::post().to(kvs_platform_key)),
+                )
+        })
+        .bind(args.kvs_address)?
+        .workers(1)
+        .run();
+        actix_web::rt::spawn(kvs_server);
+    }
+
     info!(
-        "starting HTTP server with {} workers at {}",
-        args.workers, args.address
+        "starting HTTP{} server with {} workers at {}",
+        if tls_config.is_some() { "S" } else { "" },
+        args.workers,
+        args.address
     );
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
             .wrap(Logger::default())
             .app_data(state.clone())
+            .app_data(web::QueryConfig::default().error_handler(query_error_handler))
+            .default_service(web::to(not_found))
             .service(
                 web::resource("/dev_app/v1/app_list")
                     .guard(guard::Header("content-type", "application/json"))
                     .route(web::get().to(app_list)),
             )
+            .service(
+                web::resource("/dev_app/v1/obtain_app_loc_availability")
+                    .guard(guard::Header("content-type", "application/json"))
+                    .route(web::post().to(obtain_app_loc_availability)),
+            )
             .service(
                 web::resource("/dev_app/v1/app_contexts")
                     .guard(guard::Header("content-type", "application/json"))
@@ -243,7 +700,11 @@ async fn main() -> std::io::Result<()> {
                     .route(web::get().to(get_context)),
             )
     })
-    .bind(args.address)?
+    .on_connect(on_connect);
+    match tls_config {
+        Some(config) => server.bind_rustls_0_23(args.address, config)?,
+        None => server.bind(args.address)?,
+    }
     .workers(args.workers)
     .run()
     .await
@@ -0,0 +1,166 @@
+//! Errors returned by the LCMP components and their mapping to HTTP responses.
+
+use crate::messages::ProblemDetails;
+use std::fmt::{Display, Formatter};
+
+/// Base URI of the problem types, each identified by a fragment in docs/problems.md.
+pub const PROBLEM_TYPE_BASE: &str =
+    "https://github.com/ccicconetti/etsi-mec-qkd/blob/main/docs/problems.md";
+
+/// Error of an operation of the LCMP components.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LcmpError {
+    /// The request is malformed or contains invalid values.
+    InvalidRequest(String),
+    /// The caller could not be authenticated.
+    Unauthorized(String),
+    /// The caller is not authorized to perform the operation.
+    Forbidden(String),
+    /// The resource addressed by the request does not exist.
+    NotFound(String),
+    /// The request conflicts with the current state of the resource.
+    Conflict(String),
+    /// Accepting the request would exceed a limit, e.g., on the number of contexts.
+    QuotaExceeded(String),
+    /// A backend needed to serve the request is unavailable, e.g., a database,
+    /// or no platform can host the application.
+    BackendUnavailable(String),
+    /// Not enough QKD keys are available to serve the request, which can be
+    /// retried after the given number of seconds.
+    KeysUnavailable(String, u64),
+    /// Unexpected failure.
+    Internal(String),
+}
+
+impl LcmpError {
+    /// Return the HTTP status code corresponding to the error.
+    pub fn status(&self) -> u16 {
+        match self {
+            LcmpError::InvalidRequest(_) => 400,
+            LcmpError::Unauthorized(_) => 401,
+            LcmpError::Forbidden(_) => 403,
+            LcmpError::NotFound(_) => 404,
+            LcmpError::Conflict(_) => 409,
+            LcmpError::QuotaExceeded(_) => 403,
+            LcmpError::BackendUnavailable(_) => 503,
+            LcmpError::KeysUnavailable(_, _) => 503,
+            LcmpError::Internal(_) => 500,
+        }
+    }
+
+    /// Return the human-readable explanation of the error.
+    pub fn detail(&self) -> &str {
+        match self {
+            LcmpError::InvalidRequest(x)
+            | LcmpError::Unauthorized(x)
+            | LcmpError::Forbidden(x)
+            | LcmpError::NotFound(x)
+            | LcmpError::Conflict(x)
+            | LcmpError::QuotaExceeded(x)
+            | LcmpError::BackendUnavailable(x)
+            | LcmpError::KeysUnavailable(x, _)
+            | LcmpError::Internal(x) => x,
+        }
+    }
+
+    /// Return the number of seconds after which the request can be retried, if known.
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            LcmpError::KeysUnavailable(_, x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    /// Return the URI identifying the problem type of the error.
+    pub fn problem_type(&self) -> String {
+        let fragment = match self {
+            LcmpError::InvalidRequest(_) => "invalid-request",
+            LcmpError::Unauthorized(_) => "unauthorized",
+            LcmpError::Forbidden(_) => "forbidden",
+            LcmpError::NotFound(_) => "not-found",
+            LcmpError::Conflict(_) => "conflict",
+            LcmpError::QuotaExceeded(_) => "quota-exceeded",
+            LcmpError::BackendUnavailable(_) => "backend-unavailable",
+            LcmpError::KeysUnavailable(_, _) => "keys-unavailable",
+            LcmpError::Internal(_) => "internal-error",
+        };
+        format!("{}#{}", PROBLEM_TYPE_BASE, fragment)
+    }
+
+    /// Return the short summary of the problem type of the error.
+    pub fn title(&self) -> &'static str {
+        match self {
+            LcmpError::InvalidRequest(_) => "Invalid request",
+            LcmpError::Unauthorized(_) => "Unauthorized",
+            LcmpError::Forbidden(_) => "Forbidden",
+            LcmpError::NotFound(_) => "Resource not found",
+            LcmpError::Conflict(_) => "Conflict with the current state",
+            LcmpError::QuotaExceeded(_) => "Quota exceeded",
+            LcmpError::BackendUnavailable(_) => "Backend unavailable",
+            LcmpError::KeysUnavailable(_, _) => "QKD keys unavailable",
+            LcmpError::Internal(_) => "Internal error",
+        }
+    }
+
+    /// Return the ProblemDetails to be sent to the client, with the given
+    /// URI of the occurrence of the problem, e.g., the path of the request.
+    pub fn problem_details(&self, instance: Option<&str>) -> ProblemDetails {
+        ProblemDetails {
+            r#type: Some(self.problem_type()),
+            title: Some(self.title().to_string()),
+            status: self.status().into(),
+            detail: self.detail().to_string(),
+            instance: instance.map(|x| x.to_string()),
+        }
+    }
+}
+
+impl Display for LcmpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.detail())
+    }
+}
+
+impl std::error::Error for LcmpError {}
+
+/// Errors are propagated as strings where the type is not relevant,
+/// e.g., when building the components from their configuration.
+impl From<LcmpError> for String {
+    fn from(err: LcmpError) -> Self {
+        err.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lcmp_error() {
+        let err = LcmpError::Conflict("mismatch".to_string());
+        assert_eq!(409, err.status());
+        assert_eq!("mismatch", err.to_string());
+        let p = err.problem_details(Some("/dev_app/v1/app_contexts/123"));
+        assert_eq!(409, p.status);
+        assert_eq!("mismatch", p.detail);
+        assert_eq!(Some(format!("{}#conflict", PROBLEM_TYPE_BASE)), p.r#type);
+        assert_eq!(Some("Conflict with the current state".to_string()), p.title);
+        assert_eq!(Some("/dev_app/v1/app_contexts/123".to_string()), p.instance);
+        assert!(LcmpError::Internal(String::new())
+            .problem_details(None)
+            .instance
+            .is_none());
+        assert_eq!(503, LcmpError::BackendUnavailable(String::new()).status());
+        assert!(LcmpError::BackendUnavailable(String::new())
+            .retry_after()
+            .is_none());
+        let err = LcmpError::KeysUnavailable("no keys".to_string(), 10);
+        assert_eq!(503, err.status());
+        assert_eq!(Some(10), err.retry_after());
+        assert_eq!("no keys", err.to_string());
+        assert_eq!(401, LcmpError::Unauthorized(String::new()).status());
+        assert_eq!(403, LcmpError::Forbidden(String::new()).status());
+        let s: String = LcmpError::NotFound("not found".to_string()).into();
+        assert_eq!("not found", s);
+    }
+}
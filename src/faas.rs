@@ -0,0 +1,544 @@
+//! Drivers of the FaaS platforms where the MEO deploys the MEC apps as actions.
+//!
+//! The actions are described by the deployInfo of the apps in the KVS,
+//! whose content depends on the platform type.
+
+#![allow(non_snake_case)]
+
+use crate::kvs::Platform;
+use base64::Engine;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// Interface of the driver of a FaaS platform.
+pub trait FaasPlatform {
+    /// Deploy an action, overwriting it if already present.
+    fn deploy_action(&self, deploy_info: &serde_json::Value) -> Result<(), String>;
+    /// Delete an action.
+    fn delete_action(&self, deploy_info: &serde_json::Value) -> Result<(), String>;
+    /// Return the names of the actions deployed.
+    fn list_actions(&self) -> Result<Vec<String>, String>;
+    /// Return an error if the platform is not reachable or not working properly.
+    fn health(&self) -> Result<(), String>;
+}
+
+/// Function creating the driver of a FaaS platform.
+pub type FaasPlatformFactory =
+    Box<dyn Fn(&Platform) -> Result<Box<dyn FaasPlatform + Send + Sync>, String> + Send + Sync>;
+
+/// Create the driver of a FaaS platform depending on its type.
+pub fn build_faas_platform(
+    platform: &Platform,
+) -> Result<Box<dyn FaasPlatform + Send + Sync>, String> {
+    match platform.platformType.as_str() {
+        "openwhisk" => Ok(Box::new(OpenWhiskPlatform::new(
+            &platform.endpointMgmt,
+            &platform.credentials,
+            Duration::from_secs(10),
+        ))),
+        _ => Err(format!(
+            "unsupported platform type: {}",
+            platform.platformType
+        )),
+    }
+}
+
+/// Outcome of an operation, if completed.
+type Outcome = Option<Result<(), String>>;
+
+/// Outcome of an operation performed by a FaasWorker, which can be waited for.
+/// The clones share the same outcome.
+#[derive(Clone, Default)]
+pub struct Completion {
+    state: Arc<(Mutex<Outcome>, Condvar)>,
+}
+
+impl Completion {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return a Completion already completed with the given outcome.
+    pub fn completed(result: Result<(), String>) -> Self {
+        let ret = Self::new();
+        ret.complete(result);
+        ret
+    }
+
+    /// Set the outcome of the operation and wake up the waiters.
+    pub fn complete(&self, result: Result<(), String>) {
+        let (outcome, cvar) = self.state.as_ref();
+        *outcome.lock().unwrap() = Some(result);
+        cvar.notify_all();
+    }
+
+    /// Return the outcome of the operation, if completed, without waiting.
+    pub fn result(&self) -> Outcome {
+        self.state.0.lock().unwrap().clone()
+    }
+
+    /// Wait for the operation to complete and return its outcome.
+    pub fn wait(&self) -> Result<(), String> {
+        let (outcome, cvar) = self.state.as_ref();
+        let outcome = cvar
+            .wait_while(outcome.lock().unwrap(), |x| x.is_none())
+            .unwrap();
+        outcome.clone().unwrap()
+    }
+}
+
+/// Operation performed by a FaasWorker.
+type FaasJob = Box<dyn FnOnce() + Send>;
+
+/// Thread performing in order the operations on the actions of a FaaS platform,
+/// so that they are not performed while holding the locks of the LCMP.
+pub struct FaasWorker {
+    sender: Sender<FaasJob>,
+}
+
+impl FaasWorker {
+    /// Start the thread, which terminates when the worker is dropped.
+    pub fn start() -> Self {
+        let (sender, receiver) = mpsc::channel::<FaasJob>();
+        std::thread::spawn(move || {
+            for job in receiver {
+                job();
+            }
+        });
+        Self { sender }
+    }
+
+    /// Queue an operation, performed after those already queued.
+    pub fn submit(&self, job: impl FnOnce() + Send + 'static) {
+        if self.sender.send(Box::new(job)).is_err() {
+            warn!("FaaS worker terminated, operation discarded");
+        }
+    }
+
+    /// Wait until the operations already queued have been performed.
+    pub fn flush(&self) {
+        let completion = Completion::new();
+        let cloned = completion.clone();
+        self.submit(move || cloned.complete(Ok(())));
+        let _ = completion.wait();
+    }
+}
+
+/// Content of the deployInfo of an app for Apache OpenWhisk platforms.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct OpenWhiskDeployInfo {
+    /// Name of the action.
+    pub name: String,
+    /// Namespace of the action, "_" means the default namespace of the credentials.
+    #[serde(default = "default_namespace")]
+    pub namespace: String,
+    /// Runtime of the action, e.g., nodejs:default, python:3, or blackbox.
+    pub kind: String,
+    /// Source code of the action, not needed with blackbox actions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    /// Docker image of blackbox actions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Name of the entry-point function, if not the runtime's default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub main: Option<String>,
+    /// If true, the action is exported as a web action.
+    #[serde(default = "default_web")]
+    pub web: bool,
+    /// Default parameters of the action.
+    #[serde(default)]
+    pub parameters: serde_json::Map<String, serde_json::Value>,
+}
+
+fn default_namespace() -> String {
+    "_".to_string()
+}
+
+fn default_web() -> bool {
+    true
+}
+
+impl OpenWhiskDeployInfo {
+    pub fn from_value(deploy_info: &serde_json::Value) -> Result<Self, String> {
+        let deploy_info: Self = serde_json::from_value(deploy_info.clone())
+            .map_err(|x| format!("invalid OpenWhisk deployInfo: {}", x))?;
+        if deploy_info.name.is_empty() {
+            return Err("empty action name in OpenWhisk deployInfo".to_string());
+        }
+        match (
+            deploy_info.kind.as_str(),
+            &deploy_info.code,
+            &deploy_info.image,
+        ) {
+            ("blackbox", _, None) => Err("missing image of blackbox action".to_string()),
+            ("blackbox", _, Some(_)) => Ok(deploy_info),
+            (_, None, _) => Err("missing code of action".to_string()),
+            _ => Ok(deploy_info),
+        }
+    }
+
+    /// Return the body of the request to create or update the action.
+    fn action(&self) -> serde_json::Value {
+        let mut exec = serde_json::json!({ "kind": self.kind });
+        for (key, value) in [
+            ("code", &self.code),
+            ("image", &self.image),
+            ("main", &self.main),
+        ] {
+            if let Some(value) = value {
+                exec[key] = serde_json::Value::String(value.clone());
+            }
+        }
+        let parameters: Vec<serde_json::Value> = self
+            .parameters
+            .iter()
+            .map(|(key, value)| serde_json::json!({ "key": key, "value": value }))
+            .collect();
+        serde_json::json!({
+            "exec": exec,
+            "parameters": parameters,
+            "annotations": [ { "key": "web-export", "value": self.web } ],
+        })
+    }
+}
+
+/// Driver of an Apache OpenWhisk platform using its REST API.
+pub struct OpenWhiskPlatform {
+    /// Base URL of the OpenWhisk API host, e.g., http://openwhisk:3233
+    endpoint: String,
+    /// Value of the Authorization header, derived from the credentials user:pass.
+    authorization: String,
+    agent: ureq::Agent,
+}
+
+impl OpenWhiskPlatform {
+    pub fn new(endpoint: &str, credentials: &str, timeout: Duration) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            authorization: format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD.encode(credentials)
+            ),
+            agent: ureq::AgentBuilder::new().timeout(timeout).build(),
+        }
+    }
+
+    fn url(&self, namespace: &str, name: &str) -> String {
+        format!(
+            "{}/api/v1/namespaces/{}/actions/{}",
+            self.endpoint, namespace, name
+        )
+    }
+
+    fn error(err: ureq::Error) -> String {
+        match err {
+            ureq::Error::Status(code, response) => format!(
+                "OpenWhisk error {}: {}",
+                code,
+                response.into_string().unwrap_or_default()
+            ),
+            err => format!("could not reach OpenWhisk: {}", err),
+        }
+    }
+}
+
+impl FaasPlatform for OpenWhiskPlatform {
+    fn deploy_action(&self, deploy_info: &serde_json::Value) -> Result<(), String> {
+        let deploy_info = OpenWhiskDeployInfo::from_value(deploy_info)?;
+        self.agent
+            .put(&self.url(&deploy_info.namespace, &deploy_info.name))
+            .query("overwrite", "true")
+            .set("Authorization", &self.authorization)
+            .set("Content-Type", "application/json")
+            .send_string(&deploy_info.action().to_string())
+            .map_err(Self::error)?;
+        Ok(())
+    }
+
+    fn delete_action(&self, deploy_info: &serde_json::Value) -> Result<(), String> {
+        let deploy_info = OpenWhiskDeployInfo::from_value(deploy_info)?;
+        self.agent
+            .delete(&self.url(&deploy_info.namespace, &deploy_info.name))
+            .set("Authorization", &self.authorization)
+            .call()
+            .map_err(Self::error)?;
+        Ok(())
+    }
+
+    fn list_actions(&self) -> Result<Vec<String>, String> {
+        let body = self
+            .agent
+            .get(&format!("{}/api/v1/namespaces/_/actions", self.endpoint))
+            .set("Authorization", &self.authorization)
+            .call()
+            .map_err(Self::error)?
+            .into_string()
+            .map_err(|x| x.to_string())?;
+        let actions: Vec<serde_json::Value> = serde_json::from_str(&body)
+            .map_err(|x| format!("invalid response from OpenWhisk: {}", x))?;
+        Ok(actions
+            .iter()
+            .filter_map(|x| x["name"].as_str().map(|x| x.to_string()))
+            .collect())
+    }
+
+    fn health(&self) -> Result<(), String> {
+        self.agent
+            .get(&format!("{}/api/v1", self.endpoint))
+            .set("Authorization", &self.authorization)
+            .call()
+            .map_err(Self::error)?;
+        Ok(())
+    }
+}
+
+/// In-process FaaS platform keeping the actions in memory, for testing.
+/// The clones share the same state.
+#[derive(Clone, Default)]
+pub struct FakeFaasPlatform {
+    state: Arc<Mutex<FakeState>>,
+}
+
+#[derive(Default)]
+struct FakeState {
+    /// Actions deployed, key: name, value: deployInfo.
+    actions: HashMap<String, serde_json::Value>,
+    /// Number of deploy operations.
+    deployed: usize,
+    /// Number of delete operations.
+    deleted: usize,
+    /// If true, all the operations fail.
+    failing: bool,
+}
+
+impl FakeFaasPlatform {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make all the subsequent operations fail, or succeed again.
+    pub fn set_failing(&self, failing: bool) {
+        self.state.lock().unwrap().failing = failing;
+    }
+
+    /// Return the number of deploy and delete operations performed.
+    pub fn operations(&self) -> (usize, usize) {
+        let state = self.state.lock().unwrap();
+        (state.deployed, state.deleted)
+    }
+
+    fn action_name(deploy_info: &serde_json::Value) -> Result<String, String> {
+        match deploy_info["name"].as_str() {
+            Some(name) if !name.is_empty() => Ok(name.to_string()),
+            _ => Err("missing action name in deployInfo".to_string()),
+        }
+    }
+
+    fn check(state: &FakeState) -> Result<(), String> {
+        match state.failing {
+            true => Err("fake platform failure".to_string()),
+            false => Ok(()),
+        }
+    }
+}
+
+impl FaasPlatform for FakeFaasPlatform {
+    fn deploy_action(&self, deploy_info: &serde_json::Value) -> Result<(), String> {
+        let name = Self::action_name(deploy_info)?;
+        let mut state = self.state.lock().unwrap();
+        Self::check(&state)?;
+        state.actions.insert(name, deploy_info.clone());
+        state.deployed += 1;
+        Ok(())
+    }
+
+    fn delete_action(&self, deploy_info: &serde_json::Value) -> Result<(), String> {
+        let name = Self::action_name(deploy_info)?;
+        let mut state = self.state.lock().unwrap();
+        Self::check(&state)?;
+        match state.actions.remove(&name) {
+            Some(_) => {
+                state.deleted += 1;
+                Ok(())
+            }
+            None => Err(format!("action not found: {}", name)),
+        }
+    }
+
+    fn list_actions(&self) -> Result<Vec<String>, String> {
+        let state = self.state.lock().unwrap();
+        Self::check(&state)?;
+        let mut actions: Vec<String> = state.actions.keys().cloned().collect();
+        actions.sort();
+        Ok(actions)
+    }
+
+    fn health(&self) -> Result<(), String> {
+        Self::check(&self.state.lock().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::httpserver::stand_in::HttpStandIn;
+    use serde_json::json;
+
+    fn example_deploy_info() -> serde_json::Value {
+        json!({
+            "name": "hello",
+            "kind": "nodejs:default",
+            "code": "function main(params) { return { payload: 'hello' }; }",
+            "parameters": { "greeting": "hi" }
+        })
+    }
+
+    #[test]
+    fn test_openwhisk_deploy_info() -> Result<(), String> {
+        let deploy_info = OpenWhiskDeployInfo::from_value(&example_deploy_info())?;
+        assert_eq!("_", deploy_info.namespace);
+        assert!(deploy_info.web);
+        assert_eq!(
+            json!({
+                "exec": {
+                    "kind": "nodejs:default",
+                    "code": "function main(params) { return { payload: 'hello' }; }"
+                },
+                "parameters": [ { "key": "greeting", "value": "hi" } ],
+                "annotations": [ { "key": "web-export", "value": true } ]
+            }),
+            deploy_info.action()
+        );
+
+        let blackbox = json!({ "name": "bb", "kind": "blackbox", "image": "user/image" });
+        assert_eq!(
+            json!({ "kind": "blackbox", "image": "user/image" }),
+            OpenWhiskDeployInfo::from_value(&blackbox)?.action()["exec"]
+        );
+
+        for invalid in [
+            json!(null),
+            json!({ "kind": "nodejs:default", "code": "x" }),
+            json!({ "name": "", "kind": "nodejs:default", "code": "x" }),
+            json!({ "name": "a", "kind": "nodejs:default" }),
+            json!({ "name": "a", "kind": "blackbox" }),
+        ] {
+            assert!(OpenWhiskDeployInfo::from_value(&invalid).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_openwhisk_platform() -> Result<(), String> {
+        let server = HttpStandIn::start(vec![
+            (200, "{}".to_string()),
+            (200, r#"[{"name":"hello"},{"name":"other"}]"#.to_string()),
+            (200, "{}".to_string()),
+            (200, "{}".to_string()),
+            (
+                404,
+                r#"{"error":"The requested resource does not exist."}"#.to_string(),
+            ),
+        ]);
+        let platform = OpenWhiskPlatform::new(&server.url(), "user:pass", Duration::from_secs(1));
+
+        platform.deploy_action(&example_deploy_info())?;
+        assert_eq!(vec!["hello", "other"], platform.list_actions()?);
+        platform.health()?;
+        platform.delete_action(&example_deploy_info())?;
+        let err = platform.delete_action(&example_deploy_info());
+        assert!(err.unwrap_err().starts_with("OpenWhisk error 404"));
+
+        // invalid deployInfo is not sent
+        assert!(platform.deploy_action(&json!({})).is_err());
+
+        let requests = server.requests();
+        assert_eq!(5, requests.len());
+        assert_eq!("PUT", requests[0].method);
+        assert_eq!(
+            "/api/v1/namespaces/_/actions/hello?overwrite=true",
+            requests[0].path
+        );
+        assert_eq!(
+            Some("Basic dXNlcjpwYXNz"),
+            requests[0].header("authorization")
+        );
+        let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!("nodejs:default", body["exec"]["kind"]);
+        assert_eq!("GET", requests[1].method);
+        assert_eq!("/api/v1/namespaces/_/actions", requests[1].path);
+        assert_eq!("/api/v1", requests[2].path);
+        assert_eq!("DELETE", requests[3].method);
+        assert_eq!("/api/v1/namespaces/_/actions/hello", requests[3].path);
+
+        // unreachable platform
+        drop(server);
+        let platform = OpenWhiskPlatform::new("http://127.0.0.1:1", "", Duration::from_secs(1));
+        assert!(platform.health().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fake_faas_platform() -> Result<(), String> {
+        let platform = FakeFaasPlatform::new();
+        let other = platform.clone();
+        platform.health()?;
+        platform.deploy_action(&json!({ "name": "a" }))?;
+        platform.deploy_action(&json!({ "name": "b" }))?;
+        assert!(platform.deploy_action(&json!({})).is_err());
+        assert_eq!(vec!["a", "b"], other.list_actions()?);
+        other.delete_action(&json!({ "name": "a" }))?;
+        assert!(other.delete_action(&json!({ "name": "a" })).is_err());
+        assert_eq!(vec!["b"], platform.list_actions()?);
+        assert_eq!((2, 1), platform.operations());
+
+        platform.set_failing(true);
+        assert!(other.health().is_err());
+        assert!(other.deploy_action(&json!({ "name": "c" })).is_err());
+        assert_eq!((2, 1), platform.operations());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_faas_worker() -> Result<(), String> {
+        let platform = FakeFaasPlatform::new();
+        let worker = FaasWorker::start();
+        let deployed = Completion::new();
+        let cloned = (platform.clone(), deployed.clone());
+        worker.submit(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            cloned
+                .1
+                .complete(cloned.0.deploy_action(&json!({ "name": "a" })));
+        });
+        assert!(deployed.result().is_none());
+
+        // the operations are performed in order
+        let cloned = platform.clone();
+        worker.submit(move || cloned.delete_action(&json!({ "name": "a" })).unwrap());
+        deployed.wait()?;
+        assert_eq!(Some(Ok(())), deployed.result());
+        worker.flush();
+        assert_eq!((1, 1), platform.operations());
+
+        let failed = Completion::completed(Err("failure".to_string()));
+        assert_eq!(Err("failure".to_string()), failed.clone().wait());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_faas_platform() {
+        let mut platform = Platform::example("p1");
+        assert!(build_faas_platform(&platform).is_ok());
+        platform.platformType = "unknown".to_string();
+        assert!(build_faas_platform(&platform).is_err());
+    }
+}
@@ -1,14 +1,154 @@
 //! HTTP test and utilities
 
-use actix_web::{get, post, HttpResponse, HttpServer, Responder};
+/// Stand-in of remote HTTP end-points, for unit tests.
+#[cfg(test)]
+pub mod stand_in {
+    use std::collections::VecDeque;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    /// HTTP request received by an HttpStandIn.
+    #[derive(Clone)]
+    pub struct RecordedRequest {
+        pub method: String,
+        pub path: String,
+        pub headers: Vec<(String, String)>,
+        pub body: String,
+    }
+
+    impl RecordedRequest {
+        /// Return the value of a header, if present.
+        pub fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|x| x.0.eq_ignore_ascii_case(name))
+                .map(|x| x.1.as_str())
+        }
+    }
+
+    /// Minimal HTTP server recording the requests received, used in unit tests
+    /// as a stand-in of remote end-points.
+    /// The responses are returned in the order given upon construction, then
+    /// 204 No Content is returned for all subsequent requests.
+    pub struct HttpStandIn {
+        address: String,
+        requests: Arc<Mutex<Vec<RecordedRequest>>>,
+        stop: Arc<AtomicBool>,
+    }
+
+    impl HttpStandIn {
+        /// Start a new server on a random port of the loopback interface.
+        pub fn start(responses: Vec<(u16, String)>) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").expect("could not bind");
+            let address = listener.local_addr().unwrap().to_string();
+            let requests = Arc::new(Mutex::new(vec![]));
+            let stop = Arc::new(AtomicBool::new(false));
+            let mut responses: VecDeque<(u16, String)> = responses.into();
+            let thread_requests = requests.clone();
+            let thread_stop = stop.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if thread_stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let response = responses.pop_front().unwrap_or((204, String::new()));
+                        if let Some(request) = Self::serve(stream, response) {
+                            thread_requests.lock().unwrap().push(request);
+                        }
+                    }
+                }
+            });
+            Self {
+                address,
+                requests,
+                stop,
+            }
+        }
+
+        /// Return the base URL of the server.
+        pub fn url(&self) -> String {
+            format!("http://{}", self.address)
+        }
+
+        /// Return the requests received so far.
+        pub fn requests(&self) -> Vec<RecordedRequest> {
+            self.requests.lock().unwrap().clone()
+        }
+
+        /// Wait until at least the given number of requests are received.
+        /// Return false if the timeout expires.
+        pub fn wait_for(&self, num: usize, timeout: Duration) -> bool {
+            let deadline = Instant::now() + timeout;
+            while Instant::now() < deadline {
+                if self.requests.lock().unwrap().len() >= num {
+                    return true;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            false
+        }
+
+        fn serve(mut stream: TcpStream, response: (u16, String)) -> Option<RecordedRequest> {
+            let mut reader = BufReader::new(stream.try_clone().ok()?);
+            let mut line = String::new();
+            reader.read_line(&mut line).ok()?;
+            let mut tokens = line.split_whitespace();
+            let method = tokens.next()?.to_string();
+            let path = tokens.next()?.to_string();
+            let mut headers = vec![];
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).ok()?;
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap_or(0);
+                    }
+                    headers.push((name.trim().to_string(), value.trim().to_string()));
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).ok()?;
+            let (status, content) = response;
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {} Stand-in\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                content.len(),
+                content
+            );
+            Some(RecordedRequest {
+                method,
+                path,
+                headers,
+                body: String::from_utf8_lossy(&body).to_string(),
+            })
+        }
+    }
+
+    impl Drop for HttpStandIn {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::Relaxed);
+            let _ = TcpStream::connect(&self.address);
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    use super::*;
     use actix_web::{
         body::{BodySize, MessageBody},
         test, web, App,
     };
+    use actix_web::{get, post, HttpResponse, HttpServer, Responder};
 
     const HELLO_MSG: &str = "Hello world!";
 
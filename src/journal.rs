@@ -0,0 +1,569 @@
+//! AppContextServer persisting the active contexts on disk, so that they
+//! survive a restart of the LCMP.
+//!
+//! Every operation modifying a context is appended to a journal, one JSON
+//! record per line, before being acknowledged. After a given number of
+//! records a snapshot of all the active contexts is written and the journal
+//! is emptied. Upon start-up the snapshot is loaded and the journal replayed.
+
+#![allow(non_snake_case)]
+
+use crate::appcontextserver::{AppContextServer, SimpleAppContextServer};
+use crate::error::LcmpError;
+use crate::messages::{AppContext, TimeStamp};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Name of the snapshot file in the journal directory.
+const SNAPSHOT_FILE: &str = "snapshot.json";
+/// Name of the journal file in the journal directory.
+const JOURNAL_FILE: &str = "journal.jsonl";
+/// Default number of journal records between consecutive snapshots.
+const DEFAULT_SNAPSHOT_EVERY: usize = 1000;
+
+/// Record of the journal.
+/// The records carry the full state of a context, so that replaying a record
+/// already included in the snapshot is harmless.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op")]
+enum Record {
+    /// A context was created or modified.
+    Put { context: Box<AppContext> },
+    /// A context was deleted.
+    Del { contextId: String },
+}
+
+/// Append-only journal of the operations on the contexts, with snapshots.
+struct Journal {
+    dir: PathBuf,
+    file: File,
+    /// Current length of the journal file, in bytes.
+    len: u64,
+    /// Number of records in the journal file.
+    records: usize,
+    /// Number of records after which a snapshot is taken.
+    snapshot_every: usize,
+}
+
+impl Journal {
+    /// Open the journal in the given directory, creating it if needed, and
+    /// return the active contexts recovered from the snapshot and the journal.
+    /// A partial record at the end of the journal, e.g., due to a crash while
+    /// writing it, is discarded.
+    fn open(
+        dir: &Path,
+        snapshot_every: usize,
+    ) -> Result<(Self, HashMap<String, AppContext>), String> {
+        std::fs::create_dir_all(dir)
+            .map_err(|x| format!("could not create directory {}: {}", dir.display(), x))?;
+
+        // Load the snapshot, if any.
+        let mut contexts = HashMap::new();
+        let snapshot = dir.join(SNAPSHOT_FILE);
+        if snapshot.exists() {
+            let content = std::fs::read_to_string(&snapshot)
+                .map_err(|x| format!("could not read {}: {}", snapshot.display(), x))?;
+            let snapshot: Vec<AppContext> = serde_json::from_str(&content)
+                .map_err(|x| format!("invalid snapshot {}: {}", snapshot.display(), x))?;
+            for context in snapshot {
+                if let Some(context_id) = &context.contextId {
+                    contexts.insert(context_id.clone(), context);
+                }
+            }
+        }
+
+        // Replay the journal.
+        let path = dir.join(JOURNAL_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)
+            .map_err(|x| format!("could not open {}: {}", path.display(), x))?;
+        let mut content = vec![];
+        file.read_to_end(&mut content)
+            .map_err(|x| format!("could not read {}: {}", path.display(), x))?;
+        let mut len = 0;
+        let mut records = 0;
+        for line in content.split_inclusive(|x| *x == b'\n') {
+            let complete = line.ends_with(b"\n");
+            match serde_json::from_slice::<Record>(line) {
+                Ok(record) if complete => {
+                    Self::apply(&mut contexts, record);
+                    len += line.len() as u64;
+                    records += 1;
+                }
+                _ if !complete => {
+                    warn!(
+                        "discarding partial record at the end of {} ({} bytes)",
+                        path.display(),
+                        line.len()
+                    );
+                    break;
+                }
+                _ => {
+                    return Err(format!(
+                        "corrupted record {} in {}",
+                        records + 1,
+                        path.display()
+                    ))
+                }
+            }
+        }
+        if len < content.len() as u64 {
+            file.set_len(len)
+                .map_err(|x| format!("could not truncate {}: {}", path.display(), x))?;
+        }
+        info!(
+            "recovered {} contexts from {} ({} journal records)",
+            contexts.len(),
+            dir.display(),
+            records
+        );
+
+        Ok((
+            Self {
+                dir: dir.to_path_buf(),
+                file,
+                len,
+                records,
+                snapshot_every,
+            },
+            contexts,
+        ))
+    }
+
+    fn apply(contexts: &mut HashMap<String, AppContext>, record: Record) {
+        match record {
+            Record::Put { context } => {
+                if let Some(context_id) = &context.contextId {
+                    contexts.insert(context_id.clone(), *context);
+                }
+            }
+            Record::Del { contextId } => {
+                contexts.remove(&contextId);
+            }
+        }
+    }
+
+    /// Append a record to the journal and flush it to disk.
+    /// If the operation fails, then the journal is left unchanged.
+    fn append(&mut self, record: &Record) -> Result<(), String> {
+        let mut line = serde_json::to_string(record).map_err(|x| x.to_string())?;
+        line.push('\n');
+        let res = self
+            .file
+            .write_all(line.as_bytes())
+            .and_then(|_| self.file.sync_data());
+        if let Err(err) = res {
+            let _ = self.file.set_len(self.len);
+            return Err(format!("could not write to the journal: {}", err));
+        }
+        self.len += line.len() as u64;
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Return true if enough records have been written to the journal since
+    /// the last snapshot.
+    fn snapshot_due(&self) -> bool {
+        self.records >= self.snapshot_every
+    }
+
+    /// Take a snapshot of the given contexts, then empty the journal.
+    /// The snapshot is first written to a temporary file, which is then
+    /// renamed, so that a crash leaves either the old or the new snapshot.
+    fn snapshot(&mut self, contexts: &[AppContext]) -> Result<(), String> {
+        let tmp = self.dir.join(format!("{}.tmp", SNAPSHOT_FILE));
+        let res = File::create(&tmp)
+            .and_then(|mut x| {
+                x.write_all(
+                    serde_json::to_string(&contexts)
+                        .unwrap_or_default()
+                        .as_bytes(),
+                )?;
+                x.sync_all()
+            })
+            .and_then(|_| std::fs::rename(&tmp, self.dir.join(SNAPSHOT_FILE)))
+            .and_then(|_| File::open(&self.dir).and_then(|x| x.sync_all()));
+        if let Err(err) = res {
+            return Err(format!("could not write the snapshot: {}", err));
+        }
+        self.file
+            .set_len(0)
+            .and_then(|_| self.file.sync_data())
+            .map_err(|x| format!("could not truncate the journal: {}", x))?;
+        self.len = 0;
+        self.records = 0;
+        Ok(())
+    }
+}
+
+/// AppContextServer with the same behavior as the single one, i.e., accepting
+/// new contexts up to a maximum and always returning the same referenceURI,
+/// which persists the active contexts in a journal.
+pub struct JournalAppContextServer {
+    inner: SimpleAppContextServer,
+    journal: Journal,
+}
+
+impl JournalAppContextServer {
+    /// Create a server with the journal in the given directory, recovering
+    /// the active contexts from a previous execution, if any.
+    pub fn new(
+        dir: &Path,
+        max_contexts: usize,
+        reference_uri: &str,
+        snapshot_every: usize,
+    ) -> Result<Self, String> {
+        let (journal, contexts) = Journal::open(dir, snapshot_every.max(1))?;
+        let mut inner = SimpleAppContextServer::default_empty(max_contexts, reference_uri);
+        for context in contexts.into_values() {
+            inner.put_context(context);
+        }
+        Ok(Self { inner, journal })
+    }
+
+    /// Create a server from a string of semicolon-separated key=value parameters:
+    /// dir (directory of the journal), max (maximum number of active contexts),
+    /// uri (reference URI), snapshot (number of records between snapshots, optional).
+    pub fn from_params(params: &str) -> Result<Self, String> {
+        let mut dir = None;
+        let mut max_contexts = None;
+        let mut reference_uri = None;
+        let mut snapshot_every = DEFAULT_SNAPSHOT_EVERY;
+        for param in params.split(';').filter(|x| !x.is_empty()) {
+            let (key, value) = param
+                .split_once('=')
+                .ok_or(format!("invalid journal parameter: {}", param))?;
+            let invalid = || format!("invalid value for {}: {}", key, value);
+            match key {
+                "dir" => dir = Some(PathBuf::from(value)),
+                "max" => max_contexts = Some(value.parse::<usize>().map_err(|_| invalid())?),
+                "uri" => reference_uri = Some(value.to_string()),
+                "snapshot" => snapshot_every = value.parse::<usize>().map_err(|_| invalid())?,
+                _ => return Err(format!("unknown journal parameter: {}", key)),
+            }
+        }
+        match (dir, max_contexts, reference_uri) {
+            (Some(dir), Some(max_contexts), Some(reference_uri)) if !reference_uri.is_empty() => {
+                Self::new(&dir, max_contexts, &reference_uri, snapshot_every)
+            }
+            _ => Err("journal parameters dir, max, and uri are mandatory".to_string()),
+        }
+    }
+
+    /// Write a record to the journal, then possibly take a snapshot.
+    /// Snapshot failures are only logged, since the record is already persisted.
+    fn persist(&mut self, record: &Record) -> Result<(), LcmpError> {
+        self.journal
+            .append(record)
+            .map_err(LcmpError::BackendUnavailable)?;
+        if self.journal.snapshot_due() {
+            let mut active = vec![];
+            for context_id in self.inner.list_contexts()? {
+                active.push(self.inner.get_context(&context_id)?.clone());
+            }
+            if let Err(err) = self.journal.snapshot(&active) {
+                warn!("{}", err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Persist the current state of a context.
+    fn persist_put(&mut self, context_id: &str) -> Result<(), LcmpError> {
+        let context = Box::new(self.inner.get_context(context_id)?.clone());
+        self.persist(&Record::Put { context })
+    }
+}
+
+impl AppContextServer for JournalAppContextServer {
+    fn new_context(&mut self, app_context: &mut AppContext) -> Result<(), LcmpError> {
+        self.inner.new_context(app_context)?;
+        let context_id = app_context.contextId.clone().unwrap_or_default();
+        if let Err(err) = self.persist_put(&context_id) {
+            let _ = self.inner.del_context(&context_id);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn del_context(&mut self, context_id: &str) -> Result<(), LcmpError> {
+        let old = self.inner.get_context(context_id)?.clone();
+        self.inner.del_context(context_id)?;
+        if let Err(err) = self.persist(&Record::Del {
+            contextId: context_id.to_string(),
+        }) {
+            self.inner.put_context(old);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn get_context(&mut self, context_id: &str) -> Result<&AppContext, LcmpError> {
+        self.inner.get_context(context_id)
+    }
+
+    fn update_context(&mut self, app_context: &mut AppContext) -> Result<(), LcmpError> {
+        let context_id = app_context
+            .contextId
+            .clone()
+            .ok_or(LcmpError::InvalidRequest(
+                "context ID not specified in the request".to_string(),
+            ))?;
+        let old = self.inner.get_context(&context_id)?.clone();
+        self.inner.update_context(app_context)?;
+        if let Err(err) = self.persist_put(&context_id) {
+            self.inner.put_context(old);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn list_contexts(&mut self) -> Result<Vec<String>, LcmpError> {
+        self.inner.list_contexts()
+    }
+
+    fn status(&self) -> Result<(), LcmpError> {
+        self.inner.status()
+    }
+
+    fn reference_uri(&mut self, app_context: &AppContext) -> Result<Option<String>, LcmpError> {
+        self.inner.reference_uri(app_context)
+    }
+
+    fn update_reference_uri(
+        &mut self,
+        context_id: &str,
+        reference_uri: &str,
+    ) -> Result<bool, LcmpError> {
+        let old = self.inner.get_context(context_id)?.clone();
+        let changed = self.inner.update_reference_uri(context_id, reference_uri)?;
+        if changed {
+            if let Err(err) = self.persist_put(context_id) {
+                self.inner.put_context(old);
+                return Err(err);
+            }
+        }
+        Ok(changed)
+    }
+
+    fn set_qkd_key_ids(
+        &mut self,
+        context_id: &str,
+        key_ids: &[Option<Vec<String>>],
+    ) -> Result<(), LcmpError> {
+        let old = self.inner.get_context(context_id)?.clone();
+        self.inner.set_qkd_key_ids(context_id, key_ids)?;
+        if let Err(err) = self.persist_put(context_id) {
+            self.inner.put_context(old);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn set_expires_at(
+        &mut self,
+        context_id: &str,
+        expires_at: Option<TimeStamp>,
+    ) -> Result<(), LcmpError> {
+        let old = self.inner.get_context(context_id)?.clone();
+        self.inner.set_expires_at(context_id, expires_at)?;
+        if let Err(err) = self.persist_put(context_id) {
+            self.inner.put_context(old);
+            return Err(err);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Return a new empty directory, removed when dropped.
+    struct TestDir {
+        path: PathBuf,
+    }
+
+    impl TestDir {
+        fn new() -> Self {
+            let path = std::env::temp_dir()
+                .join(format!("lcmp-journal-{}", uuid::Uuid::new_v4().simple()));
+            Self { path }
+        }
+
+        fn journal_len(&self) -> u64 {
+            std::fs::metadata(self.path.join(JOURNAL_FILE))
+                .map(|x| x.len())
+                .unwrap_or_default()
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+
+    fn request() -> AppContext {
+        AppContext::request_from_name_provider("my_app_name", "my_app_provider")
+    }
+
+    fn sorted(mut x: Vec<String>) -> Vec<String> {
+        x.sort();
+        x
+    }
+
+    #[test]
+    fn test_journal_from_params() {
+        let dir = TestDir::new();
+        let params = |x: &str| format!("dir={};{}", dir.path.display(), x);
+        assert!(JournalAppContextServer::from_params(&params("max=10;uri=URI")).is_ok());
+        assert!(JournalAppContextServer::from_params(&params("max=10;uri=URI;snapshot=5")).is_ok());
+        assert!(JournalAppContextServer::from_params(&params("max=10")).is_err());
+        assert!(JournalAppContextServer::from_params(&params("uri=URI")).is_err());
+        assert!(JournalAppContextServer::from_params(&params("max=x;uri=URI")).is_err());
+        assert!(JournalAppContextServer::from_params(&params("max=1;uri=U;x=1")).is_err());
+        assert!(JournalAppContextServer::from_params("max=10;uri=URI").is_err());
+    }
+
+    #[test]
+    fn test_journal_recovery() -> Result<(), String> {
+        let dir = TestDir::new();
+        let mut ids = vec![];
+        let expires_at = TimeStamp {
+            seconds: 10,
+            nanoSeconds: 0,
+        };
+        {
+            let mut server = JournalAppContextServer::new(&dir.path, 3, "URI", 1000)?;
+            for _ in 0..3 {
+                let mut a = request();
+                server.new_context(&mut a)?;
+                ids.push(a.contextId.clone().unwrap());
+            }
+            // maximum number of contexts reached: not persisted
+            assert!(server.new_context(&mut request()).is_err());
+
+            let mut a = server.get_context(&ids[1])?.clone();
+            a.callbackReference = Some("http://callback".to_string());
+            server.update_context(&mut a)?;
+            assert!(server.update_reference_uri(&ids[2], "URI2")?);
+            server.set_qkd_key_ids(&ids[2], &[Some(vec!["key1".to_string()])])?;
+            server.set_expires_at(&ids[2], Some(expires_at))?;
+            server.del_context(&ids[0])?;
+            assert!(server.del_context(&ids[0]).is_err());
+        }
+
+        let mut server = JournalAppContextServer::new(&dir.path, 3, "URI", 1000)?;
+        assert_eq!(sorted(ids[1..].to_vec()), sorted(server.list_contexts()?));
+        assert_eq!(
+            Some("http://callback".to_string()),
+            server.get_context(&ids[1])?.callbackReference
+        );
+        assert_eq!(
+            Some("URI2".to_string()),
+            server.get_context(&ids[2])?.appInfo.userAppInstanceInfo[0].referenceURI
+        );
+        assert_eq!(
+            Some(vec!["key1".to_string()]),
+            server.get_context(&ids[2])?.appInfo.userAppInstanceInfo[0].qkdKeyIds
+        );
+        assert_eq!(Some(expires_at), server.get_context(&ids[2])?.expiresAt);
+
+        // the maximum number of contexts also accounts for the recovered ones
+        server.new_context(&mut request())?;
+        assert!(server.new_context(&mut request()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_journal_snapshot() -> Result<(), String> {
+        let dir = TestDir::new();
+        let mut ids = vec![];
+        {
+            let mut server = JournalAppContextServer::new(&dir.path, 100, "URI", 4)?;
+            for _ in 0..10 {
+                let mut a = request();
+                server.new_context(&mut a)?;
+                ids.push(a.contextId.clone().unwrap());
+            }
+            for context_id in &ids[0..5] {
+                server.del_context(context_id)?;
+            }
+            // 15 records, i.e., 3 snapshots and 3 records in the journal
+            assert!(dir.path.join(SNAPSHOT_FILE).exists());
+            assert_eq!(3, server.journal.records);
+        }
+
+        let mut server = JournalAppContextServer::new(&dir.path, 100, "URI", 4)?;
+        assert_eq!(sorted(ids[5..].to_vec()), sorted(server.list_contexts()?));
+
+        // crash after the snapshot is taken, but before the journal is emptied:
+        // the records replayed on top of the snapshot do not change the state
+        let journal = std::fs::read(dir.path.join(JOURNAL_FILE)).unwrap();
+        server.del_context(&ids[5])?;
+        assert_eq!(0, server.journal.records);
+        std::fs::write(dir.path.join(JOURNAL_FILE), &journal).unwrap();
+        drop(server);
+        let mut server = JournalAppContextServer::new(&dir.path, 100, "URI", 4)?;
+        assert_eq!(sorted(ids[6..].to_vec()), sorted(server.list_contexts()?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_journal_truncated() -> Result<(), String> {
+        let dir = TestDir::new();
+        let mut ids = vec![];
+        {
+            let mut server = JournalAppContextServer::new(&dir.path, 100, "URI", 1000)?;
+            for _ in 0..3 {
+                let mut a = request();
+                server.new_context(&mut a)?;
+                ids.push(a.contextId.clone().unwrap());
+            }
+        }
+
+        // truncate the journal at every position within the last record
+        let journal = std::fs::read(dir.path.join(JOURNAL_FILE)).unwrap();
+        let last_start = journal[..journal.len() - 1]
+            .iter()
+            .rposition(|x| *x == b'\n')
+            .unwrap()
+            + 1;
+        for len in last_start..journal.len() {
+            std::fs::write(dir.path.join(JOURNAL_FILE), &journal[..len]).unwrap();
+            let mut server = JournalAppContextServer::new(&dir.path, 100, "URI", 1000)?;
+            assert_eq!(sorted(ids[..2].to_vec()), sorted(server.list_contexts()?));
+            assert_eq!(last_start as u64, dir.journal_len());
+            drop(server);
+        }
+
+        // new records are appended after the last complete one
+        {
+            let mut server = JournalAppContextServer::new(&dir.path, 100, "URI", 1000)?;
+            let mut a = request();
+            server.new_context(&mut a)?;
+            ids[2] = a.contextId.clone().unwrap();
+        }
+        let mut server = JournalAppContextServer::new(&dir.path, 100, "URI", 1000)?;
+        assert_eq!(sorted(ids.clone()), sorted(server.list_contexts()?));
+
+        // a corrupted record in the middle of the journal is an error
+        drop(server);
+        let mut journal = std::fs::read(dir.path.join(JOURNAL_FILE)).unwrap();
+        journal[1] = b'#';
+        std::fs::write(dir.path.join(JOURNAL_FILE), &journal).unwrap();
+        assert!(JournalAppContextServer::new(&dir.path, 100, "URI", 1000).is_err());
+
+        Ok(())
+    }
+}
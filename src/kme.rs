@@ -0,0 +1,600 @@
+//! Mock Key Management Entity (KME) serving the ETSI GS QKD 014 methods,
+//! intended for testing without QKD hardware.
+//!
+//! A pool of keys is kept for each pair of master/slave SAEs, which is
+//! created upon first use with an initial number of keys.
+//! Keys are generated at a constant rate, until the maximum number of keys
+//! is reached, and consumed by the Get key method.
+//! The keys delivered to the master SAEs are kept until they are retrieved
+//! by the slave SAEs, for a limited time and up to a maximum number.
+
+use crate::messages::Validate;
+use crate::qkd014::{Key, KeyContainer, KeyIds, KeyRequest, Status};
+use base64::Engine;
+use rand::RngCore;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Name of the HTTP header carrying the SAE ID of the caller.
+/// In ETSI GS QKD 014 the SAE is identified by its TLS client certificate,
+/// which is not supported by the mock KME.
+pub const SAE_ID_HEADER: &str = "X-SAE-ID";
+
+/// Error of a method of the mock KME.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KmeError {
+    /// The request is malformed or contains invalid values.
+    InvalidRequest(String),
+    /// Not enough keys are available to serve the request.
+    KeysUnavailable(String),
+}
+
+impl KmeError {
+    /// Return the HTTP status code corresponding to the error.
+    pub fn status(&self) -> u16 {
+        match self {
+            KmeError::InvalidRequest(_) => 400,
+            KmeError::KeysUnavailable(_) => 503,
+        }
+    }
+}
+
+impl Display for KmeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KmeError::InvalidRequest(x) | KmeError::KeysUnavailable(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+impl From<KmeError> for String {
+    fn from(err: KmeError) -> Self {
+        err.to_string()
+    }
+}
+
+/// Configuration of the mock KME.
+#[derive(Clone)]
+pub struct KmeConf {
+    /// KME ID of this KME.
+    pub kme_id: String,
+    /// KME ID of the target KME.
+    pub target_kme_id: String,
+    /// SAE ID assumed for the callers that do not identify themselves.
+    pub default_sae_id: String,
+    /// Default size of the keys, in bits.
+    pub key_size: u32,
+    /// Minimum size of the keys, in bits.
+    pub min_key_size: u32,
+    /// Maximum size of the keys, in bits.
+    pub max_key_size: u32,
+    /// Maximum number of keys stored for every pair of SAEs.
+    pub max_key_count: u32,
+    /// Number of keys stored when a pool is created.
+    pub initial_key_count: u32,
+    /// Maximum number of keys per request.
+    pub max_key_per_request: u32,
+    /// Number of keys generated per second for every pair of SAEs.
+    pub generation_rate: f64,
+    /// Time after which the keys delivered to a master SAE can no longer be
+    /// retrieved by the slave SAE.
+    pub delivered_key_ttl: Duration,
+    /// Maximum number of keys delivered to the master SAEs and not yet
+    /// retrieved by the slave SAEs, beyond which the oldest ones are discarded.
+    pub max_delivered_keys: usize,
+}
+
+impl Default for KmeConf {
+    fn default() -> Self {
+        Self {
+            kme_id: "kme1".to_string(),
+            target_kme_id: "kme2".to_string(),
+            default_sae_id: "sae1".to_string(),
+            key_size: 256,
+            min_key_size: 64,
+            max_key_size: 1024,
+            max_key_count: 1000,
+            initial_key_count: 1000,
+            max_key_per_request: 10,
+            generation_rate: 10.0,
+            delivered_key_ttl: Duration::from_secs(60),
+            max_delivered_keys: 10000,
+        }
+    }
+}
+
+impl Validate for KmeConf {
+    fn validate(&self) -> Result<(), String> {
+        if [self.key_size, self.min_key_size, self.max_key_size]
+            .iter()
+            .any(|x| x % 8 != 0)
+        {
+            return Err("key sizes must be multiple of 8".to_string());
+        }
+        if self.min_key_size == 0
+            || self.min_key_size > self.key_size
+            || self.key_size > self.max_key_size
+        {
+            return Err(format!(
+                "invalid key sizes: min {}, default {}, max {}",
+                self.min_key_size, self.key_size, self.max_key_size
+            ));
+        }
+        if self.initial_key_count > self.max_key_count {
+            return Err(format!(
+                "initial_key_count ({}) greater than max_key_count ({})",
+                self.initial_key_count, self.max_key_count
+            ));
+        }
+        if self.max_key_per_request == 0 {
+            return Err("max_key_per_request cannot be zero".to_string());
+        }
+        if self.max_delivered_keys == 0 {
+            return Err("max_delivered_keys cannot be zero".to_string());
+        }
+        if self.generation_rate < 0.0 {
+            return Err(format!(
+                "negative generation rate: {}",
+                self.generation_rate
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Pool of keys shared by a pair of SAEs.
+struct KeyPool {
+    /// Number of keys stored, fractional to account for partial generation.
+    stored: f64,
+    /// Time of the last update of the number of keys stored.
+    last_update: Instant,
+}
+
+/// Key delivered to a master SAE, waiting to be retrieved by the slave SAE.
+struct DeliveredKey {
+    master_sae_id: String,
+    slave_sae_id: String,
+    key: String,
+    /// Time when the key was delivered to the master SAE.
+    delivered_at: Instant,
+}
+
+/// State of the mock KME.
+pub struct MockKme {
+    conf: KmeConf,
+    /// Key pools, key: (master SAE ID, slave SAE ID).
+    pools: HashMap<(String, String), KeyPool>,
+    /// Keys delivered, key: key ID.
+    delivered: HashMap<String, DeliveredKey>,
+    /// IDs of the keys delivered, from the oldest, including those already
+    /// retrieved, which are skipped when purging.
+    delivery_order: VecDeque<String>,
+}
+
+impl MockKme {
+    pub fn new(conf: KmeConf) -> Result<Self, String> {
+        conf.validate()?;
+        Ok(Self {
+            conf,
+            pools: HashMap::new(),
+            delivered: HashMap::new(),
+            delivery_order: VecDeque::new(),
+        })
+    }
+
+    /// Return the SAE ID of the caller, given the optional value of the header.
+    pub fn caller(&self, header: Option<&str>) -> String {
+        match header {
+            Some(x) if !x.is_empty() => x.to_string(),
+            _ => self.conf.default_sae_id.clone(),
+        }
+    }
+
+    /// Get status: return the status of the pool shared by the master SAE
+    /// (i.e., the caller) and the slave SAE.
+    pub fn status(&mut self, master_sae_id: &str, slave_sae_id: &str) -> Status {
+        self.status_at(master_sae_id, slave_sae_id, Instant::now())
+    }
+
+    /// Get key: return new keys from the pool shared by the master SAE
+    /// (i.e., the caller) and the slave SAE.
+    pub fn enc_keys(
+        &mut self,
+        master_sae_id: &str,
+        slave_sae_id: &str,
+        request: &KeyRequest,
+    ) -> Result<KeyContainer, KmeError> {
+        self.enc_keys_at(master_sae_id, slave_sae_id, request, Instant::now())
+    }
+
+    /// Get key with key IDs: return the keys previously delivered to the
+    /// master SAE to the slave SAE (i.e., the caller).
+    /// The keys cannot be retrieved again.
+    pub fn dec_keys(
+        &mut self,
+        slave_sae_id: &str,
+        master_sae_id: &str,
+        key_ids: &KeyIds,
+    ) -> Result<KeyContainer, KmeError> {
+        self.dec_keys_at(slave_sae_id, master_sae_id, key_ids, Instant::now())
+    }
+
+    /// Return the number of keys delivered to the master SAEs and not yet
+    /// retrieved by the slave SAEs.
+    pub fn delivered_keys(&self) -> usize {
+        self.delivered.len()
+    }
+
+    fn dec_keys_at(
+        &mut self,
+        slave_sae_id: &str,
+        master_sae_id: &str,
+        key_ids: &KeyIds,
+        now: Instant,
+    ) -> Result<KeyContainer, KmeError> {
+        key_ids.validate().map_err(KmeError::InvalidRequest)?;
+        self.purge_delivered(now);
+        for key_id in &key_ids.key_IDs {
+            match self.delivered.get(&key_id.key_ID) {
+                Some(x) if x.master_sae_id == master_sae_id && x.slave_sae_id == slave_sae_id => {}
+                _ => {
+                    return Err(KmeError::InvalidRequest(format!(
+                        "key not found: {}",
+                        key_id.key_ID
+                    )))
+                }
+            }
+        }
+        Ok(KeyContainer {
+            keys: key_ids
+                .key_IDs
+                .iter()
+                .filter_map(|x| {
+                    self.delivered.remove(&x.key_ID).map(|delivered| Key {
+                        key_ID: x.key_ID.clone(),
+                        key_ID_extension: None,
+                        key: delivered.key,
+                        key_extension: None,
+                    })
+                })
+                .collect(),
+            key_container_extension: None,
+        })
+    }
+
+    fn status_at(&mut self, master_sae_id: &str, slave_sae_id: &str, now: Instant) -> Status {
+        let stored = self.pool(master_sae_id, slave_sae_id, now).stored;
+        Status {
+            source_KME_ID: self.conf.kme_id.clone(),
+            target_KME_ID: self.conf.target_kme_id.clone(),
+            master_SAE_ID: master_sae_id.to_string(),
+            slave_SAE_ID: slave_sae_id.to_string(),
+            key_size: self.conf.key_size,
+            stored_key_count: stored.floor() as u32,
+            max_key_count: self.conf.max_key_count,
+            max_key_per_request: self.conf.max_key_per_request,
+            max_key_size: self.conf.max_key_size,
+            min_key_size: self.conf.min_key_size,
+            max_SAE_ID_count: 0,
+            status_extension: None,
+        }
+    }
+
+    fn enc_keys_at(
+        &mut self,
+        master_sae_id: &str,
+        slave_sae_id: &str,
+        request: &KeyRequest,
+        now: Instant,
+    ) -> Result<KeyContainer, KmeError> {
+        request.validate().map_err(KmeError::InvalidRequest)?;
+        let number = request.number.unwrap_or(1);
+        let size = request.size.unwrap_or(self.conf.key_size);
+        if number > self.conf.max_key_per_request {
+            return Err(KmeError::InvalidRequest(format!(
+                "too many keys requested: {} (max {})",
+                number, self.conf.max_key_per_request
+            )));
+        }
+        if size < self.conf.min_key_size || size > self.conf.max_key_size {
+            return Err(KmeError::InvalidRequest(format!(
+                "key size out of range [{},{}]: {}",
+                self.conf.min_key_size, self.conf.max_key_size, size
+            )));
+        }
+        if request
+            .additional_slave_SAE_IDs
+            .as_ref()
+            .is_some_and(|x| !x.is_empty())
+        {
+            return Err(KmeError::InvalidRequest(
+                "key multicast not supported".to_string(),
+            ));
+        }
+
+        // Keys larger than the default size consume multiple keys from the pool.
+        let needed = (number * size.div_ceil(self.conf.key_size)) as f64;
+        let pool = self.pool(master_sae_id, slave_sae_id, now);
+        if pool.stored < needed {
+            return Err(KmeError::KeysUnavailable(format!(
+                "not enough keys available: {} requested, {} stored",
+                needed,
+                pool.stored.floor()
+            )));
+        }
+        pool.stored -= needed;
+
+        let mut keys = vec![];
+        for _ in 0..number {
+            let mut material = vec![0_u8; (size / 8) as usize];
+            rand::thread_rng().fill_bytes(&mut material);
+            let key = base64::engine::general_purpose::STANDARD.encode(&material);
+            let key_id = Uuid::new_v4().to_string();
+            self.delivered.insert(
+                key_id.clone(),
+                DeliveredKey {
+                    master_sae_id: master_sae_id.to_string(),
+                    slave_sae_id: slave_sae_id.to_string(),
+                    key: key.clone(),
+                    delivered_at: now,
+                },
+            );
+            self.delivery_order.push_back(key_id.clone());
+            keys.push(Key {
+                key_ID: key_id,
+                key_ID_extension: None,
+                key,
+                key_extension: None,
+            });
+        }
+        self.purge_delivered(now);
+        Ok(KeyContainer {
+            keys,
+            key_container_extension: None,
+        })
+    }
+
+    /// Discard the keys delivered that have expired, and the oldest ones
+    /// beyond the maximum number.
+    fn purge_delivered(&mut self, now: Instant) {
+        while let Some(key_id) = self.delivery_order.front() {
+            let purge = match self.delivered.get(key_id) {
+                // already retrieved
+                None => false,
+                Some(x) => {
+                    now.saturating_duration_since(x.delivered_at) >= self.conf.delivered_key_ttl
+                        || self.delivered.len() > self.conf.max_delivered_keys
+                }
+            };
+            if purge {
+                self.delivered.remove(key_id);
+            } else if self.delivered.contains_key(key_id) {
+                break;
+            }
+            self.delivery_order.pop_front();
+        }
+    }
+
+    /// Return the pool of the given SAEs, created if needed, after having
+    /// generated the keys since the last update.
+    fn pool(&mut self, master_sae_id: &str, slave_sae_id: &str, now: Instant) -> &mut KeyPool {
+        let conf = &self.conf;
+        let pool = self
+            .pools
+            .entry((master_sae_id.to_string(), slave_sae_id.to_string()))
+            .or_insert_with(|| KeyPool {
+                stored: conf.initial_key_count as f64,
+                last_update: now,
+            });
+        let elapsed = now
+            .saturating_duration_since(pool.last_update)
+            .as_secs_f64();
+        pool.stored = (pool.stored + elapsed * conf.generation_rate).min(conf.max_key_count as f64);
+        pool.last_update = now;
+        pool
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn example_conf() -> KmeConf {
+        KmeConf {
+            initial_key_count: 10,
+            max_key_count: 20,
+            max_key_per_request: 5,
+            generation_rate: 2.0,
+            ..Default::default()
+        }
+    }
+
+    fn request(number: u32, size: Option<u32>) -> KeyRequest {
+        KeyRequest {
+            number: Some(number),
+            size,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_kme_conf() {
+        assert!(MockKme::new(KmeConf::default()).is_ok());
+        for conf in [
+            KmeConf {
+                key_size: 100,
+                ..Default::default()
+            },
+            KmeConf {
+                min_key_size: 512,
+                ..Default::default()
+            },
+            KmeConf {
+                initial_key_count: 2000,
+                ..Default::default()
+            },
+            KmeConf {
+                max_key_per_request: 0,
+                ..Default::default()
+            },
+        ] {
+            assert!(MockKme::new(conf).is_err());
+        }
+    }
+
+    #[test]
+    fn test_kme_caller() -> Result<(), String> {
+        let kme = MockKme::new(example_conf())?;
+        assert_eq!("sae1", kme.caller(None));
+        assert_eq!("sae1", kme.caller(Some("")));
+        assert_eq!("sae9", kme.caller(Some("sae9")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_kme_depletion_and_generation() -> Result<(), String> {
+        let mut kme = MockKme::new(example_conf())?;
+        let t0 = Instant::now();
+
+        let status = kme.status_at("sae1", "sae2", t0);
+        assert_eq!(Ok(()), status.validate());
+        assert_eq!(10, status.stored_key_count);
+        assert_eq!(0.5, status.key_availability());
+
+        // keys are consumed
+        let keys = kme.enc_keys_at("sae1", "sae2", &request(4, None), t0)?;
+        assert_eq!(4, keys.keys.len());
+        for key in &keys.keys {
+            assert_eq!(
+                32,
+                base64::engine::general_purpose::STANDARD
+                    .decode(&key.key)
+                    .unwrap()
+                    .len()
+            );
+        }
+        assert_eq!(6, kme.status_at("sae1", "sae2", t0).stored_key_count);
+
+        // keys larger than the default consume more
+        kme.enc_keys_at("sae1", "sae2", &request(2, Some(512)), t0)?;
+        assert_eq!(2, kme.status_at("sae1", "sae2", t0).stored_key_count);
+
+        // pools are independent
+        assert_eq!(10, kme.status_at("sae1", "sae3", t0).stored_key_count);
+
+        // depletion
+        let err = kme
+            .enc_keys_at("sae1", "sae2", &request(3, None), t0)
+            .unwrap_err();
+        assert!(matches!(err, KmeError::KeysUnavailable(_)));
+        assert_eq!(503, err.status());
+        assert_eq!(2, kme.status_at("sae1", "sae2", t0).stored_key_count);
+
+        // generation
+        let t1 = t0 + Duration::from_millis(1500);
+        assert_eq!(5, kme.status_at("sae1", "sae2", t1).stored_key_count);
+        kme.enc_keys_at("sae1", "sae2", &request(3, None), t1)?;
+        assert_eq!(2, kme.status_at("sae1", "sae2", t1).stored_key_count);
+
+        // up to the maximum
+        let t2 = t1 + Duration::from_secs(60);
+        assert_eq!(20, kme.status_at("sae1", "sae2", t2).stored_key_count);
+
+        // invalid requests
+        for request in [
+            request(6, None),
+            request(1, Some(32)),
+            request(1, Some(2048)),
+            request(0, None),
+            KeyRequest {
+                additional_slave_SAE_IDs: Some(vec!["sae3".to_string()]),
+                ..Default::default()
+            },
+        ] {
+            let err = kme.enc_keys_at("sae1", "sae2", &request, t2).unwrap_err();
+            assert!(matches!(err, KmeError::InvalidRequest(_)));
+            assert_eq!(400, err.status());
+        }
+        assert_eq!(20, kme.status_at("sae1", "sae2", t2).stored_key_count);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kme_dec_keys() -> Result<(), String> {
+        let mut kme = MockKme::new(example_conf())?;
+        let keys = kme.enc_keys("sae1", "sae2", &request(2, None))?;
+        let key_ids = KeyIds::from_ids(&keys.ids());
+
+        // only the slave SAE can retrieve the keys
+        assert!(kme.dec_keys("sae3", "sae1", &key_ids).is_err());
+        assert!(kme.dec_keys("sae1", "sae2", &key_ids).is_err());
+
+        // a single unknown key fails the request
+        let mut ids = keys.ids();
+        ids.push("unknown".to_string());
+        assert!(kme
+            .dec_keys("sae2", "sae1", &KeyIds::from_ids(&ids))
+            .is_err());
+
+        assert_eq!(keys, kme.dec_keys("sae2", "sae1", &key_ids)?);
+
+        // keys cannot be retrieved twice
+        assert_eq!(
+            Some(400),
+            kme.dec_keys("sae2", "sae1", &key_ids)
+                .err()
+                .map(|x| x.status())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kme_delivered_keys_purged() -> Result<(), String> {
+        let mut kme = MockKme::new(KmeConf {
+            delivered_key_ttl: Duration::from_secs(10),
+            max_delivered_keys: 3,
+            ..example_conf()
+        })?;
+        let t0 = Instant::now();
+
+        // the oldest keys are discarded beyond the maximum number
+        let first = kme.enc_keys_at("sae1", "sae2", &request(2, None), t0)?;
+        let second = kme.enc_keys_at("sae1", "sae2", &request(2, None), t0)?;
+        assert_eq!(3, kme.delivered_keys());
+        let first_ids = first.ids();
+        assert!(kme
+            .dec_keys_at("sae2", "sae1", &KeyIds::from_ids(&first_ids[..1]), t0)
+            .is_err());
+        kme.dec_keys_at("sae2", "sae1", &KeyIds::from_ids(&first_ids[1..]), t0)?;
+        assert_eq!(2, kme.delivered_keys());
+
+        // the keys expire if not retrieved in time
+        let t1 = t0 + Duration::from_secs(5);
+        let third = kme.enc_keys_at("sae1", "sae2", &request(1, None), t1)?;
+        assert_eq!(3, kme.delivered_keys());
+        let t2 = t0 + Duration::from_secs(10);
+        assert!(kme
+            .dec_keys_at("sae2", "sae1", &KeyIds::from_ids(&second.ids()), t2)
+            .is_err());
+        assert_eq!(1, kme.delivered_keys());
+        assert_eq!(
+            third,
+            kme.dec_keys_at("sae2", "sae1", &KeyIds::from_ids(&third.ids()), t2)?
+        );
+        assert_eq!(0, kme.delivered_keys());
+
+        // invalid maximum
+        assert!(MockKme::new(KmeConf {
+            max_delivered_keys: 0,
+            ..example_conf()
+        })
+        .is_err());
+
+        Ok(())
+    }
+}
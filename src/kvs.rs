@@ -0,0 +1,609 @@
+//! Key-Value Store (KVS) keeping the run-time configuration of the MEC system.
+//!
+//! The data model and the admin/telemetry REST interface are described in docs/kvs.md.
+
+#![allow(non_snake_case)]
+
+use crate::error::LcmpError;
+use crate::messages::{AppContext, AppInfoList, LocationConstraints, Validate};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+
+/// Platform types supported.
+pub const PLATFORM_TYPES: [&str; 1] = ["openwhisk"];
+
+/// Return an error if the platform type is not supported.
+fn check_platform_type(platform_type: &str) -> Result<(), String> {
+    match PLATFORM_TYPES.contains(&platform_type) {
+        true => Ok(()),
+        false => Err(format!("unsupported platform type: {}", platform_type)),
+    }
+}
+
+/// Element of the platforms array in the add app command.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AppPlatform {
+    /// One of the platform types supported, e.g., openwhisk.
+    #[serde(rename = "type")]
+    pub platformType: String,
+    /// Names of the platforms where the app cannot be deployed.
+    #[serde(default)]
+    pub blacklist: Vec<String>,
+    /// Names of the platforms where the app can be deployed.
+    /// If empty, then the app can be deployed on any platform not in the blacklist.
+    #[serde(default)]
+    pub whitelist: Vec<String>,
+    /// Data needed to deploy this app on this platform, depending on the platform type.
+    pub deployInfo: serde_json::Value,
+}
+
+/// Body of the add app command.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct App {
+    /// Application information, as in ETSI GS MEC 016 ApplicationList.
+    pub appInfo: AppInfoList,
+    /// Where the app can be deployed, with at least one element.
+    pub platforms: Vec<AppPlatform>,
+}
+
+/// Body of the add platform command.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Platform {
+    /// Name of the platform, unique in the system.
+    pub name: String,
+    /// One of the platform types supported, e.g., openwhisk.
+    #[serde(rename = "type")]
+    pub platformType: String,
+    /// Credentials to deploy apps on this platform.
+    pub credentials: String,
+    /// End-point to configure this platform, e.g., to deploy or delete apps.
+    pub endpointMgmt: String,
+    /// End-point of the platform returned to the device apps in the referenceURI.
+    pub endpointApps: String,
+    /// KME from which the key availability of this platform is retrieved.
+    /// If absent, the key availability is only set via the telemetry interface.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qkd: Option<PlatformQkd>,
+    /// Location of the platform, matched against the appLocation requested
+    /// by the device apps. If absent, the platform is not selected for
+    /// the contexts requesting a location.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<LocationConstraints>,
+}
+
+/// Configuration of the ETSI GS QKD 014 KME associated with a platform.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct PlatformQkd {
+    /// Base URL of the KME REST API, e.g., https://kme:8443
+    pub kmeEndpoint: String,
+    /// SAE ID of the slave SAE whose keys shared with the platform are counted.
+    pub slaveSaeId: String,
+    /// SAE ID of the platform, i.e., the master SAE, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub masterSaeId: Option<String>,
+    /// Base URL of the KME REST API of the slave SAE, on the device apps' side, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slaveKmeEndpoint: Option<String>,
+    /// URL of the platform where the keys of the key pairs are delivered
+    /// to the master SAE, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyDeliveryEndpoint: Option<String>,
+}
+
+impl PlatformQkd {
+    /// Return true if key pairs can be obtained for the device apps served
+    /// by the platform, i.e., if masterSaeId, slaveKmeEndpoint, and
+    /// keyDeliveryEndpoint are all set.
+    pub fn key_pairs_enabled(&self) -> bool {
+        self.masterSaeId.is_some()
+            && self.slaveKmeEndpoint.is_some()
+            && self.keyDeliveryEndpoint.is_some()
+    }
+}
+
+/// Body of the telemetry commands.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TelemetryValue {
+    /// Normalized value in [0,1].
+    pub value: f64,
+}
+
+impl App {
+    /// Return the key that uniquely identifies this app.
+    pub fn key(&self) -> String {
+        self.appInfo.app_key()
+    }
+
+    /// Return the first element of the platforms of the app that allows its
+    /// deployment on the platform, i.e., with the same platform type and whose
+    /// blacklist/whitelist allow the platform name, if any.
+    pub fn app_platform(&self, platform: &Platform) -> Option<&AppPlatform> {
+        self.platforms.iter().find(|x| {
+            x.platformType == platform.platformType
+                && !x.blacklist.contains(&platform.name)
+                && (x.whitelist.is_empty() || x.whitelist.contains(&platform.name))
+        })
+    }
+
+    /// Return true if the app can be deployed on the platform, i.e., the platform
+    /// type is supported by the app and allowed by its blacklist/whitelist.
+    pub fn allows(&self, platform: &Platform) -> bool {
+        self.app_platform(platform).is_some()
+    }
+}
+
+impl Validate for AppPlatform {
+    fn validate(&self) -> Result<(), String> {
+        check_platform_type(&self.platformType)
+    }
+}
+
+impl Validate for App {
+    fn validate(&self) -> Result<(), String> {
+        self.appInfo.validate()?;
+        if self.platforms.is_empty() {
+            return Err("at least one platform must be specified".to_string());
+        }
+        for platform in &self.platforms {
+            platform.validate()?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for Platform {
+    fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("empty platform name".to_string());
+        }
+        if self.endpointMgmt.is_empty() {
+            return Err("empty endpointMgmt".to_string());
+        }
+        if self.endpointApps.is_empty() {
+            return Err("empty endpointApps".to_string());
+        }
+        if let Some(qkd) = &self.qkd {
+            if qkd.kmeEndpoint.is_empty() {
+                return Err("empty qkd.kmeEndpoint".to_string());
+            }
+            if qkd.slaveSaeId.is_empty() {
+                return Err("empty qkd.slaveSaeId".to_string());
+            }
+            if qkd.masterSaeId.as_deref() == Some("") {
+                return Err("empty qkd.masterSaeId".to_string());
+            }
+            if qkd.slaveKmeEndpoint.as_deref() == Some("") {
+                return Err("empty qkd.slaveKmeEndpoint".to_string());
+            }
+            if qkd.keyDeliveryEndpoint.as_deref() == Some("") {
+                return Err("empty qkd.keyDeliveryEndpoint".to_string());
+            }
+            if qkd.slaveKmeEndpoint.is_some() != qkd.keyDeliveryEndpoint.is_some()
+                || (qkd.slaveKmeEndpoint.is_some() && qkd.masterSaeId.is_none())
+            {
+                return Err(
+                    "qkd.slaveKmeEndpoint and qkd.keyDeliveryEndpoint require each other and qkd.masterSaeId"
+                        .to_string(),
+                );
+            }
+        }
+        if let Some(location) = &self.location {
+            location.validate()?;
+        }
+        check_platform_type(&self.platformType)
+    }
+}
+
+impl Validate for TelemetryValue {
+    fn validate(&self) -> Result<(), String> {
+        match (0.0..=1.0).contains(&self.value) {
+            true => Ok(()),
+            false => Err(format!(
+                "telemetry value out of range [0,1]: {}",
+                self.value
+            )),
+        }
+    }
+}
+
+impl Display for App {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let platforms: Vec<String> = self
+            .platforms
+            .iter()
+            .map(|x| x.platformType.clone())
+            .collect();
+        write!(
+            f,
+            "appInfo: {}, platforms: {}",
+            self.appInfo,
+            platforms.join(",")
+        )
+    }
+}
+
+impl Display for Platform {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "name: {}, type: {}, endpointMgmt: {}, endpointApps: {}",
+            self.name, self.platformType, self.endpointMgmt, self.endpointApps
+        )
+    }
+}
+
+/// In-memory KVS storage.
+#[derive(Default)]
+pub struct Kvs {
+    /// Applications, key: apps.
+    apps: Vec<App>,
+    /// Platforms, key: platforms.
+    platforms: Vec<Platform>,
+    /// Application contexts assigned to each platform, key: contexts:{platformName}.
+    contexts: HashMap<String, Vec<AppContext>>,
+    /// Applications' operations counter, key: apps:operations.
+    apps_operations: u64,
+    /// Platforms' operations counter, key: platforms:operations.
+    platforms_operations: u64,
+    /// Contexts' operations counter, key: contexts:operations.
+    contexts_operations: u64,
+    /// Last load reported for the platform at the same index, key: telemetry:platforms:load.
+    telemetry_load: Vec<f64>,
+    /// Last key availability reported for the platform at the same index, key: telemetry:platforms:key.
+    telemetry_key: Vec<f64>,
+}
+
+/// KVS shared among the components of the MEC system.
+pub type SharedKvs = Arc<Mutex<Kvs>>;
+
+/// Default load assigned to a platform until the first telemetry report.
+pub(crate) const DEFAULT_LOAD: f64 = 0.0;
+/// Default key availability assigned to a platform until the first telemetry report.
+pub(crate) const DEFAULT_KEY: f64 = 1.0;
+
+impl Kvs {
+    /// Create an empty KVS.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty KVS that can be shared.
+    pub fn shared() -> SharedKvs {
+        Arc::new(Mutex::new(Self::new()))
+    }
+
+    /// Add a new app, which must be valid and not already present.
+    pub fn add_app(&mut self, app: App) -> Result<(), LcmpError> {
+        app.validate().map_err(LcmpError::InvalidRequest)?;
+        let key = app.key();
+        if self.apps.iter().any(|x| x.key() == key) {
+            return Err(LcmpError::Conflict(format!("app already present: {}", key)));
+        }
+        self.apps.push(app);
+        self.apps_operations += 1;
+        Ok(())
+    }
+
+    /// Delete an existing app.
+    pub fn del_app(&mut self, appkey: &str) -> Result<(), LcmpError> {
+        match self.apps.iter().position(|x| x.key() == appkey) {
+            Some(ndx) => {
+                self.apps.remove(ndx);
+                self.apps_operations += 1;
+                Ok(())
+            }
+            None => Err(LcmpError::NotFound(format!("app not found: {}", appkey))),
+        }
+    }
+
+    /// Add a new platform, which must be valid and not already present.
+    pub fn add_platform(&mut self, platform: Platform) -> Result<(), LcmpError> {
+        platform.validate().map_err(LcmpError::InvalidRequest)?;
+        if self.platform_index(&platform.name).is_some() {
+            return Err(LcmpError::Conflict(format!(
+                "platform already present: {}",
+                platform.name
+            )));
+        }
+        self.platforms.push(platform);
+        self.telemetry_load.push(DEFAULT_LOAD);
+        self.telemetry_key.push(DEFAULT_KEY);
+        self.platforms_operations += 1;
+        Ok(())
+    }
+
+    /// Delete an existing platform, together with its telemetry data.
+    pub fn del_platform(&mut self, name: &str) -> Result<(), LcmpError> {
+        match self.platform_index(name) {
+            Some(ndx) => {
+                self.platforms.remove(ndx);
+                self.telemetry_load.remove(ndx);
+                self.telemetry_key.remove(ndx);
+                self.platforms_operations += 1;
+                Ok(())
+            }
+            None => Err(LcmpError::NotFound(format!("platform not found: {}", name))),
+        }
+    }
+
+    /// Set the last load value reported by a platform.
+    pub fn set_load(&mut self, name: &str, value: &TelemetryValue) -> Result<(), LcmpError> {
+        value.validate().map_err(LcmpError::InvalidRequest)?;
+        match self.platform_index(name) {
+            Some(ndx) => {
+                self.telemetry_load[ndx] = value.value;
+                Ok(())
+            }
+            None => Err(LcmpError::NotFound(format!("platform not found: {}", name))),
+        }
+    }
+
+    /// Set the last key availability value reported for a platform.
+    pub fn set_key(&mut self, name: &str, value: &TelemetryValue) -> Result<(), LcmpError> {
+        value.validate().map_err(LcmpError::InvalidRequest)?;
+        match self.platform_index(name) {
+            Some(ndx) => {
+                self.telemetry_key[ndx] = value.value;
+                Ok(())
+            }
+            None => Err(LcmpError::NotFound(format!("platform not found: {}", name))),
+        }
+    }
+
+    /// Add an application context assigned to a platform.
+    pub fn add_context(&mut self, platform: &str, app_context: AppContext) {
+        self.contexts
+            .entry(platform.to_string())
+            .or_default()
+            .push(app_context);
+        self.contexts_operations += 1;
+    }
+
+    /// Remove an application context from a platform.
+    pub fn del_context(&mut self, platform: &str, context_id: &str) -> Result<(), LcmpError> {
+        if let Some(contexts) = self.contexts.get_mut(platform) {
+            if let Some(ndx) = contexts
+                .iter()
+                .position(|x| x.contextId.as_deref() == Some(context_id))
+            {
+                contexts.remove(ndx);
+                if contexts.is_empty() {
+                    self.contexts.remove(platform);
+                }
+                self.contexts_operations += 1;
+                return Ok(());
+            }
+        }
+        Err(LcmpError::NotFound(format!(
+            "context ID not found on platform {}: {}",
+            platform, context_id
+        )))
+    }
+
+    /// Replace an application context assigned to a platform with an updated version.
+    pub fn update_context(
+        &mut self,
+        platform: &str,
+        app_context: AppContext,
+    ) -> Result<(), LcmpError> {
+        if let Some(contexts) = self.contexts.get_mut(platform) {
+            if let Some(x) = contexts
+                .iter_mut()
+                .find(|x| x.contextId.is_some() && x.contextId == app_context.contextId)
+            {
+                *x = app_context;
+                self.contexts_operations += 1;
+                return Ok(());
+            }
+        }
+        Err(LcmpError::NotFound(format!(
+            "context ID not found on platform {}: {}",
+            platform,
+            app_context.contextId.unwrap_or_default()
+        )))
+    }
+
+    /// Return all the apps.
+    pub fn apps(&self) -> &Vec<App> {
+        &self.apps
+    }
+
+    /// Return all the platforms.
+    pub fn platforms(&self) -> &Vec<Platform> {
+        &self.platforms
+    }
+
+    /// Return the platform with given name, if any.
+    pub fn platform(&self, name: &str) -> Option<&Platform> {
+        self.platforms.iter().find(|x| x.name == name)
+    }
+
+    /// Return the application contexts assigned to a platform.
+    pub fn contexts(&self, platform: &str) -> &[AppContext] {
+        match self.contexts.get(platform) {
+            Some(x) => x.as_slice(),
+            None => &[],
+        }
+    }
+
+    /// Return the last load value reported by a platform.
+    pub fn load(&self, name: &str) -> Option<f64> {
+        self.platform_index(name)
+            .map(|ndx| self.telemetry_load[ndx])
+    }
+
+    /// Return the last key availability value reported for a platform.
+    pub fn key(&self, name: &str) -> Option<f64> {
+        self.platform_index(name).map(|ndx| self.telemetry_key[ndx])
+    }
+
+    /// Return the applications' operations counter.
+    pub fn apps_operations(&self) -> u64 {
+        self.apps_operations
+    }
+
+    /// Return the platforms' operations counter.
+    pub fn platforms_operations(&self) -> u64 {
+        self.platforms_operations
+    }
+
+    /// Return the contexts' operations counter.
+    pub fn contexts_operations(&self) -> u64 {
+        self.contexts_operations
+    }
+
+    fn platform_index(&self, name: &str) -> Option<usize> {
+        self.platforms.iter().position(|x| x.name == name)
+    }
+}
+
+#[cfg(test)]
+impl App {
+    /// Return an app with the given appDId that can be deployed on any
+    /// openwhisk platform, for testing.
+    pub(crate) fn example(app_d_id: &str) -> Self {
+        serde_json::from_str(&format!(
+            r#"{{
+            "appInfo": {{
+                "appDId": "{}",
+                "appName": "my_app_name",
+                "appProvider": "my_app_provider",
+                "appSoftVersion": "1.0",
+                "appDVersion": "",
+                "appDescription": "",
+                "appLocation": []
+            }},
+            "platforms": [ {{ "type": "openwhisk", "deployInfo": {{ "name": "my_action" }} }} ]
+        }}"#,
+            app_d_id
+        ))
+        .expect("could not deserialize app")
+    }
+}
+
+#[cfg(test)]
+impl Platform {
+    /// Return an openwhisk platform with the given name, without KME and
+    /// location, for testing.
+    pub(crate) fn example(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            platformType: "openwhisk".to_string(),
+            credentials: "".to_string(),
+            endpointMgmt: format!("http://{}/mgmt", name),
+            endpointApps: format!("http://{}/apps", name),
+            qkd: None,
+            location: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kvs_messages() {
+        let mut app = App::example("id");
+        assert_eq!(Ok(()), app.validate());
+        assert!(app.platforms[0].whitelist.is_empty());
+        assert_eq!("idmy_app_namemy_app_provider1.0", app.key());
+        println!("{}", app);
+
+        app.platforms[0].platformType = "unknown".to_string();
+        assert!(app.validate().is_err());
+
+        app.platforms.clear();
+        assert!(app.validate().is_err());
+
+        let mut platform = Platform::example("p1");
+        assert_eq!(Ok(()), platform.validate());
+        println!("{}", platform);
+
+        platform.endpointApps.clear();
+        assert!(platform.validate().is_err());
+
+        assert!(TelemetryValue { value: 0.5 }.validate().is_ok());
+        assert!(TelemetryValue { value: 1.5 }.validate().is_err());
+        assert!(TelemetryValue { value: -0.1 }.validate().is_err());
+    }
+
+    #[test]
+    fn test_kvs_apps() -> Result<(), String> {
+        let mut kvs = Kvs::new();
+        assert!(kvs.apps().is_empty());
+
+        kvs.add_app(App::example("id1"))?;
+        kvs.add_app(App::example("id2"))?;
+        assert!(kvs.add_app(App::example("id1")).is_err());
+        assert_eq!(2, kvs.apps().len());
+        assert_eq!(2, kvs.apps_operations());
+
+        assert!(kvs.del_app("non-existing").is_err());
+        kvs.del_app(App::example("id1").key().as_str())?;
+        assert_eq!(1, kvs.apps().len());
+        assert_eq!(3, kvs.apps_operations());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kvs_platforms() -> Result<(), String> {
+        let mut kvs = Kvs::new();
+
+        kvs.add_platform(Platform::example("p1"))?;
+        kvs.add_platform(Platform::example("p2"))?;
+        assert!(kvs.add_platform(Platform::example("p1")).is_err());
+        assert_eq!(2, kvs.platforms().len());
+        assert_eq!(2, kvs.platforms_operations());
+
+        assert_eq!(Some(DEFAULT_LOAD), kvs.load("p1"));
+        assert_eq!(Some(DEFAULT_KEY), kvs.key("p1"));
+        kvs.set_load("p1", &TelemetryValue { value: 0.3 })?;
+        kvs.set_key("p2", &TelemetryValue { value: 0.7 })?;
+        assert!(kvs.set_load("p3", &TelemetryValue { value: 0.3 }).is_err());
+        assert!(kvs.set_key("p1", &TelemetryValue { value: 2.0 }).is_err());
+        assert_eq!(Some(0.3), kvs.load("p1"));
+        assert_eq!(Some(0.7), kvs.key("p2"));
+
+        kvs.del_platform("p1")?;
+        assert!(kvs.del_platform("p1").is_err());
+        assert!(kvs.load("p1").is_none());
+        assert_eq!(Some(0.7), kvs.key("p2"));
+        assert_eq!(3, kvs.platforms_operations());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kvs_contexts() -> Result<(), String> {
+        let mut kvs = Kvs::new();
+
+        let mut a = AppContext::request_from_name_provider("name", "provider");
+        a.contextId = Some("ctx1".to_string());
+        kvs.add_context("p1", a.clone());
+        a.contextId = Some("ctx2".to_string());
+        kvs.add_context("p1", a);
+        assert_eq!(2, kvs.contexts("p1").len());
+        assert!(kvs.contexts("p2").is_empty());
+
+        a = kvs.contexts("p1")[1].clone();
+        a.callbackReference = Some("callback".to_string());
+        kvs.update_context("p1", a.clone())?;
+        assert!(kvs.update_context("p2", a).is_err());
+        assert_eq!(
+            Some("callback".to_string()),
+            kvs.contexts("p1")[1].callbackReference
+        );
+
+        assert!(kvs.del_context("p2", "ctx1").is_err());
+        kvs.del_context("p1", "ctx1")?;
+        assert!(kvs.del_context("p1", "ctx1").is_err());
+        assert_eq!(1, kvs.contexts("p1").len());
+        assert_eq!(4, kvs.contexts_operations());
+
+        Ok(())
+    }
+}
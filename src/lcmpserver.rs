@@ -2,11 +2,98 @@
 
 use crate::appcontextserver::{build_app_context_server, AppContextServer};
 use crate::applicationlistserver::{build_application_list_server, ApplicationListServer};
+use crate::auth::Identity;
+use crate::error::LcmpError;
+use crate::faas::Completion;
+use crate::kvs::{App, Kvs, Platform, PlatformQkd, SharedKvs, TelemetryValue, DEFAULT_KEY};
+use crate::lease::Leases;
+use crate::meo::OptimizationConf;
+use crate::messages::{
+    AppContext, ApplicationContextUpdateNotification, ApplicationLocationAvailabilityNotification,
+};
+use crate::notification::{HttpNotifier, Notifier, RetryPolicy};
+use crate::qkd014::{self, KeyRequest};
+use crate::rediskvs::{split_url, RedisKvs};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Timeout of the requests to the KMEs.
+pub const KME_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Configuration of the admission control of the new contexts based on the
+/// key availability of the platforms.
+#[derive(Clone, Debug)]
+pub struct KeyAdmissionConf {
+    /// New contexts are rejected if the key availability of the platform
+    /// hosting the user application instance is lower than this, in [0,1].
+    pub min_key: f64,
+    /// Number of seconds after which the device apps can retry.
+    pub retry_after: u64,
+}
+
+/// Creation of a new context started by LcmpServer::start_context, whose
+/// operations not needing the LCMP server are performed by run, without
+/// holding its lock, and which is concluded by LcmpServer::finish_context.
+pub struct PendingContext {
+    /// Outcome of the deployment of the app, if in progress.
+    deployment: Option<Completion>,
+    /// QKD configuration of the platforms of the user application instances,
+    /// in the same order, if configured for key pairs.
+    key_pairs: Vec<Option<PlatformQkd>>,
+    /// IDs of the QKD keys obtained by run, in the same order.
+    key_ids: Vec<Option<Vec<String>>>,
+    /// Outcome of the operations performed by run.
+    result: Result<(), LcmpError>,
+}
+
+impl PendingContext {
+    /// Wait for the deployment of the app, if in progress, then obtain the
+    /// QKD key pairs from the KMEs, if needed.
+    pub fn run(&mut self) {
+        if let Some(deployment) = &self.deployment {
+            if let Err(err) = deployment.wait() {
+                self.result = Err(LcmpError::BackendUnavailable(err));
+            }
+        }
+        if self.result.is_err() {
+            return;
+        }
+        for qkd in &self.key_pairs {
+            let ids = match qkd {
+                Some(qkd) => {
+                    match qkd014::fetch_key_pair(qkd, &KeyRequest::default(), KME_TIMEOUT) {
+                        Ok(ids) => Some(ids),
+                        Err(err) => {
+                            self.result = Err(LcmpError::BackendUnavailable(format!(
+                                "could not obtain a QKD key pair: {}",
+                                err
+                            )));
+                            return;
+                        }
+                    }
+                }
+                None => None,
+            };
+            self.key_ids.push(ids);
+        }
+    }
+}
 
 /// LCMP server.
 pub struct LcmpServer {
     application_list_server: Box<dyn ApplicationListServer + Send + Sync>,
     app_context_server: Box<dyn AppContextServer + Send + Sync>,
+    kvs: SharedKvs,
+    /// KVS in a Redis-compatible server, used instead of kvs for the admin and
+    /// telemetry operations if the servers above are of type redis.
+    redis: Option<RedisKvs>,
+    notifier: Box<dyn Notifier + Send + Sync>,
+    /// Leases of the active contexts, if enabled.
+    leases: Option<Leases>,
+    /// Admission control based on the key availability, if enabled.
+    key_admission: Option<KeyAdmissionConf>,
 }
 
 impl LcmpServer {
@@ -18,27 +105,1266 @@ impl LcmpServer {
         self.app_context_server.as_mut()
     }
 
+    pub fn kvs(&self) -> SharedKvs {
+        self.kvs.clone()
+    }
+
+    /// Enable the leases of the active contexts with the given time-to-live:
+    /// a context is deleted if the device app does not create, retrieve, or
+    /// update it for longer than that.
+    pub fn with_context_ttl(mut self, ttl: Duration) -> Self {
+        self.leases = Some(Leases::new(ttl));
+        self
+    }
+
+    /// Enable the admission control of the new contexts: a context is rejected
+    /// if the key availability of the platform hosting the user application
+    /// instance, in the KVS, is lower than the minimum.
+    pub fn with_key_admission(mut self, conf: KeyAdmissionConf) -> Self {
+        self.key_admission = Some(conf);
+        self
+    }
+
+    /// Create a new context owned by the caller, with its lease if enabled.
+    /// The owner is stored by the AppContext server, but it is never included
+    /// in the contexts returned by the LCMP server, which are sent to the device apps.
+    /// The context is not created if it is not admitted due to the key availability
+    /// of the platform of the user application instance, and it is deleted if the
+    /// app cannot be deployed, or if the platform is configured for key pairs and
+    /// they cannot be obtained.
+    /// This is the same as start_context, PendingContext::run, and finish_context.
+    pub fn new_context(
+        &mut self,
+        identity: &Identity,
+        app_context: &mut AppContext,
+    ) -> Result<(), LcmpError> {
+        let mut pending = self.start_context(identity, app_context)?;
+        pending.run();
+        self.finish_context(pending, app_context)
+    }
+
+    /// Start the creation of a new context owned by the caller.
+    pub fn start_context(
+        &mut self,
+        identity: &Identity,
+        app_context: &mut AppContext,
+    ) -> Result<PendingContext, LcmpError> {
+        app_context.owner = Some(identity.subject.clone());
+        let admit = self.admission()?;
+        let deployment = self.app_context_server.start_context(app_context, &admit)?;
+        let (key_pairs, result) = match self.key_pairs(app_context) {
+            Ok(x) => (x, Ok(())),
+            Err(err) => (vec![], Err(err)),
+        };
+        Ok(PendingContext {
+            deployment,
+            key_pairs,
+            key_ids: vec![],
+            result,
+        })
+    }
+
+    /// Conclude the creation of a new context, see new_context.
+    pub fn finish_context(
+        &mut self,
+        pending: PendingContext,
+        app_context: &mut AppContext,
+    ) -> Result<(), LcmpError> {
+        let res = pending
+            .result
+            .and_then(|_| self.assign_key_ids(app_context, &pending.key_ids))
+            .and_then(|_| self.renew(app_context));
+        if let Err(err) = res {
+            if let Some(context_id) = &app_context.contextId {
+                if let Err(err) = self.app_context_server.del_context(context_id) {
+                    warn!("could not delete context {}: {}", context_id, err);
+                }
+            }
+            return Err(err);
+        }
+        app_context.owner = None;
+        Ok(())
+    }
+
+    /// Return the admission control of the new contexts, as a function of the
+    /// reference URI of the platform hosting the user application instance:
+    /// if enabled, a context is rejected if the key availability in the KVS of
+    /// the platform is lower than the minimum, for the platforms with a KME.
+    fn admission(&self) -> Result<impl Fn(&str) -> Result<(), LcmpError>, LcmpError> {
+        let conf = self.key_admission.clone();
+        let mut keys = HashMap::new();
+        if conf.is_some() {
+            for platform in self.platforms()?.into_iter().filter(|x| x.qkd.is_some()) {
+                let value = match &self.redis {
+                    Some(redis) => redis.key(&platform.name)?,
+                    None => self.kvs.lock().unwrap().key(&platform.name),
+                };
+                keys.insert(platform.endpointApps, value.unwrap_or(DEFAULT_KEY));
+            }
+        }
+        Ok(
+            move |reference_uri: &str| match (&conf, keys.get(reference_uri)) {
+                (Some(conf), Some(value)) if *value < conf.min_key => {
+                    Err(LcmpError::KeysUnavailable(
+                        format!(
+                            "key availability too low: {:.3} < {:.3}",
+                            value, conf.min_key
+                        ),
+                        conf.retry_after,
+                    ))
+                }
+                _ => Ok(()),
+            },
+        )
+    }
+
+    /// Return the QKD configuration of the platforms hosting the user
+    /// application instances of a context, found by their reference URIs,
+    /// if configured for key pairs.
+    fn key_pairs(&self, app_context: &AppContext) -> Result<Vec<Option<PlatformQkd>>, LcmpError> {
+        let platforms = self.platforms()?;
+        Ok(app_context
+            .appInfo
+            .userAppInstanceInfo
+            .iter()
+            .map(|info| {
+                platforms
+                    .iter()
+                    .find(|x| info.referenceURI.as_ref() == Some(&x.endpointApps))
+                    .and_then(|x| x.qkd.clone())
+                    .filter(|x| x.key_pairs_enabled())
+            })
+            .collect())
+    }
+
+    /// Store the IDs of the QKD keys obtained for the user application
+    /// instances of a new context, if any, also in the userAppInstanceInfo.
+    fn assign_key_ids(
+        &mut self,
+        app_context: &mut AppContext,
+        key_ids: &[Option<Vec<String>>],
+    ) -> Result<(), LcmpError> {
+        if let (Some(context_id), true) =
+            (&app_context.contextId, key_ids.iter().any(|x| x.is_some()))
+        {
+            self.app_context_server
+                .set_qkd_key_ids(context_id, key_ids)?;
+            app_context.assign_qkd_key_ids(key_ids);
+        }
+        Ok(())
+    }
+
+    /// Retrieve an active context, renewing its lease if enabled.
+    pub fn get_context(
+        &mut self,
+        identity: &Identity,
+        context_id: &str,
+    ) -> Result<AppContext, LcmpError> {
+        self.check_owner(identity, context_id)?;
+        let mut app_context = self.app_context_server.get_context(context_id)?.clone();
+        self.renew(&mut app_context)?;
+        app_context.owner = None;
+        Ok(app_context)
+    }
+
+    /// Update an active context, renewing its lease if enabled.
+    pub fn update_context(
+        &mut self,
+        identity: &Identity,
+        app_context: &mut AppContext,
+    ) -> Result<(), LcmpError> {
+        if let Some(context_id) = &app_context.contextId {
+            self.check_owner(identity, context_id)?;
+        }
+        self.app_context_server.update_context(app_context)?;
+        app_context.owner = None;
+        self.renew(app_context)
+    }
+
+    /// Delete an active context.
+    pub fn del_context(&mut self, identity: &Identity, context_id: &str) -> Result<(), LcmpError> {
+        self.check_owner(identity, context_id)?;
+        self.app_context_server.del_context(context_id)?;
+        Ok(())
+    }
+
+    /// Return the contexts among those given that the caller can access,
+    /// i.e., all of them for an admin, otherwise only those it owns.
+    pub fn retain_owned(
+        &mut self,
+        identity: &Identity,
+        context_ids: Vec<String>,
+    ) -> Result<Vec<String>, LcmpError> {
+        if identity.is_admin() {
+            return Ok(context_ids);
+        }
+        let owned = self
+            .app_context_server
+            .contexts_by_owner(&identity.subject)?;
+        Ok(context_ids
+            .into_iter()
+            .filter(|x| owned.contains(x))
+            .collect())
+    }
+
+    /// Return an error if the caller is neither the owner of an active context
+    /// nor an admin, or if the context does not exist.
+    /// The contexts without an owner, e.g., created before the owners were
+    /// recorded and restored from a persistent storage, are accessible only
+    /// by the admins.
+    fn check_owner(&mut self, identity: &Identity, context_id: &str) -> Result<(), LcmpError> {
+        let app_context = self.app_context_server.get_context(context_id)?;
+        if identity.is_admin() || app_context.owner.as_deref() == Some(identity.subject.as_str()) {
+            return Ok(());
+        }
+        Err(LcmpError::Forbidden(format!(
+            "context ID {} owned by another device app",
+            context_id
+        )))
+    }
+
+    /// Renew the lease of a context, if enabled, and set its expiration time,
+    /// which is also stored in the AppContext server.
+    fn renew(&mut self, app_context: &mut AppContext) -> Result<(), LcmpError> {
+        if let (Some(leases), Some(context_id)) = (&self.leases, &app_context.contextId) {
+            let expires_at = leases.renew();
+            self.app_context_server
+                .set_expires_at(context_id, Some(expires_at))?;
+            app_context.expiresAt = Some(expires_at);
+        }
+        Ok(())
+    }
+
+    /// Delete the contexts whose lease has expired, which also releases the
+    /// resources allocated on the platforms, if any.
+    /// The expiration time is that stored in the AppContext server, hence the
+    /// leases renewed by other LCMP instances sharing it are honored, while a
+    /// new lease is created for the active contexts without one, e.g., those
+    /// created before the leases were enabled.
+    /// Return the IDs of the contexts deleted.
+    pub fn expire_contexts(&mut self) -> Result<Vec<String>, LcmpError> {
+        let leases = match &self.leases {
+            Some(x) => x,
+            None => return Ok(vec![]),
+        };
+        let mut deleted = vec![];
+        for context_id in self.app_context_server.list_contexts()? {
+            let expires_at = match self.app_context_server.get_context(&context_id) {
+                Ok(x) => x.expiresAt,
+                // deleted in the meanwhile
+                Err(_) => continue,
+            };
+            let res = match expires_at {
+                None => self
+                    .app_context_server
+                    .set_expires_at(&context_id, Some(leases.renew())),
+                Some(x) if leases.expired(&x) => {
+                    self.app_context_server.del_context(&context_id).map(|_| {
+                        info!("context {} expired", context_id);
+                        deleted.push(context_id.clone());
+                    })
+                }
+                Some(_) => Ok(()),
+            };
+            if let Err(err) = res {
+                warn!("could not expire context {}: {}", context_id, err);
+            }
+        }
+        deleted.sort();
+        Ok(deleted)
+    }
+
+    /// Change the reference URI of an active context and, if changed, notify
+    /// the device app via its callbackReference, if any.
+    pub fn update_reference_uri(
+        &mut self,
+        context_id: &str,
+        reference_uri: &str,
+    ) -> Result<(), LcmpError> {
+        if self
+            .app_context_server
+            .update_reference_uri(context_id, reference_uri)?
+        {
+            self.notify_context_update(context_id)?;
+        }
+        Ok(())
+    }
+
+    /// Send an ApplicationContextUpdateNotification with the current content
+    /// of an active context to its callbackReference, if any.
+    pub fn notify_context_update(&mut self, context_id: &str) -> Result<(), LcmpError> {
+        let app_context = self.app_context_server.get_context(context_id)?;
+        if let Some(callback_reference) = &app_context.callbackReference {
+            let notification = ApplicationContextUpdateNotification::from_app_context(app_context);
+            info!("notifying {}", notification);
+            self.notifier
+                .notify(
+                    callback_reference,
+                    serde_json::to_string(&notification).unwrap_or_default(),
+                )
+                .map_err(|x| {
+                    LcmpError::Internal(format!(
+                        "could not notify the update of context {}: {}",
+                        context_id, x
+                    ))
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Notify the update of the given contexts, see notify_context_update,
+    /// logging the failures without stopping at the first one.
+    fn notify_context_updates(&mut self, context_ids: &[String]) {
+        for context_id in context_ids {
+            if let Err(err) = self.notify_context_update(context_id) {
+                warn!("{}", err);
+            }
+        }
+    }
+
+    /// Return the apps in the KVS.
+    fn apps(&self) -> Result<Vec<App>, LcmpError> {
+        match &self.redis {
+            Some(redis) => redis.apps(),
+            None => Ok(self.kvs.lock().unwrap().apps().clone()),
+        }
+    }
+
+    /// Return the platforms in the KVS.
+    fn platforms(&self) -> Result<Vec<Platform>, LcmpError> {
+        match &self.redis {
+            Some(redis) => redis.platforms(),
+            None => Ok(self.kvs.lock().unwrap().platforms().clone()),
+        }
+    }
+
+    /// Add an app to the KVS.
+    pub fn add_app(&mut self, app: App) -> Result<(), LcmpError> {
+        match &self.redis {
+            Some(redis) => redis.add_app(app),
+            None => self.kvs.lock().unwrap().add_app(app),
+        }
+    }
+
+    /// Delete an app from the KVS.
+    pub fn del_app(&mut self, appkey: &str) -> Result<(), LcmpError> {
+        match &self.redis {
+            Some(redis) => redis.del_app(appkey),
+            None => self.kvs.lock().unwrap().del_app(appkey),
+        }
+    }
+
+    /// Set the last load value reported by a platform in the KVS.
+    pub fn set_load(&mut self, name: &str, value: &TelemetryValue) -> Result<(), LcmpError> {
+        match &self.redis {
+            Some(redis) => redis.set_load(name, value),
+            None => self.kvs.lock().unwrap().set_load(name, value),
+        }
+    }
+
+    /// Set the last key availability value reported for a platform in the KVS.
+    pub fn set_key(&mut self, name: &str, value: &TelemetryValue) -> Result<(), LcmpError> {
+        match &self.redis {
+            Some(redis) => redis.set_key(name, value),
+            None => self.kvs.lock().unwrap().set_key(name, value),
+        }
+    }
+
+    /// Add a platform to the KVS and notify its location, if any, to the
+    /// device apps that requested appLocationUpdates.
+    pub fn add_platform(&mut self, platform: Platform) -> Result<(), LcmpError> {
+        match &self.redis {
+            Some(redis) => redis.add_platform(platform.clone())?,
+            None => self.kvs.lock().unwrap().add_platform(platform.clone())?,
+        }
+        self.notify_location_availability(&platform)?;
+        Ok(())
+    }
+
+    /// Delete a platform from the KVS and migrate the active contexts assigned
+    /// to it, if any, notifying the device apps of the new reference URIs.
+    /// Return the number of contexts migrated, also if some notifications failed.
+    pub fn del_platform(&mut self, name: &str) -> Result<usize, LcmpError> {
+        match &self.redis {
+            Some(redis) => redis.del_platform(name)?,
+            None => self.kvs.lock().unwrap().del_platform(name)?,
+        }
+        let context_ids = self.app_context_server.evacuate_platform(name)?;
+        self.notify_context_updates(&context_ids);
+        Ok(context_ids.len())
+    }
+
+    /// Send an ApplicationLocationAvailabilityNotification with the location of
+    /// a platform to the callbackReference of the active contexts that requested
+    /// appLocationUpdates, provided that the location satisfies the one requested,
+    /// if any, and that the app can be deployed on the platform, if in the KVS.
+    /// Return the number of notifications sent.
+    pub fn notify_location_availability(
+        &mut self,
+        platform: &Platform,
+    ) -> Result<usize, LcmpError> {
+        let location = match &platform.location {
+            Some(x) => x,
+            None => return Ok(0),
+        };
+        let apps = self.apps()?;
+        let context_ids = self.app_context_server.filter_contexts(&|x| {
+            x.appLocationUpdates == Some(true)
+                && x.callbackReference.is_some()
+                && x.requested_location().is_none_or(|x| x.contains(location))
+                && apps
+                    .iter()
+                    .find(|a| a.appInfo.matches_context(&x.appInfo))
+                    .is_none_or(|a| a.allows(platform))
+        })?;
+        let mut sent = 0;
+        for context_id in &context_ids {
+            let app_context = self.app_context_server.get_context(context_id)?;
+            let notification = ApplicationLocationAvailabilityNotification::from_app_context(
+                app_context,
+                std::slice::from_ref(location),
+            );
+            info!("notifying {}", notification);
+            match self.notifier.notify(
+                app_context.callbackReference.as_deref().unwrap_or_default(),
+                serde_json::to_string(&notification).unwrap_or_default(),
+            ) {
+                Ok(_) => sent += 1,
+                Err(err) => warn!(
+                    "could not notify the location availability to context {}: {}",
+                    context_id, err
+                ),
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Perform a round of optimization of the active contexts and notify the
+    /// device apps of those whose reference URI has changed.
+    /// Return the number of contexts changed, also if some notifications failed.
+    pub fn optimize(&mut self, conf: &OptimizationConf) -> Result<usize, LcmpError> {
+        let context_ids = self.app_context_server.optimize(conf)?;
+        self.notify_context_updates(&context_ids);
+        Ok(context_ids.len())
+    }
+
+    /// Create a server with the given types of ApplicationList and AppContext
+    /// servers. If any of them is of type redis, then the admin and telemetry
+    /// operations are performed on the same Redis-compatible server, which
+    /// must be the same if both are of type redis.
     pub fn build(als_value: &str, acs_value: &str) -> Result<LcmpServer, String> {
+        let kvs = Kvs::shared();
+        let urls = [als_value, acs_value]
+            .iter()
+            .filter_map(|x| x.strip_prefix("redis;"))
+            .map(|x| split_url(x).map(|x| x.0))
+            .collect::<Result<Vec<String>, String>>()?;
+        if urls.iter().any(|x| *x != urls[0]) {
+            return Err(
+                "the ApplicationList and AppContext servers must use the same redis url"
+                    .to_string(),
+            );
+        }
+        let redis = match urls.first() {
+            Some(url) => Some(RedisKvs::new(url)?),
+            None => None,
+        };
         Ok(Self {
-            application_list_server: build_application_list_server(als_value)?,
-            app_context_server: build_app_context_server(acs_value)?,
+            application_list_server: build_application_list_server(als_value, &kvs)?,
+            app_context_server: build_app_context_server(acs_value, &kvs)?,
+            kvs,
+            redis,
+            notifier: Box::new(HttpNotifier::new(RetryPolicy::default())),
+            leases: None,
+            key_admission: None,
         })
     }
 }
 
+/// Update the key availability telemetry of all the platforms configured with
+/// a KME in the KVS of the LCMP server, i.e., in the Redis server if the
+/// servers are of type redis, querying the KMEs without holding its lock.
+pub fn update_key_telemetry(lcmp: &Mutex<LcmpServer>, timeout: Duration) -> Result<(), LcmpError> {
+    let platforms = lcmp.lock().unwrap().platforms()?;
+    for (name, value) in qkd014::key_telemetry(&platforms, timeout) {
+        if let Err(err) = lcmp
+            .lock()
+            .unwrap()
+            .set_key(&name, &TelemetryValue { value })
+        {
+            warn!(
+                "could not update the key availability of platform {}: {}",
+                name, err
+            );
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::auth::{SCOPE_ADMIN, SCOPE_READ, SCOPE_WRITE};
+    use crate::faas::{FaasPlatform, FaasPlatformFactory, FakeFaasPlatform};
+    use crate::httpserver::stand_in::HttpStandIn;
+    use crate::meo::MeoAppContextServer;
+    use crate::messages::{
+        AppContext, ApplicationListInfo, LocationConstraints, UserAppInstanceInfo,
+    };
+    use crate::rediskvs::stand_in::RespStandIn;
+    use std::sync::Arc;
+
+    /// Notifier recording the callbackReference of the notifications, which
+    /// fail for the callbackReferences containing "failing".
+    #[derive(Clone, Default)]
+    struct RecordingNotifier {
+        sent: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Notifier for RecordingNotifier {
+        fn notify(&self, callback_reference: &str, _body: String) -> Result<(), String> {
+            if callback_reference.contains("failing") {
+                return Err("failing callback".to_string());
+            }
+            self.sent
+                .lock()
+                .unwrap()
+                .push(callback_reference.to_string());
+            Ok(())
+        }
+    }
 
     #[test]
     fn test_empty_lcmp() -> Result<(), String> {
+        let kvs = Kvs::shared();
         let mut lcmp = LcmpServer {
-            application_list_server: build_application_list_server("empty")?,
-            app_context_server: build_app_context_server("single;1,URI")?,
+            application_list_server: build_application_list_server("empty", &kvs)?,
+            app_context_server: build_app_context_server("single;1,URI", &kvs)?,
+            kvs,
+            redis: None,
+            notifier: Box::new(HttpNotifier::new(RetryPolicy::default())),
+            leases: None,
+            key_admission: None,
         };
 
         assert!(lcmp.application_list().status().is_ok());
         assert!(lcmp.app_context().status().is_ok());
+        assert!(lcmp.kvs().lock().unwrap().apps().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lcmp_redis_admin() -> Result<(), String> {
+        let server = RespStandIn::start();
+        let url = server.url();
+        let mut lcmp = LcmpServer::build(
+            &format!("redis;url={}", url),
+            &format!("redis;url={};platform=p0;max=10", url),
+        )?;
+        assert!(LcmpServer::build(
+            &format!("redis;url={}", url),
+            "redis;url=redis://127.0.0.1:1/0;platform=p0;max=10"
+        )
+        .is_err());
+
+        // the admin operations are performed on the Redis server
+        lcmp.add_app(App::example("my_appDId"))?;
+        assert!(lcmp.add_app(App::example("my_appDId")).is_err());
+        let app_list = lcmp
+            .application_list()
+            .application_list(ApplicationListInfo::empty())?;
+        assert_eq!(1, app_list.appList.len());
+        assert!(lcmp.kvs().lock().unwrap().apps().is_empty());
+
+        lcmp.add_platform(Platform {
+            qkd: Some(PlatformQkd {
+                kmeEndpoint: "http://127.0.0.1:1".to_string(),
+                slaveSaeId: "sae2".to_string(),
+                masterSaeId: None,
+                slaveKmeEndpoint: None,
+                keyDeliveryEndpoint: None,
+            }),
+            ..Platform::example("p0")
+        })?;
+        lcmp.set_load("p0", &TelemetryValue { value: 0.5 })?;
+        lcmp.set_key("p0", &TelemetryValue { value: 0.25 })?;
+        let redis = RedisKvs::new(&url)?;
+        assert_eq!(Some(0.5), redis.load("p0")?);
+        assert_eq!(Some(0.25), redis.key("p0")?);
+        assert!(lcmp.kvs().lock().unwrap().platforms().is_empty());
+
+        // the admission is based on the key availability in the Redis server
+        let mut lcmp = lcmp.with_key_admission(KeyAdmissionConf {
+            min_key: 0.5,
+            retry_after: 10,
+        });
+        let mut a = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        assert!(matches!(
+            lcmp.new_context(&Identity::anonymous(), &mut a.clone()),
+            Err(LcmpError::KeysUnavailable(_, 10))
+        ));
+        assert!(redis.contexts("p0")?.is_empty());
+        lcmp.set_key("p0", &TelemetryValue { value: 0.75 })?;
+
+        // the reference URI is that of the platform added
+        lcmp.new_context(&Identity::anonymous(), &mut a)?;
+        assert_eq!(
+            Some("http://p0/apps".to_string()),
+            a.appInfo.userAppInstanceInfo[0].referenceURI
+        );
+
+        lcmp.del_platform("p0")?;
+        assert!(redis.platforms()?.is_empty());
+        lcmp.del_app(&App::example("my_appDId").key())?;
+        assert!(lcmp
+            .application_list()
+            .application_list(ApplicationListInfo::empty())?
+            .appList
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lcmp_key_telemetry_redis() -> Result<(), String> {
+        let status = r#"{"source_KME_ID":"kme1","target_KME_ID":"kme2","master_SAE_ID":"sae1","slave_SAE_ID":"sae2","key_size":256,"stored_key_count":250,"max_key_count":1000,"max_key_per_request":10,"max_key_size":1024,"min_key_size":64,"max_SAE_ID_count":0}"#;
+        let kme = HttpStandIn::start(vec![(200, status.to_string())]);
+        let server = RespStandIn::start();
+        let url = server.url();
+        let lcmp = LcmpServer::build(
+            &format!("redis;url={}", url),
+            &format!("redis;url={};platform=p0;max=10", url),
+        )?;
+        let redis = RedisKvs::new(&url)?;
+        for (name, kme_endpoint) in [("p0", kme.url()), ("p1", "http://127.0.0.1:1".to_string())] {
+            redis.add_platform(Platform {
+                qkd: Some(PlatformQkd {
+                    kmeEndpoint: kme_endpoint,
+                    slaveSaeId: "sae2".to_string(),
+                    masterSaeId: None,
+                    slaveKmeEndpoint: None,
+                    keyDeliveryEndpoint: None,
+                }),
+                ..Platform::example(name)
+            })?;
+        }
+        redis.add_platform(Platform::example("p2"))?;
+
+        // the key availability is written to the Redis server
+        let lcmp = Mutex::new(lcmp);
+        update_key_telemetry(&lcmp, Duration::from_secs(1))?;
+        assert_eq!(1, kme.requests().len());
+        assert_eq!(Some(0.25), redis.key("p0")?);
+        assert_eq!(Some(0.0), redis.key("p1")?);
+        assert_eq!(Some(DEFAULT_KEY), redis.key("p2")?);
+        assert!(lcmp
+            .lock()
+            .unwrap()
+            .kvs()
+            .lock()
+            .unwrap()
+            .platforms()
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lcmp_update_reference_uri() -> Result<(), String> {
+        let server = HttpStandIn::start(vec![]);
+        let mut lcmp = LcmpServer::build("empty", "single;10,URI")?;
+
+        // context without callbackReference: no notification
+        let mut a = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        lcmp.app_context().new_context(&mut a)?;
+        lcmp.update_reference_uri(a.contextId.as_ref().unwrap(), "URI2")?;
+
+        // context with callbackReference: notification sent only if changed
+        let mut b = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        b.callbackReference = Some(format!("{}/notify", server.url()));
+        lcmp.app_context().new_context(&mut b)?;
+        let context_id = b.contextId.clone().unwrap();
+        lcmp.update_reference_uri(&context_id, "URI")?;
+        lcmp.update_reference_uri(&context_id, "URI2")?;
+        assert!(lcmp.update_reference_uri("non-existing", "URI2").is_err());
+
+        assert!(server.wait_for(1, Duration::from_secs(5)));
+        std::thread::sleep(Duration::from_millis(100));
+        let requests = server.requests();
+        assert_eq!(1, requests.len());
+        assert_eq!("/notify", requests[0].path);
+        let notification: ApplicationContextUpdateNotification =
+            serde_json::from_str(&requests[0].body).map_err(|x| x.to_string())?;
+        assert_eq!(context_id, notification.contextId);
+        assert_eq!(
+            Some("URI2".to_string()),
+            notification.userAppInstanceInfo[0].referenceURI
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lcmp_optimize() -> Result<(), String> {
+        let server = HttpStandIn::start(vec![]);
+        let mut lcmp = LcmpServer::build("empty", "meo;seed=1;deploy=false")?;
+        let conf = OptimizationConf::default();
+        let kvs = lcmp.kvs();
+        for name in ["p0", "p1"] {
+            kvs.lock().unwrap().add_platform(Platform::example(name))?;
+        }
+        kvs.lock().unwrap().add_app(App::example("my_appDId"))?;
+
+        let mut a = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        a.callbackReference = Some(format!("{}/notify", server.url()));
+        lcmp.app_context().new_context(&mut a)?;
+        let origin = a.appInfo.userAppInstanceInfo[0].referenceURI.clone();
+        let origin_name = if origin == Some("http://p0/apps".to_string()) {
+            "p0"
+        } else {
+            "p1"
+        };
+
+        assert_eq!(0, lcmp.optimize(&conf)?);
+        kvs.lock()
+            .unwrap()
+            .set_load(origin_name, &TelemetryValue { value: 1.0 })?;
+        assert_eq!(1, lcmp.optimize(&conf)?);
+
+        assert!(server.wait_for(1, Duration::from_secs(5)));
+        let notification: ApplicationContextUpdateNotification =
+            serde_json::from_str(&server.requests()[0].body).map_err(|x| x.to_string())?;
+        assert_eq!(a.contextId, Some(notification.contextId));
+        assert_ne!(origin, notification.userAppInstanceInfo[0].referenceURI);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lcmp_pending_context() -> Result<(), String> {
+        let kvs = Kvs::shared();
+        kvs.lock().unwrap().add_platform(Platform::example("p0"))?;
+        kvs.lock().unwrap().add_app(App::example("my_appDId"))?;
+        let fake = FakeFaasPlatform::new();
+        let cloned = fake.clone();
+        let faas: FaasPlatformFactory = Box::new(move |_: &Platform| {
+            Ok(Box::new(cloned.clone()) as Box<dyn FaasPlatform + Send + Sync>)
+        });
+        let meo = MeoAppContextServer::from_params(kvs.clone(), "seed=1")?.with_faas(Some(faas));
+        let mut lcmp = LcmpServer {
+            application_list_server: build_application_list_server("empty", &kvs)?,
+            app_context_server: Box::new(meo),
+            kvs,
+            redis: None,
+            notifier: Box::new(HttpNotifier::new(RetryPolicy::default())),
+            leases: None,
+            key_admission: None,
+        };
+        let anonymous = Identity::anonymous();
+
+        // the deployment is performed without the LCMP server
+        let mut a = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        let mut pending = lcmp.start_context(&anonymous, &mut a)?;
+        assert_eq!(1, lcmp.app_context().list_contexts()?.len());
+        pending.run();
+        assert_eq!((1, 0), fake.operations());
+        lcmp.finish_context(pending, &mut a)?;
+        assert_eq!(1, lcmp.app_context().list_contexts()?.len());
+
+        // deployment failure: the context is deleted
+        lcmp.del_context(&anonymous, a.contextId.as_ref().unwrap())?;
+        fake.set_failing(true);
+        let mut b = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        let mut pending = lcmp.start_context(&anonymous, &mut b)?;
+        pending.run();
+        assert!(matches!(
+            lcmp.finish_context(pending, &mut b),
+            Err(LcmpError::BackendUnavailable(_))
+        ));
+        assert!(lcmp.app_context().list_contexts()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lcmp_del_platform() -> Result<(), String> {
+        let server = HttpStandIn::start(vec![]);
+        let mut lcmp = LcmpServer::build("empty", "meo;seed=1;deploy=false")?;
+        let anonymous = Identity::anonymous();
+        let kvs = lcmp.kvs();
+        kvs.lock().unwrap().add_platform(Platform::example("p0"))?;
+        kvs.lock().unwrap().add_app(App::example("my_appDId"))?;
+
+        let mut a = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        a.callbackReference = Some(format!("{}/notify", server.url()));
+        lcmp.new_context(&anonymous, &mut a)?;
+        let a_id = a.contextId.clone().unwrap();
+
+        // no other platform: the context stays on the removed one
+        assert_eq!(0, lcmp.del_platform("p0")?);
+        assert!(lcmp.del_platform("p0").is_err());
+        assert!(server.requests().is_empty());
+        assert_eq!(1, kvs.lock().unwrap().contexts("p0").len());
+
+        // the context is migrated by the next optimization round
+        lcmp.add_platform(Platform::example("p1"))?;
+        lcmp.add_platform(Platform::example("p2"))?;
+        assert_eq!(1, lcmp.optimize(&OptimizationConf::default())?);
+        assert!(kvs.lock().unwrap().contexts("p0").is_empty());
+        let reference_uri = lcmp
+            .get_context(&anonymous, &a_id)?
+            .appInfo
+            .userAppInstanceInfo[0]
+            .referenceURI
+            .clone()
+            .unwrap();
+        let (current, other) = match reference_uri.as_str() {
+            "http://p1/apps" => ("p1", "p2"),
+            _ => ("p2", "p1"),
+        };
+
+        // the context is migrated as soon as its platform is removed
+        lcmp.del_platform(current)?;
+        assert_eq!(
+            Some(format!("http://{}/apps", other)),
+            lcmp.get_context(&anonymous, &a_id)?
+                .appInfo
+                .userAppInstanceInfo[0]
+                .referenceURI
+        );
+        assert_eq!(1, kvs.lock().unwrap().contexts(other).len());
+
+        assert!(server.wait_for(2, Duration::from_secs(5)));
+        for request in server.requests() {
+            let notification: ApplicationContextUpdateNotification =
+                serde_json::from_str(&request.body).map_err(|x| x.to_string())?;
+            assert_eq!(a_id, notification.contextId);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lcmp_failed_notifications() -> Result<(), String> {
+        let mut lcmp = LcmpServer::build("empty", "meo;seed=1;deploy=false")?;
+        let notifier = RecordingNotifier::default();
+        lcmp.notifier = Box::new(notifier.clone());
+        let anonymous = Identity::anonymous();
+        let kvs = lcmp.kvs();
+        kvs.lock().unwrap().add_platform(Platform::example("p0"))?;
+        kvs.lock().unwrap().add_app(App::example("my_appDId"))?;
+
+        // the notification of the first context fails
+        for callback_reference in ["http://failing/notify", "http://ok/notify"] {
+            let mut x = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+            x.callbackReference = Some(callback_reference.to_string());
+            lcmp.new_context(&anonymous, &mut x)?;
+        }
+
+        // both contexts are migrated and the other one is notified anyway
+        lcmp.add_platform(Platform::example("p1"))?;
+        assert_eq!(2, lcmp.del_platform("p0")?);
+        assert_eq!(2, kvs.lock().unwrap().contexts("p1").len());
+        assert_eq!(vec!["http://ok/notify"], *notifier.sent.lock().unwrap());
+
+        lcmp.add_platform(Platform::example("p2"))?;
+        kvs.lock()
+            .unwrap()
+            .set_load("p1", &TelemetryValue { value: 1.0 })?;
+        assert_eq!(2, lcmp.optimize(&OptimizationConf::default())?);
+        assert_eq!(2, kvs.lock().unwrap().contexts("p2").len());
+        assert_eq!(
+            vec!["http://ok/notify", "http://ok/notify"],
+            *notifier.sent.lock().unwrap()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lcmp_location_availability() -> Result<(), String> {
+        let server = HttpStandIn::start(vec![]);
+        let mut lcmp = LcmpServer::build("empty", "single;10,URI")?;
+        let anonymous = Identity::anonymous();
+        let location = |json: &str| -> Result<LocationConstraints, String> {
+            serde_json::from_str(json).map_err(|x| x.to_string())
+        };
+        let square = location(
+            r#"{"civicAddressElement":[],"area":{"coordinates":[[[0.0,0.0],[1.0,0.0],[1.0,1.0],[0.0,1.0]]]}}"#,
+        )?;
+        let pisa = location(
+            r#"{"countryCode":"IT","civicAddressElement":[{"caType":3,"caValue":"Pisa"}]}"#,
+        )?;
+
+        // a: location requested, b: no location requested,
+        // c: no updates requested, d: another location requested
+        let mut context_ids = vec![];
+        for (name, updates, requested) in [
+            ("a", true, Some(square)),
+            ("b", true, None),
+            ("c", false, None),
+            ("d", true, Some(pisa)),
+        ] {
+            let mut x = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+            x.callbackReference = Some(format!("{}/{}", server.url(), name));
+            x.appLocationUpdates = Some(updates);
+            if requested.is_some() {
+                x.appInfo.userAppInstanceInfo.push(UserAppInstanceInfo {
+                    appInstanceId: None,
+                    referenceURI: None,
+                    appLocation: requested,
+                    qkdKeyIds: None,
+                });
+            }
+            lcmp.new_context(&anonymous, &mut x)?;
+            context_ids.push(x.contextId.unwrap());
+        }
+
+        let mut platform = Platform::example("p0");
+
+        // platform without location: no notification
+        lcmp.add_platform(platform.clone())?;
+        assert!(lcmp.kvs().lock().unwrap().platform("p0").is_some());
+
+        // platform with a location in the area requested by a
+        platform.name = "p1".to_string();
+        platform.location = Some(location(
+            r#"{"civicAddressElement":[],"area":{"coordinates":[[[0.5,0.5]]]}}"#,
+        )?);
+        lcmp.add_platform(platform.clone())?;
+        assert!(lcmp.add_platform(platform).is_err());
+
+        assert!(server.wait_for(2, Duration::from_secs(5)));
+        std::thread::sleep(Duration::from_millis(100));
+        let mut requests = server.requests();
+        assert_eq!(2, requests.len());
+        requests.sort_by(|x, y| x.path.cmp(&y.path));
+        assert_eq!("/a", requests[0].path);
+        assert_eq!("/b", requests[1].path);
+        let notification: ApplicationLocationAvailabilityNotification =
+            serde_json::from_str(&requests[0].body).map_err(|x| x.to_string())?;
+        assert_eq!(
+            "ApplicationLocationAvailabilityNotification",
+            notification.notificationType
+        );
+        assert_eq!(context_ids[0], notification.contextId);
+        assert_eq!(1, notification.availableLocations.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lcmp_key_pairs() -> Result<(), String> {
+        let container = r#"{"keys":[{"key_ID":"bc490419-7d60-487f-adc1-4ddcc177c139","key":"wHHVxRwDJs3/bXd38GHP3oe4svTuRpZS0yCC7x4Ly+s="}]}"#;
+        // the same server acts as the KMEs of both SAEs and the platform
+        let server = HttpStandIn::start(vec![
+            (200, container.to_string()),
+            (200, container.to_string()),
+            (200, String::new()),
+            (503, r#"{"message":"no keys available"}"#.to_string()),
+        ]);
+        let mut lcmp = LcmpServer::build("empty", "meo;seed=1;deploy=false")?;
+        let anonymous = Identity::anonymous();
+        let kvs = lcmp.kvs();
+        kvs.lock().unwrap().add_platform(Platform {
+            qkd: Some(PlatformQkd {
+                kmeEndpoint: server.url(),
+                slaveSaeId: "sae2".to_string(),
+                masterSaeId: Some("sae1".to_string()),
+                slaveKmeEndpoint: Some(server.url()),
+                keyDeliveryEndpoint: Some(format!("{}/keys", server.url())),
+            }),
+            ..Platform::example("p0")
+        })?;
+        kvs.lock().unwrap().add_app(App::example("my_appDId"))?;
+
+        // the key IDs are returned in the response to the creation and retrieval
+        let key_ids = Some(vec!["bc490419-7d60-487f-adc1-4ddcc177c139".to_string()]);
+        // the KME is only requested by run, without the LCMP server
+        let mut a = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        let mut pending = lcmp.start_context(&anonymous, &mut a)?;
+        assert!(server.requests().is_empty());
+        pending.run();
+        assert_eq!(3, server.requests().len());
+        lcmp.finish_context(pending, &mut a)?;
+        assert_eq!(key_ids, a.appInfo.userAppInstanceInfo[0].qkdKeyIds);
+        let a_id = a.contextId.clone().unwrap();
+        let mut retrieved = lcmp.get_context(&anonymous, &a_id)?;
+        assert_eq!(key_ids, retrieved.appInfo.userAppInstanceInfo[0].qkdKeyIds);
+
+        // the key IDs are ignored in the updates, and retained
+        retrieved.callbackReference = Some("http://callback".to_string());
+        lcmp.update_context(&anonymous, &mut retrieved)?;
+        retrieved.appInfo.userAppInstanceInfo[0].qkdKeyIds = None;
+        lcmp.update_context(&anonymous, &mut retrieved)?;
+        let retrieved = lcmp.get_context(&anonymous, &a_id)?;
+        assert_eq!(key_ids, retrieved.appInfo.userAppInstanceInfo[0].qkdKeyIds);
+        assert_eq!(
+            Some("http://callback".to_string()),
+            retrieved.callbackReference
+        );
+
+        // the context is not created if the key pair cannot be obtained
+        let mut b = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        assert!(matches!(
+            lcmp.new_context(&anonymous, &mut b),
+            Err(LcmpError::BackendUnavailable(_))
+        ));
+        assert_eq!(vec![a_id], lcmp.app_context().list_contexts()?);
+        assert_eq!(1, kvs.lock().unwrap().contexts("p0").len());
+
+        // the keys are delivered to the platform after obtaining them on both sides
+        let paths: Vec<String> = server.requests().into_iter().map(|x| x.path).collect();
+        assert_eq!(
+            vec![
+                "/api/v1/keys/sae2/enc_keys",
+                "/api/v1/keys/sae1/dec_keys",
+                "/keys",
+                "/api/v1/keys/sae2/enc_keys"
+            ],
+            paths
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lcmp_key_admission() -> Result<(), String> {
+        let conf = KeyAdmissionConf {
+            min_key: 0.1,
+            retry_after: 30,
+        };
+        let anonymous = Identity::anonymous();
+        let new_context = |lcmp: &mut LcmpServer| {
+            let mut x = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+            lcmp.new_context(&anonymous, &mut x).map(|_| x)
+        };
+        let rejected =
+            |x: Result<AppContext, LcmpError>| matches!(x, Err(LcmpError::KeysUnavailable(_, 30)));
+        let key = |value: f64| TelemetryValue { value };
+        let with_qkd = |name: &str| Platform {
+            // never queried for the admission
+            qkd: Some(PlatformQkd {
+                kmeEndpoint: "http://127.0.0.1:1".to_string(),
+                slaveSaeId: "sae2".to_string(),
+                masterSaeId: None,
+                slaveKmeEndpoint: None,
+                keyDeliveryEndpoint: None,
+            }),
+            ..Platform::example(name)
+        };
+
+        // static reference URI
+        let mut lcmp = LcmpServer::build("empty", "single;10,http://p0/apps")?
+            .with_key_admission(conf.clone());
+
+        // platform not in the KVS: always admitted
+        new_context(&mut lcmp)?;
+
+        // key availability in the KVS too low: rejected without creating the context
+        lcmp.add_platform(with_qkd("p0"))?;
+        new_context(&mut lcmp)?;
+        lcmp.set_key("p0", &key(0.05))?;
+        assert!(rejected(new_context(&mut lcmp)));
+        assert_eq!(2, lcmp.app_context().list_contexts()?.len());
+
+        // key availability sufficient: admitted
+        lcmp.set_key("p0", &key(0.5))?;
+        new_context(&mut lcmp)?;
+        assert_eq!(3, lcmp.app_context().list_contexts()?.len());
+
+        // MEO: the platforms not admitted are not candidates
+        let mut lcmp =
+            LcmpServer::build("empty", "meo;seed=1;deploy=false")?.with_key_admission(conf);
+        lcmp.add_app(App::example("my_appDId"))?;
+        lcmp.add_platform(with_qkd("p0"))?;
+        lcmp.add_platform(with_qkd("p1"))?;
+        lcmp.set_key("p0", &key(0.05))?;
+        for _ in 0..5 {
+            assert_eq!(
+                Some("http://p1/apps".to_string()),
+                new_context(&mut lcmp)?.appInfo.userAppInstanceInfo[0].referenceURI
+            );
+        }
+        lcmp.set_key("p1", &key(0.0))?;
+        assert!(rejected(new_context(&mut lcmp)));
+        assert_eq!(5, lcmp.app_context().list_contexts()?.len());
+
+        // platforms without a KME are always admitted
+        lcmp.add_platform(Platform::example("p2"))?;
+        lcmp.set_key("p2", &key(0.0))?;
+        assert_eq!(
+            Some("http://p2/apps".to_string()),
+            new_context(&mut lcmp)?.appInfo.userAppInstanceInfo[0].referenceURI
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lcmp_context_owner() -> Result<(), String> {
+        let mut lcmp = LcmpServer::build("empty", "single;10,URI")?;
+        let identity = |subject: &str, scopes: &[&str]| Identity {
+            subject: subject.to_string(),
+            scopes: scopes.iter().map(|x| x.to_string()).collect(),
+        };
+        let app1 = identity("app1", &[SCOPE_READ, SCOPE_WRITE]);
+        let app2 = identity("app2", &[SCOPE_READ, SCOPE_WRITE]);
+        let admin = identity("admin", &[SCOPE_READ, SCOPE_ADMIN]);
+
+        // the owner is assigned by the LCMP
+        let mut a = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        a.owner = Some("app2".to_string());
+        lcmp.new_context(&app1, &mut a)?;
+        let mut b = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        lcmp.new_context(&app2, &mut b)?;
+        let a_id = a.contextId.clone().unwrap();
+        let b_id = b.contextId.clone().unwrap();
+        assert_eq!(
+            Some("app1".to_string()),
+            lcmp.app_context().get_context(&a_id)?.owner
+        );
+
+        // the owner is stored, but not included in the responses
+        let mut retrieved = lcmp.get_context(&app1, &a_id)?;
+        lcmp.update_context(&app1, &mut retrieved)?;
+        for x in [&a, &b, &retrieved] {
+            assert!(x.owner.is_none());
+            let serialized = serde_json::to_string(x).map_err(|x| x.to_string())?;
+            assert!(!serialized.contains("owner"));
+        }
+
+        // only the owner and the admins can access a context
+        assert!(lcmp.get_context(&app1, &a_id).is_ok());
+        assert!(lcmp.get_context(&admin, &a_id).is_ok());
+        let forbidden = |x: Result<(), LcmpError>| matches!(x, Err(LcmpError::Forbidden(_)));
+        assert!(forbidden(lcmp.get_context(&app2, &a_id).map(|_| ())));
+        assert!(forbidden(lcmp.update_context(&app2, &mut a)));
+        assert!(forbidden(lcmp.del_context(&app2, &a_id)));
+        assert!(matches!(
+            lcmp.get_context(&app2, "non-existing"),
+            Err(LcmpError::NotFound(_))
+        ));
+        a.callbackReference = Some("http://callback".to_string());
+        lcmp.update_context(&app1, &mut a)?;
+
+        // only the owned contexts are listed, unless admin
+        let all = lcmp.app_context().list_contexts()?;
+        assert_eq!(vec![a_id.clone()], lcmp.retain_owned(&app1, all.clone())?);
+        assert_eq!(vec![b_id.clone()], lcmp.retain_owned(&app2, all.clone())?);
+        assert_eq!(2, lcmp.retain_owned(&admin, all)?.len());
+        assert!(lcmp
+            .retain_owned(&identity("app3", &[SCOPE_READ]), vec![a_id.clone()])?
+            .is_empty());
+
+        // a context without owner is accessible only by the admins
+        let mut c = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        lcmp.app_context().new_context(&mut c)?;
+        assert!(c.owner.is_none());
+        let c_id = c.contextId.clone().unwrap();
+        assert!(forbidden(lcmp.get_context(&app1, &c_id).map(|_| ())));
+        assert!(forbidden(lcmp.update_context(&app1, &mut c)));
+        assert!(forbidden(lcmp.del_context(&app1, &c_id)));
+        let all = lcmp.app_context().list_contexts()?;
+        assert!(!lcmp.retain_owned(&app1, all.clone())?.contains(&c_id));
+        assert!(lcmp.retain_owned(&admin, all)?.contains(&c_id));
+        assert!(lcmp.get_context(&admin, &c_id).is_ok());
+
+        lcmp.del_context(&admin, &a_id)?;
+        lcmp.del_context(&app2, &b_id)?;
+        lcmp.del_context(&admin, &c_id)?;
+        assert!(lcmp.app_context().list_contexts()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lcmp_context_leases() -> Result<(), String> {
+        let ttl = Duration::from_millis(500);
+        let mut lcmp = LcmpServer::build("empty", "meo;seed=1;deploy=false")?.with_context_ttl(ttl);
+        let anonymous = Identity::anonymous();
+        let kvs = lcmp.kvs();
+        kvs.lock().unwrap().add_platform(Platform::example("p0"))?;
+        kvs.lock().unwrap().add_app(App::example("my_appDId"))?;
+
+        // the expiration time is returned in the responses
+        let mut a = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        let mut b = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        lcmp.new_context(&anonymous, &mut a)?;
+        lcmp.new_context(&anonymous, &mut b)?;
+        assert!(a.expiresAt.is_some());
+        let a_id = a.contextId.clone().unwrap();
+        let b_id = b.contextId.clone().unwrap();
+        assert!(lcmp.get_context(&anonymous, &a_id)?.expiresAt.unwrap() >= a.expiresAt.unwrap());
+        assert!(lcmp.expire_contexts()?.is_empty());
+
+        // a is renewed, b is not
+        std::thread::sleep(ttl / 2);
+        lcmp.update_context(&anonymous, &mut a)?;
+        std::thread::sleep(ttl / 2 + Duration::from_millis(50));
+        assert_eq!(vec![b_id.clone()], lcmp.expire_contexts()?);
+        assert!(lcmp.get_context(&anonymous, &b_id).is_err());
+        assert_eq!(1, kvs.lock().unwrap().contexts("p0").len());
+
+        // explicitly deleted
+        lcmp.del_context(&anonymous, &a_id)?;
+        assert!(lcmp.expire_contexts()?.is_empty());
+        assert!(kvs.lock().unwrap().contexts("p0").is_empty());
+
+        // without leases
+        let mut lcmp = LcmpServer::build("empty", "single;10,URI")?;
+        let mut a = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        lcmp.new_context(&anonymous, &mut a)?;
+        assert!(a.expiresAt.is_none());
+        assert!(lcmp.expire_contexts()?.is_empty());
+        let serialized = serde_json::to_string(&a).map_err(|x| x.to_string())?;
+        assert!(!serialized.contains("expiresAt"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lcmp_context_leases_redis() -> Result<(), String> {
+        let server = RespStandIn::start();
+        let url = server.url();
+        let redis = RedisKvs::new(&url)?;
+        redis.add_platform(Platform::example("p0"))?;
+        redis.add_app(App::example("my_appDId"))?;
+        let build = || {
+            LcmpServer::build(
+                &format!("redis;url={}", url),
+                &format!("redis;url={};platform=p0;max=10", url),
+            )
+        };
+        let ttl = Duration::from_millis(500);
+        let mut lcmp_a = build()?.with_context_ttl(ttl);
+        let mut lcmp_b = build()?.with_context_ttl(ttl);
+        let anonymous = Identity::anonymous();
+
+        let mut a = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        let mut b = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        lcmp_a.new_context(&anonymous, &mut a)?;
+        lcmp_a.new_context(&anonymous, &mut b)?;
+        let a_id = a.contextId.clone().unwrap();
+        let b_id = b.contextId.clone().unwrap();
+        assert!(lcmp_b.expire_contexts()?.is_empty());
+
+        // a is renewed through the other instance, b is not
+        std::thread::sleep(ttl / 2);
+        lcmp_b.get_context(&anonymous, &a_id)?;
+        std::thread::sleep(ttl / 2 + Duration::from_millis(50));
+        assert_eq!(vec![b_id], lcmp_a.expire_contexts()?);
+        assert!(lcmp_b.get_context(&anonymous, &a_id).is_ok());
+        assert_eq!(1, redis.contexts("p0")?.len());
+
+        // created without leases: a new lease is stored
+        let mut c = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        build()?.new_context(&anonymous, &mut c)?;
+        assert!(lcmp_a.expire_contexts()?.is_empty());
+        let contexts = redis.contexts("p0")?;
+        assert_eq!(2, contexts.len());
+        assert!(contexts.iter().all(|x| x.expiresAt.is_some()));
 
         Ok(())
     }
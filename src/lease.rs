@@ -0,0 +1,74 @@
+//! Leases of the application contexts, which expire unless renewed.
+
+use crate::messages::TimeStamp;
+use std::time::{Duration, SystemTime};
+
+/// Leases of the active contexts, each expiring after a given time-to-live
+/// since it was last renewed.
+/// The expiration time of the leases is stored with the contexts in the
+/// AppContext server, so that they are shared by all the LCMP instances
+/// using the same backend.
+pub struct Leases {
+    /// Time-to-live of the leases.
+    ttl: Duration,
+}
+
+impl Leases {
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl }
+    }
+
+    /// Return the time-to-live of the leases.
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Return the expiration time of a lease created or renewed now.
+    pub fn renew(&self) -> TimeStamp {
+        self.renew_at(SystemTime::now())
+    }
+
+    fn renew_at(&self, now: SystemTime) -> TimeStamp {
+        TimeStamp::from_system_time(now + self.ttl)
+    }
+
+    /// Return true if a lease with the given expiration time has expired.
+    pub fn expired(&self, expires_at: &TimeStamp) -> bool {
+        self.expired_at(expires_at, SystemTime::now())
+    }
+
+    fn expired_at(&self, expires_at: &TimeStamp, now: SystemTime) -> bool {
+        *expires_at <= TimeStamp::from_system_time(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leases() {
+        let ttl = Duration::from_secs(10);
+        let leases = Leases::new(ttl);
+        assert_eq!(ttl, leases.ttl());
+        let t0 = SystemTime::now();
+
+        let a = leases.renew_at(t0);
+        let b = leases.renew_at(t0 + Duration::from_secs(5));
+        assert!(!leases.expired_at(&a, t0));
+        assert!(!leases.expired_at(&b, t0));
+        assert!(leases.expired_at(&a, t0 + Duration::from_secs(10)));
+        assert!(!leases.expired_at(&b, t0 + Duration::from_secs(10)));
+
+        // renewal
+        let a = leases.renew_at(t0 + Duration::from_secs(10));
+        assert!(!leases.expired_at(&a, t0 + Duration::from_secs(15)));
+        assert!(leases.expired_at(&b, t0 + Duration::from_secs(15)));
+
+        // expiration time
+        let now = TimeStamp::from_system_time(SystemTime::now());
+        let expires_at = leases.renew();
+        assert!(expires_at.seconds >= now.seconds + 10 && expires_at.seconds <= now.seconds + 11);
+        assert!(!leases.expired(&expires_at));
+    }
+}
@@ -1,5 +1,18 @@
 pub mod appcontextserver;
 pub mod applicationlistserver;
+pub mod auth;
+pub mod error;
+pub mod faas;
 pub mod httpserver;
+pub mod journal;
+pub mod kme;
+pub mod kvs;
 pub mod lcmpserver;
+pub mod lease;
+pub mod meo;
 pub mod messages;
+pub mod notification;
+pub mod qkd014;
+pub mod rediskvs;
+pub mod sqlite;
+pub mod tls;
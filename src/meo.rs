@@ -0,0 +1,1561 @@
+//! MEC Orchestrator (MEO) assigning application contexts to platforms.
+//!
+//! The operations are described in docs/meo.md.
+
+use crate::appcontextserver::AppContextServer;
+use crate::error::LcmpError;
+use crate::faas::{build_faas_platform, Completion, FaasPlatformFactory, FaasWorker};
+use crate::kvs::{App, Kvs, Platform, SharedKvs};
+use crate::messages::{AppContext, LocationConstraints, TimeStamp};
+use log::{info, warn};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Function returning the score of a platform given its load and key availability,
+/// both normalized in [0,1]. The score must be non-negative.
+pub type ScoreFunction = Box<dyn Fn(f64, f64) -> f64 + Send + Sync>;
+
+/// Return a score function by name.
+pub fn score_function(name: &str) -> Result<ScoreFunction, String> {
+    match name {
+        "product" => Ok(Box::new(|load, key| (1.0 - load) * key)),
+        "load" => Ok(Box::new(|load, _key| 1.0 - load)),
+        "key" => Ok(Box::new(|_load, key| key)),
+        "uniform" => Ok(Box::new(|_load, _key| 1.0)),
+        _ => Err(format!("unknown score function: {}", name)),
+    }
+}
+
+fn not_found(what: &str, name: &str) -> LcmpError {
+    LcmpError::NotFound(format!("{} not found: {}", what, name))
+}
+
+/// Parameters of the MEO.
+#[derive(Clone, Copy)]
+pub struct MeoConf {
+    /// Maximum load to consider a platform as a candidate.
+    pub max_load: f64,
+    /// Minimum key availability to consider a platform as a candidate.
+    pub min_key: f64,
+}
+
+impl MeoConf {
+    /// Check that the load and key availability limits are within [0, 1].
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [("max_load", self.max_load), ("min_key", self.min_key)] {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(format!("{} must be in [0, 1]: {}", name, value));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for MeoConf {
+    fn default() -> Self {
+        Self {
+            max_load: 1.0,
+            min_key: 0.0,
+        }
+    }
+}
+
+/// Parameters of the periodic optimization performed by the MEO.
+#[derive(Clone, Copy)]
+pub struct OptimizationConf {
+    /// Interval between consecutive optimization rounds.
+    pub period: Duration,
+    /// Contexts are migrated away from platforms with a load higher than this value.
+    pub max_load: f64,
+    /// Contexts are migrated away from platforms with a key availability lower than this value.
+    pub min_key: f64,
+    /// A platform is a candidate target of a migration only if its load is not
+    /// higher than max_load - hysteresis and its key availability is not lower
+    /// than min_key + hysteresis.
+    pub hysteresis: f64,
+    /// Maximum number of contexts migrated in a single optimization round.
+    pub max_migrations: usize,
+}
+
+impl Default for OptimizationConf {
+    fn default() -> Self {
+        Self {
+            period: Duration::from_secs(10),
+            max_load: 0.9,
+            min_key: 0.1,
+            hysteresis: 0.1,
+            max_migrations: 10,
+        }
+    }
+}
+
+/// Application context assigned to a platform.
+struct ActiveContext {
+    /// Name of the platform.
+    platform: String,
+    /// Key of the app in the KVS.
+    app_key: String,
+    /// Application context, as returned to the device app.
+    app_context: AppContext,
+}
+
+/// Action of an app deployed on a platform.
+struct Deployment {
+    /// Number of active contexts using the action.
+    count: usize,
+    /// Data used to deploy the action, also needed to delete it.
+    deploy_info: serde_json::Value,
+    /// Outcome of the deployment, possibly still in progress.
+    completion: Completion,
+    /// Optimization round in which the deployment was started.
+    round: u64,
+}
+
+/// Migration of an active context waiting for the deployment of the app on
+/// the target platform.
+struct PendingMigration {
+    /// Name of the target platform.
+    target: String,
+    /// Outcome of the deployment on the target platform.
+    deployment: Completion,
+}
+
+/// AppContextServer assigning new contexts to the platforms in the KVS based on
+/// their load and key availability.
+pub struct MeoAppContextServer {
+    kvs: SharedKvs,
+    conf: MeoConf,
+    score: ScoreFunction,
+    rng: StdRng,
+    /// Active application contexts indexed by the context ID.
+    app_contexts: HashMap<String, ActiveContext>,
+    /// Drivers of the platforms, if None then the actions are not deployed/deleted.
+    faas: Option<FaasPlatformFactory>,
+    /// Workers deploying/deleting the actions, indexed by the platform name.
+    workers: HashMap<String, FaasWorker>,
+    /// Actions deployed, indexed by the app key and the platform name.
+    deployments: HashMap<(String, String), Deployment>,
+    /// Migrations in progress, indexed by the context ID.
+    migrations: HashMap<String, PendingMigration>,
+    /// Number of optimization rounds started, including the evacuations of
+    /// the platforms removed.
+    round: u64,
+    /// Load and key availability of the platforms estimated after the
+    /// migrations of the current optimization round, indexed by the platform name.
+    estimates: HashMap<String, (f64, f64)>,
+}
+
+impl MeoAppContextServer {
+    /// Create a MEO with the given score function.
+    /// If a seed is given, then the random selection of platforms is deterministic.
+    /// The actions are not deployed/deleted unless a factory of platform
+    /// drivers is set with with_faas().
+    pub fn new(
+        kvs: SharedKvs,
+        conf: MeoConf,
+        score: ScoreFunction,
+        seed: Option<u64>,
+    ) -> Result<Self, String> {
+        conf.validate()?;
+        Ok(Self {
+            kvs,
+            conf,
+            score,
+            rng: match seed {
+                Some(x) => StdRng::seed_from_u64(x),
+                None => StdRng::from_entropy(),
+            },
+            app_contexts: HashMap::new(),
+            faas: None,
+            workers: HashMap::new(),
+            deployments: HashMap::new(),
+            migrations: HashMap::new(),
+            round: 0,
+            estimates: HashMap::new(),
+        })
+    }
+
+    /// Use the given factory to create the drivers of the platforms, or do
+    /// not deploy/delete actions at all if None.
+    pub fn with_faas(mut self, faas: Option<FaasPlatformFactory>) -> Self {
+        self.faas = faas;
+        self
+    }
+
+    /// Create a MEO from a string of semicolon-separated key=value parameters:
+    /// max_load, min_key, score (product, load, key, uniform), seed, deploy (true, false).
+    /// All parameters are optional.
+    pub fn from_params(kvs: SharedKvs, params: &str) -> Result<Self, String> {
+        let mut conf = MeoConf::default();
+        let mut score = "product";
+        let mut seed = None;
+        let mut deploy = false;
+        for param in params.split(';').filter(|x| !x.is_empty()) {
+            let (key, value) = param
+                .split_once('=')
+                .ok_or(format!("invalid MEO parameter: {}", param))?;
+            let invalid = || format!("invalid value for {}: {}", key, value);
+            match key {
+                "max_load" => conf.max_load = value.parse::<f64>().map_err(|_| invalid())?,
+                "min_key" => conf.min_key = value.parse::<f64>().map_err(|_| invalid())?,
+                "score" => score = value,
+                "seed" => seed = Some(value.parse::<u64>().map_err(|_| invalid())?),
+                "deploy" => deploy = value.parse::<bool>().map_err(|_| invalid())?,
+                _ => return Err(format!("unknown MEO parameter: {}", key)),
+            }
+        }
+        let meo = Self::new(kvs, conf, score_function(score)?, seed)?;
+        Ok(match deploy {
+            true => meo.with_faas(Some(Box::new(build_faas_platform))),
+            false => meo,
+        })
+    }
+
+    /// Return the candidate platforms for an app with their score.
+    /// A platform is a candidate if it is allowed by the blacklist/whitelist of the app
+    /// for that platform type, its location satisfies the one requested, if any,
+    /// and its load and key availability are within the limits.
+    pub fn candidates(
+        &self,
+        kvs: &Kvs,
+        app: &App,
+        location: Option<&LocationConstraints>,
+    ) -> Vec<(String, f64)> {
+        self.candidates_within(kvs, app, location, self.conf.max_load, self.conf.min_key)
+    }
+
+    /// Return the candidate platforms for an app with their score, using the given
+    /// maximum load and minimum key availability.
+    fn candidates_within(
+        &self,
+        kvs: &Kvs,
+        app: &App,
+        location: Option<&LocationConstraints>,
+        max_load: f64,
+        min_key: f64,
+    ) -> Vec<(String, f64)> {
+        let mut ret = vec![];
+        for platform in kvs.platforms() {
+            if !app.allows(platform) {
+                continue;
+            }
+            if let Some(location) = location {
+                match &platform.location {
+                    Some(x) if location.contains(x) => {}
+                    _ => continue,
+                }
+            }
+            let (load, key) = self.telemetry(kvs, &platform.name);
+            if load > max_load || key < min_key {
+                continue;
+            }
+            ret.push((platform.name.clone(), (self.score)(load, key).max(0.0)));
+        }
+        ret
+    }
+
+    /// Return the load and key availability of a platform, as estimated in the
+    /// current optimization round or otherwise from the KVS, assuming the
+    /// worst case if not available.
+    fn telemetry(&self, kvs: &Kvs, name: &str) -> (f64, f64) {
+        self.estimates.get(name).copied().unwrap_or((
+            kvs.load(name).unwrap_or(1.0),
+            kvs.key(name).unwrap_or(0.0),
+        ))
+    }
+
+    /// Return the load and key consumption of a single context on a platform,
+    /// assuming that all its contexts contribute equally, or None if the
+    /// telemetry of the platform is not available, e.g., because it has been removed.
+    fn context_share(&self, kvs: &Kvs, name: &str) -> Option<(f64, f64)> {
+        let contexts = self
+            .app_contexts
+            .values()
+            .filter(|x| x.platform == name)
+            .count()
+            .max(1) as f64;
+        match (kvs.load(name), kvs.key(name)) {
+            (None, None) => None,
+            (load, key) => Some((
+                load.unwrap_or(0.0) / contexts,
+                (1.0 - key.unwrap_or(1.0)) / contexts,
+            )),
+        }
+    }
+
+    /// Update the estimated load and key availability of the target platform
+    /// of a migration of a context with the given share.
+    /// The origin is not updated, since it exceeds the limits of the targets
+    /// until its actual load and key availability are reported.
+    fn estimate_migration(&mut self, kvs: &Kvs, target: &str, share: (f64, f64)) {
+        let (load, key) = share;
+        let (target_load, target_key) = self.telemetry(kvs, target);
+        self.estimates.insert(
+            target.to_string(),
+            ((target_load + load).min(1.0), (target_key - key).max(0.0)),
+        );
+    }
+
+    /// Select one of the candidates at random using the score as a weight.
+    /// If all the scores are zero, then the selection is uniformly random.
+    pub fn select(&mut self, candidates: &[(String, f64)]) -> Option<String> {
+        if candidates.is_empty() {
+            return None;
+        }
+        let total: f64 = candidates.iter().map(|x| x.1).sum();
+        if total <= 0.0 {
+            let ndx = self.rng.gen_range(0..candidates.len());
+            return Some(candidates[ndx].0.clone());
+        }
+        let mut threshold = self.rng.gen::<f64>() * total;
+        for (name, score) in candidates {
+            if threshold < *score {
+                return Some(name.clone());
+            }
+            threshold -= score;
+        }
+        candidates.last().map(|x| x.0.clone())
+    }
+
+    /// Add a reference to the action of an app on a platform and return the
+    /// outcome of its deployment.
+    /// If this is the first reference, or the previous deployment failed, then
+    /// the action is deployed in the background with the deployInfo of the element
+    /// of the platforms of the app that allows the platform, the same used to
+    /// select it: if the deployment fails, then the reference must be released.
+    fn acquire(&mut self, kvs: &Kvs, app: &App, platform: &str) -> Result<Completion, LcmpError> {
+        let index = (app.key(), platform.to_string());
+        if let Some(x) = self.deployments.get_mut(&index) {
+            if !matches!(x.completion.result(), Some(Err(_))) {
+                x.count += 1;
+                return Ok(x.completion.clone());
+            }
+        }
+        let platform = kvs
+            .platform(platform)
+            .ok_or_else(|| not_found("platform", platform))?;
+        let deploy_info = match app.app_platform(platform) {
+            Some(x) => x.deployInfo.clone(),
+            None => {
+                return Err(LcmpError::InvalidRequest(format!(
+                    "app {} cannot be deployed on {}",
+                    index.0, platform.name
+                )))
+            }
+        };
+        let completion = self.deploy(platform, &index.0, &deploy_info)?;
+        let deployment = self.deployments.entry(index).or_insert(Deployment {
+            count: 0,
+            deploy_info,
+            completion: completion.clone(),
+            round: self.round,
+        });
+        deployment.count += 1;
+        deployment.completion = completion.clone();
+        deployment.round = self.round;
+        Ok(completion)
+    }
+
+    /// Queue the deployment of the action of an app on a platform, if enabled.
+    fn deploy(
+        &mut self,
+        platform: &Platform,
+        app_key: &str,
+        deploy_info: &serde_json::Value,
+    ) -> Result<Completion, LcmpError> {
+        let driver = match &self.faas {
+            Some(faas) => faas(platform).map_err(|x| {
+                LcmpError::BackendUnavailable(format!(
+                    "could not deploy on {}: {}",
+                    platform.name, x
+                ))
+            })?,
+            None => return Ok(Completion::completed(Ok(()))),
+        };
+        let completion = Completion::new();
+        let cloned = completion.clone();
+        let app_key = app_key.to_string();
+        let name = platform.name.clone();
+        let deploy_info = deploy_info.clone();
+        self.worker(&platform.name).submit(move || {
+            let res = driver
+                .deploy_action(&deploy_info)
+                .map_err(|x| format!("could not deploy on {}: {}", name, x));
+            if res.is_ok() {
+                info!("app {} deployed on {}", app_key, name);
+            }
+            cloned.complete(res);
+        });
+        Ok(completion)
+    }
+
+    /// Remove a reference to the action of an app on a platform.
+    /// If this is the last reference, then the action is deleted in the background,
+    /// if its deployment succeeded: failures are only logged since the contexts
+    /// are not using the action anymore.
+    fn release(&mut self, kvs: &Kvs, app_key: &str, platform: &str) {
+        let index = (app_key.to_string(), platform.to_string());
+        let deployment = match self.deployments.get_mut(&index) {
+            Some(x) if x.count > 1 => {
+                x.count -= 1;
+                return;
+            }
+            Some(_) => self.deployments.remove(&index).unwrap(),
+            None => return,
+        };
+        let driver = match (&self.faas, kvs.platform(platform)) {
+            (None, _) => return,
+            (Some(_), None) => {
+                info!(
+                    "app {} not deleted from {}: platform removed",
+                    app_key, platform
+                );
+                return;
+            }
+            (Some(faas), Some(x)) => match faas(x) {
+                Ok(x) => x,
+                Err(err) => {
+                    warn!(
+                        "could not delete app {} from {}: {}",
+                        app_key, platform, err
+                    );
+                    return;
+                }
+            },
+        };
+        let app_key = app_key.to_string();
+        let name = platform.to_string();
+        self.worker(platform).submit(move || {
+            // the deployment was queued before, thus it has already completed
+            if deployment.completion.result() != Some(Ok(())) {
+                return;
+            }
+            match driver.delete_action(&deployment.deploy_info) {
+                Ok(_) => info!("app {} deleted from {}", app_key, name),
+                Err(err) => warn!("could not delete app {} from {}: {}", app_key, name, err),
+            }
+        });
+    }
+
+    /// Return the worker of a platform, started if needed.
+    fn worker(&mut self, platform: &str) -> &FaasWorker {
+        self.workers
+            .entry(platform.to_string())
+            .or_insert_with(FaasWorker::start)
+    }
+
+    /// Wait until the deployments and deletions of the actions already queued
+    /// have been performed.
+    pub fn flush(&self) {
+        for worker in self.workers.values() {
+            worker.flush();
+        }
+    }
+
+    /// Return the number of active contexts using the action of an app on a platform.
+    pub fn references(&self, app_key: &str, platform: &str) -> usize {
+        self.deployments
+            .get(&(app_key.to_string(), platform.to_string()))
+            .map_or(0, |x| x.count)
+    }
+
+    /// Start the migration of an active context to a different platform.
+    /// The context is moved immediately, returning true, if the app was
+    /// deployed on the target platform before the current round, otherwise
+    /// when the deployment completes, by a later call of complete_migrations.
+    fn migrate(
+        &mut self,
+        kvs: &mut Kvs,
+        app: &App,
+        context_id: &str,
+        target: &str,
+    ) -> Result<bool, LcmpError> {
+        if !self.app_contexts.contains_key(context_id) {
+            return Err(not_found("context ID", context_id));
+        }
+        let ready = self.faas.is_none()
+            || self
+                .deployments
+                .get(&(app.key(), target.to_string()))
+                .is_some_and(|x| x.round < self.round && x.completion.result() == Some(Ok(())));
+        let deployment = self.acquire(kvs, app, target)?;
+        if ready {
+            self.move_context(kvs, context_id, target)?;
+            return Ok(true);
+        }
+        self.migrations.insert(
+            context_id.to_string(),
+            PendingMigration {
+                target: target.to_string(),
+                deployment,
+            },
+        );
+        Ok(false)
+    }
+
+    /// Move an active context to a platform where the app is deployed, updating
+    /// its reference URI, and release the app on the origin platform.
+    fn move_context(
+        &mut self,
+        kvs: &mut Kvs,
+        context_id: &str,
+        target: &str,
+    ) -> Result<(), LcmpError> {
+        let reference_uri = match kvs.platform(target) {
+            Some(x) => x.endpointApps.clone(),
+            None => return Err(not_found("platform", target)),
+        };
+        let active = match self.app_contexts.get_mut(context_id) {
+            Some(x) => x,
+            None => return Err(not_found("context ID", context_id)),
+        };
+        let origin = std::mem::replace(&mut active.platform, target.to_string());
+        for info in &mut active.app_context.appInfo.userAppInstanceInfo {
+            info.referenceURI = Some(reference_uri.clone());
+        }
+        let _ = kvs.del_context(&origin, context_id);
+        kvs.add_context(target, active.app_context.clone());
+        info!(
+            "context {} migrated from {} to {}",
+            context_id, origin, target
+        );
+        let app_key = active.app_key.clone();
+        self.release(kvs, &app_key, &origin);
+        Ok(())
+    }
+
+    /// Complete the migrations whose deployment on the target platform has
+    /// finished, appending the IDs of the contexts moved to migrated.
+    /// If the deployment failed, then the context stays where it is.
+    fn complete_migrations(&mut self, kvs: &mut Kvs, migrated: &mut Vec<String>) {
+        let mut concluded: Vec<String> = self
+            .migrations
+            .iter()
+            .filter(|x| x.1.deployment.result().is_some())
+            .map(|x| x.0.clone())
+            .collect();
+        concluded.sort();
+        for context_id in concluded {
+            let migration = self.migrations.remove(&context_id).unwrap();
+            let res = match migration.deployment.result().unwrap() {
+                Ok(_) => self.move_context(kvs, &context_id, &migration.target),
+                Err(err) => Err(LcmpError::BackendUnavailable(err)),
+            };
+            match res {
+                Ok(_) => migrated.push(context_id),
+                Err(err) => {
+                    warn!("could not migrate context {}: {}", context_id, err);
+                    let app_key = self.app_contexts[&context_id].app_key.clone();
+                    self.release(kvs, &app_key, &migration.target);
+                }
+            }
+        }
+    }
+
+    /// Start the migration of the active contexts assigned to a platform, and
+    /// not already being migrated, to other platforms, selected as for new
+    /// contexts with the given maximum load and minimum key availability.
+    /// The IDs of the contexts moved immediately are appended to migrated.
+    /// Return false if the migrations stopped early, i.e., because there is no
+    /// target platform available or max_migrations contexts have been moved
+    /// or are being migrated.
+    fn migrate_from(
+        &mut self,
+        kvs: &mut Kvs,
+        origin: &str,
+        max_load: f64,
+        min_key: f64,
+        max_migrations: usize,
+        migrated: &mut Vec<String>,
+    ) -> bool {
+        let mut context_ids: Vec<String> = self
+            .app_contexts
+            .iter()
+            .filter(|x| x.1.platform == origin && !self.migrations.contains_key(x.0))
+            .map(|x| x.0.clone())
+            .collect();
+        context_ids.sort();
+        let share = self.context_share(kvs, origin);
+        for context_id in context_ids {
+            if migrated.len() + self.migrations.len() >= max_migrations {
+                return false;
+            }
+            let app = match kvs.apps().iter().find(|x| {
+                x.appInfo
+                    .matches_context(&self.app_contexts[&context_id].app_context.appInfo)
+            }) {
+                Some(x) => x.clone(),
+                None => continue,
+            };
+            let location = self.app_contexts[&context_id]
+                .app_context
+                .requested_location()
+                .cloned();
+            let candidates: Vec<(String, f64)> = self
+                .candidates_within(kvs, &app, location.as_ref(), max_load, min_key)
+                .into_iter()
+                .filter(|x| x.0 != origin)
+                .collect();
+            match self.select(&candidates) {
+                Some(target) => {
+                    if let Some(share) = share {
+                        self.estimate_migration(kvs, &target, share);
+                    }
+                    match self.migrate(kvs, &app, &context_id, &target) {
+                        Ok(true) => migrated.push(context_id),
+                        Ok(false) => {}
+                        Err(err) => warn!("could not migrate context {}: {}", context_id, err),
+                    }
+                }
+                None => {
+                    warn!(
+                        "overload condition: no target platform to migrate contexts from {}",
+                        origin
+                    );
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+impl AppContextServer for MeoAppContextServer {
+    /// The context is rejected if there is no candidate platform for the app,
+    /// or if the app cannot be deployed on the selected platform, waiting for
+    /// the deployment if needed.
+    /// Otherwise the referenceURI returned is the endpointApps of the selected platform.
+    fn new_context(&mut self, app_context: &mut AppContext) -> Result<(), LcmpError> {
+        let deployment = self.start_context(app_context, &|_| Ok(()))?;
+        if let Err(err) = deployment.map_or(Ok(()), |x| x.wait()) {
+            if let Some(context_id) = &app_context.contextId {
+                self.del_context(context_id)?;
+            }
+            return Err(LcmpError::BackendUnavailable(err));
+        }
+        Ok(())
+    }
+
+    /// As new_context, but the deployment of the app on the selected platform,
+    /// if this is the first context using it, is performed in the background.
+    /// The platforms whose endpointApps is not admitted are not candidates:
+    /// if no other candidate is available, the first admission error is returned.
+    fn start_context(
+        &mut self,
+        app_context: &mut AppContext,
+        admit: &dyn Fn(&str) -> Result<(), LcmpError>,
+    ) -> Result<Option<Completion>, LcmpError> {
+        app_context
+            .valid_request()
+            .map_err(LcmpError::InvalidRequest)?;
+
+        // Find the app and the candidate platforms.
+        let kvs = self.kvs.clone();
+        let mut kvs = kvs.lock().unwrap();
+        let app = kvs
+            .apps()
+            .iter()
+            .find(|x| x.appInfo.matches_context(&app_context.appInfo))
+            .ok_or_else(|| {
+                not_found(
+                    "application",
+                    app_context
+                        .appInfo
+                        .appDId
+                        .as_deref()
+                        .unwrap_or("unspecified"),
+                )
+            })?
+            .clone();
+        let mut candidates = self.candidates(&kvs, &app, app_context.requested_location());
+        let mut rejected = None;
+        candidates.retain(
+            |(name, _)| match kvs.platform(name).map(|x| admit(&x.endpointApps)) {
+                Some(Err(err)) => {
+                    rejected.get_or_insert(err);
+                    false
+                }
+                _ => true,
+            },
+        );
+
+        // Select the target platform.
+        let platform = match self.select(&candidates) {
+            Some(x) => x,
+            None => {
+                return Err(rejected.unwrap_or_else(|| {
+                    LcmpError::BackendUnavailable(
+                        match app_context.requested_location() {
+                            Some(_) => "no candidate platform available in the location requested",
+                            None => "no candidate platform available",
+                        }
+                        .to_string(),
+                    )
+                }))
+            }
+        };
+        let reference_uri = match kvs.platform(&platform) {
+            Some(x) => x.endpointApps.clone(),
+            None => return Err(not_found("platform", &platform)),
+        };
+
+        // Deploy the app on the platform, if this is the first context using it.
+        let deployment = self.acquire(&kvs, &app, &platform)?;
+
+        // Assign a new random context id, the app instance id and the reference URI.
+        app_context.contextId = Some(Uuid::simple(Uuid::new_v4()).to_string());
+        app_context.assign_reference_uri(&reference_uri);
+
+        // Add to the KVS and to the list of active contexts.
+        kvs.add_context(&platform, app_context.clone());
+        self.app_contexts.insert(
+            app_context.contextId.clone().unwrap(),
+            ActiveContext {
+                platform,
+                app_key: app.key(),
+                app_context: app_context.clone(),
+            },
+        );
+
+        Ok(Some(deployment))
+    }
+
+    /// Delete an active context, cancelling its migration if in progress.
+    /// The app is deleted from the platform if not used by other contexts.
+    fn del_context(&mut self, context_id: &str) -> Result<(), LcmpError> {
+        match self.app_contexts.remove(context_id) {
+            Some(x) => {
+                let kvs = self.kvs.clone();
+                let mut kvs = kvs.lock().unwrap();
+                let _ = kvs.del_context(&x.platform, context_id);
+                self.release(&kvs, &x.app_key, &x.platform);
+                if let Some(migration) = self.migrations.remove(context_id) {
+                    self.release(&kvs, &x.app_key, &migration.target);
+                }
+                Ok(())
+            }
+            None => Err(not_found("context ID", context_id)),
+        }
+    }
+
+    /// Get an active context.
+    fn get_context(&mut self, context_id: &str) -> Result<&AppContext, LcmpError> {
+        match self.app_contexts.get(context_id) {
+            Some(x) => Ok(&x.app_context),
+            None => Err(not_found("context ID", context_id)),
+        }
+    }
+
+    /// Update an active context.
+    /// Only the callbackReference is allowed to be updated. If the other
+    /// fields do not match exactly, then the command is denied.
+    fn update_context(&mut self, app_context: &mut AppContext) -> Result<(), LcmpError> {
+        let context_id = match &app_context.contextId {
+            Some(x) => x,
+            None => {
+                return Err(LcmpError::InvalidRequest(
+                    "context ID not specified in the request".to_string(),
+                ))
+            }
+        };
+        match self.app_contexts.get_mut(context_id.as_str()) {
+            Some(x) => {
+                if !x
+                    .app_context
+                    .identical_except_callback_reference(app_context)
+                {
+                    return Err(LcmpError::Conflict(
+                        "AppContext in the request does not match that in the server".to_string(),
+                    ));
+                }
+                x.app_context.callbackReference = app_context.callbackReference.clone();
+                let _ = self
+                    .kvs
+                    .lock()
+                    .unwrap()
+                    .update_context(&x.platform, x.app_context.clone());
+                Ok(())
+            }
+            None => Err(not_found("context ID", context_id)),
+        }
+    }
+
+    /// Return all active contexts.
+    fn list_contexts(&mut self) -> Result<Vec<String>, LcmpError> {
+        Ok(self.app_contexts.keys().cloned().collect())
+    }
+
+    /// Always return good health.
+    fn status(&self) -> Result<(), LcmpError> {
+        Ok(())
+    }
+
+    fn set_qkd_key_ids(
+        &mut self,
+        context_id: &str,
+        key_ids: &[Option<Vec<String>>],
+    ) -> Result<(), LcmpError> {
+        match self.app_contexts.get_mut(context_id) {
+            Some(x) => {
+                x.app_context.assign_qkd_key_ids(key_ids);
+                let _ = self
+                    .kvs
+                    .lock()
+                    .unwrap()
+                    .update_context(&x.platform, x.app_context.clone());
+                Ok(())
+            }
+            None => Err(not_found("context ID", context_id)),
+        }
+    }
+
+    fn set_expires_at(
+        &mut self,
+        context_id: &str,
+        expires_at: Option<TimeStamp>,
+    ) -> Result<(), LcmpError> {
+        match self.app_contexts.get_mut(context_id) {
+            Some(x) => {
+                x.app_context.expiresAt = expires_at;
+                let _ = self
+                    .kvs
+                    .lock()
+                    .unwrap()
+                    .update_context(&x.platform, x.app_context.clone());
+                Ok(())
+            }
+            None => Err(not_found("context ID", context_id)),
+        }
+    }
+
+    /// Migrate contexts from platforms with a load too high or a key availability
+    /// too low, or that have been removed from the KVS, to other platforms,
+    /// selected as for new contexts.
+    /// The contexts are moved when the app is deployed on the target platform,
+    /// i.e., immediately if already there or otherwise in a later round.
+    /// The round ends early if there is no target platform available for a context.
+    /// The load and key availability of the platforms are read from the KVS
+    /// at the beginning of the round and then estimated after each migration,
+    /// so that the contexts are spread across the target platforms.
+    fn optimize(&mut self, conf: &OptimizationConf) -> Result<Vec<String>, LcmpError> {
+        let kvs = self.kvs.clone();
+        let mut kvs = kvs.lock().unwrap();
+        let mut migrated = vec![];
+        self.round += 1;
+        self.estimates.clear();
+        self.complete_migrations(&mut kvs, &mut migrated);
+
+        let mut origins: Vec<String> = self
+            .app_contexts
+            .values()
+            .filter(|x| kvs.platform(&x.platform).is_none())
+            .map(|x| x.platform.clone())
+            .collect();
+        origins.sort();
+        origins.dedup();
+        origins.extend(
+            kvs.platforms()
+                .iter()
+                .filter(|x| {
+                    kvs.load(&x.name).unwrap_or(0.0) > conf.max_load
+                        || kvs.key(&x.name).unwrap_or(1.0) < conf.min_key
+                })
+                .map(|x| x.name.clone()),
+        );
+
+        for origin in origins {
+            if !self.migrate_from(
+                &mut kvs,
+                &origin,
+                conf.max_load - conf.hysteresis,
+                conf.min_key + conf.hysteresis,
+                conf.max_migrations,
+                &mut migrated,
+            ) {
+                break;
+            }
+        }
+        self.estimates.clear();
+
+        Ok(migrated)
+    }
+
+    /// Migrate all the contexts assigned to the platform removed to other
+    /// platforms, selected as for new contexts. Those that cannot be moved
+    /// now, e.g., because the app is still being deployed on the target
+    /// platform, are moved by the next optimization rounds.
+    fn evacuate_platform(&mut self, name: &str) -> Result<Vec<String>, LcmpError> {
+        let kvs = self.kvs.clone();
+        let mut kvs = kvs.lock().unwrap();
+        let mut migrated = vec![];
+        self.round += 1;
+        self.complete_migrations(&mut kvs, &mut migrated);
+        self.estimates.clear();
+        self.migrate_from(
+            &mut kvs,
+            name,
+            self.conf.max_load,
+            self.conf.min_key,
+            usize::MAX,
+            &mut migrated,
+        );
+        self.estimates.clear();
+        Ok(migrated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::faas::{FaasPlatform, FakeFaasPlatform};
+    use crate::kvs::{AppPlatform, Platform, TelemetryValue};
+    use crate::messages::UserAppInstanceInfo;
+
+    fn example_app(blacklist: &str, whitelist: &str) -> App {
+        let to_vec = |x: &str| -> Vec<String> {
+            x.split(',')
+                .filter(|x| !x.is_empty())
+                .map(|x| x.to_string())
+                .collect()
+        };
+        let mut app = App::example("my_appDId");
+        app.platforms[0].blacklist = to_vec(blacklist);
+        app.platforms[0].whitelist = to_vec(whitelist);
+        app
+    }
+
+    fn example_kvs(num_platforms: usize) -> SharedKvs {
+        let kvs = Kvs::shared();
+        for i in 0..num_platforms {
+            kvs.lock()
+                .unwrap()
+                .add_platform(Platform::example(&format!("p{}", i)))
+                .expect("could not add platform");
+        }
+        kvs
+    }
+
+    /// Return a factory of fake drivers, one for each platform p0, p1, ...
+    fn fake_faas(num_platforms: usize) -> (FaasPlatformFactory, Vec<FakeFaasPlatform>) {
+        let fakes: Vec<FakeFaasPlatform> = (0..num_platforms)
+            .map(|_| FakeFaasPlatform::new())
+            .collect();
+        let cloned = fakes.clone();
+        let factory: FaasPlatformFactory = Box::new(move |platform: &Platform| {
+            let ndx = platform.name[1..].parse::<usize>().unwrap();
+            Ok(Box::new(cloned[ndx].clone()) as Box<dyn FaasPlatform + Send + Sync>)
+        });
+        (factory, fakes)
+    }
+
+    fn request() -> AppContext {
+        AppContext::request_from_name_provider("my_app_name", "my_app_provider")
+    }
+
+    fn reference_uri(app_context: &AppContext) -> String {
+        app_context.appInfo.userAppInstanceInfo[0]
+            .referenceURI
+            .clone()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_meo_from_params() -> Result<(), String> {
+        let kvs = Kvs::shared();
+        assert!(MeoAppContextServer::from_params(kvs.clone(), "").is_ok());
+        assert!(MeoAppContextServer::from_params(
+            kvs.clone(),
+            "max_load=0.8;min_key=0.2;score=load;seed=42"
+        )
+        .is_ok());
+        assert!(MeoAppContextServer::from_params(kvs.clone(), "max_load").is_err());
+        assert!(MeoAppContextServer::from_params(kvs.clone(), "max_load=x").is_err());
+        assert!(MeoAppContextServer::from_params(kvs.clone(), "score=unknown").is_err());
+        assert!(MeoAppContextServer::from_params(kvs.clone(), "deploy=false").is_ok());
+        assert!(MeoAppContextServer::from_params(kvs.clone(), "")?.faas.is_none());
+        assert!(MeoAppContextServer::from_params(kvs.clone(), "deploy=true")?.faas.is_some());
+        assert!(MeoAppContextServer::from_params(kvs.clone(), "max_load=1.5").is_err());
+        assert!(MeoAppContextServer::from_params(kvs.clone(), "max_load=-0.1").is_err());
+        assert!(MeoAppContextServer::from_params(kvs.clone(), "min_key=2").is_err());
+        assert!(MeoAppContextServer::from_params(kvs.clone(), "min_key=NaN").is_err());
+        assert!(MeoAppContextServer::from_params(kvs.clone(), "max_load=0;min_key=1").is_ok());
+        assert!(MeoAppContextServer::from_params(kvs.clone(), "deploy=no").is_err());
+        assert!(MeoAppContextServer::from_params(kvs, "unknown=1").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_meo_candidates() -> Result<(), String> {
+        let kvs = example_kvs(4);
+        let conf = MeoConf {
+            max_load: 0.8,
+            min_key: 0.2,
+        };
+        let meo = MeoAppContextServer::new(kvs.clone(), conf, score_function("product")?, None)?;
+        let names = |candidates: Vec<(String, f64)>| -> Vec<String> {
+            candidates.into_iter().map(|x| x.0).collect()
+        };
+
+        let mut kvs = kvs.lock().unwrap();
+        assert_eq!(
+            vec!["p0", "p1", "p2", "p3"],
+            names(meo.candidates(&kvs, &example_app("", ""), None))
+        );
+        assert_eq!(
+            vec!["p0", "p3"],
+            names(meo.candidates(&kvs, &example_app("p1,p2", ""), None))
+        );
+        assert_eq!(
+            vec!["p1"],
+            names(meo.candidates(&kvs, &example_app("p2", "p1,p2"), None))
+        );
+
+        kvs.set_load("p0", &TelemetryValue { value: 0.9 })?;
+        kvs.set_key("p1", &TelemetryValue { value: 0.1 })?;
+        kvs.set_load("p2", &TelemetryValue { value: 0.5 })?;
+        kvs.set_key("p2", &TelemetryValue { value: 0.5 })?;
+        let candidates = meo.candidates(&kvs, &example_app("", ""), None);
+        assert_eq!(
+            vec![("p2".to_string(), 0.25), ("p3".to_string(), 1.0)],
+            candidates
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_meo_select() -> Result<(), String> {
+        let mut meo = MeoAppContextServer::new(
+            Kvs::shared(),
+            MeoConf::default(),
+            score_function("product")?,
+            Some(42),
+        )?;
+        assert!(meo.select(&[]).is_none());
+
+        let candidates = vec![("p0".to_string(), 1.0), ("p1".to_string(), 3.0)];
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for _ in 0..1000 {
+            *counts.entry(meo.select(&candidates).unwrap()).or_default() += 1;
+        }
+        assert!(counts["p0"] > 150 && counts["p0"] < 350);
+        assert!(counts["p1"] > 650 && counts["p1"] < 850);
+
+        let candidates = vec![("p0".to_string(), 0.0), ("p1".to_string(), 0.0)];
+        for _ in 0..10 {
+            assert!(meo.select(&candidates).is_some());
+        }
+
+        // same seed, same sequence
+        let mut meo2 = MeoAppContextServer::new(
+            Kvs::shared(),
+            MeoConf::default(),
+            score_function("product")?,
+            Some(42),
+        )?;
+        let mut meo3 = MeoAppContextServer::new(
+            Kvs::shared(),
+            MeoConf::default(),
+            score_function("product")?,
+            Some(42),
+        )?;
+        let candidates = vec![("p0".to_string(), 1.0), ("p1".to_string(), 1.0)];
+        for _ in 0..100 {
+            assert_eq!(meo2.select(&candidates), meo3.select(&candidates));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_meo_app_context_server() -> Result<(), String> {
+        let kvs = example_kvs(2);
+        let mut meo = MeoAppContextServer::from_params(kvs.clone(), "min_key=0.5;seed=1")?
+            .with_faas(Some(fake_faas(2).0));
+
+        // unknown app: error
+        assert!(meo.new_context(&mut request()).is_err());
+
+        // app allowed only on p1
+        kvs.lock().unwrap().add_app(example_app("p0", ""))?;
+        let mut a = request();
+        meo.new_context(&mut a)?;
+        assert_eq!("http://p1/apps", reference_uri(&a));
+        let context_id = a.contextId.clone().unwrap();
+        assert_eq!(1, kvs.lock().unwrap().contexts("p1").len());
+        assert_eq!(vec![context_id.clone()], meo.list_contexts()?);
+
+        // update the callback reference
+        a.callbackReference = Some("http://callback".to_string());
+        meo.update_context(&mut a)?;
+        assert_eq!(
+            Some("http://callback".to_string()),
+            kvs.lock().unwrap().contexts("p1")[0].callbackReference
+        );
+        assert_eq!(
+            Some("http://callback".to_string()),
+            meo.get_context(&context_id)?.callbackReference
+        );
+
+        // no key availability on p1: the context is rejected
+        kvs.lock()
+            .unwrap()
+            .set_key("p1", &TelemetryValue { value: 0.1 })?;
+        assert!(meo.new_context(&mut request()).is_err());
+
+        // delete the context
+        meo.del_context(&context_id)?;
+        assert!(meo.del_context(&context_id).is_err());
+        assert!(meo.get_context(&context_id).is_err());
+        assert!(kvs.lock().unwrap().contexts("p1").is_empty());
+
+        Ok(())
+    }
+
+    fn location(json: &str) -> LocationConstraints {
+        serde_json::from_str(json).expect("could not deserialize location")
+    }
+
+    fn area(points: &str) -> LocationConstraints {
+        location(&format!(
+            r#"{{"civicAddressElement":[],"area":{{"coordinates":{}}}}}"#,
+            points
+        ))
+    }
+
+    #[test]
+    fn test_meo_location() -> Result<(), String> {
+        let kvs = example_kvs(0);
+        let locations = [
+            Some(area("[[[0.5,0.5]]]")),
+            Some(area("[[[2.0,2.0]]]")),
+            Some(location(
+                r#"{"countryCode":"IT","civicAddressElement":[{"caType":1,"caValue":"Tuscany"},{"caType":3,"caValue":"Pisa"}]}"#,
+            )),
+            None,
+        ];
+        for (i, location) in locations.into_iter().enumerate() {
+            kvs.lock().unwrap().add_platform(Platform {
+                location,
+                ..Platform::example(&format!("p{}", i))
+            })?;
+        }
+        kvs.lock().unwrap().add_app(example_app("", ""))?;
+        let mut meo = MeoAppContextServer::from_params(kvs.clone(), "seed=1")?
+            .with_faas(Some(fake_faas(4).0));
+        let names = |location: &LocationConstraints| -> Vec<String> {
+            meo.candidates(&kvs.lock().unwrap(), &example_app("", ""), Some(location))
+                .into_iter()
+                .map(|x| x.0)
+                .collect()
+        };
+
+        let square = area("[[[0.0,0.0],[1.0,0.0],[1.0,1.0],[0.0,1.0],[0.0,0.0]]]");
+        assert_eq!(vec!["p0"], names(&square));
+        let square_with_hole = area(
+            "[[[0.0,0.0],[1.0,0.0],[1.0,1.0],[0.0,1.0],[0.0,0.0]],[[0.4,0.4],[0.6,0.4],[0.6,0.6],[0.4,0.6],[0.4,0.4]]]",
+        );
+        assert!(names(&square_with_hole).is_empty());
+        assert_eq!(
+            vec!["p2"],
+            names(&location(
+                r#"{"countryCode":"IT","civicAddressElement":[{"caType":3,"caValue":"Pisa"}]}"#
+            ))
+        );
+        assert!(names(&location(
+            r#"{"countryCode":"FR","civicAddressElement":[{"caType":3,"caValue":"Pisa"}]}"#
+        ))
+        .is_empty());
+
+        // the context is assigned to the only platform in the location requested
+        let mut a = request();
+        a.appInfo.userAppInstanceInfo.push(UserAppInstanceInfo {
+            appInstanceId: None,
+            referenceURI: None,
+            appLocation: Some(square.clone()),
+            qkdKeyIds: None,
+        });
+        meo.new_context(&mut a)?;
+        assert_eq!("http://p0/apps", reference_uri(&a));
+        assert_eq!(1, a.appInfo.userAppInstanceInfo.len());
+        assert!(a.appInfo.userAppInstanceInfo[0].appLocation == Some(square));
+
+        // no platform in the location requested: the context is rejected
+        let mut a = request();
+        a.appInfo.userAppInstanceInfo.push(UserAppInstanceInfo {
+            appInstanceId: None,
+            referenceURI: None,
+            appLocation: Some(square_with_hole),
+            qkdKeyIds: None,
+        });
+        assert!(matches!(
+            meo.new_context(&mut a),
+            Err(LcmpError::BackendUnavailable(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_meo_optimize() -> Result<(), String> {
+        let kvs = example_kvs(3);
+        let (faas, fakes) = fake_faas(3);
+        let mut meo = MeoAppContextServer::from_params(kvs.clone(), "max_load=0.8;seed=1")?
+            .with_faas(Some(faas));
+        let conf = OptimizationConf {
+            period: Duration::from_secs(1),
+            max_load: 0.8,
+            min_key: 0.2,
+            hysteresis: 0.1,
+            max_migrations: 3,
+        };
+        let set_load = |name: &str, value: f64| {
+            kvs.lock()
+                .unwrap()
+                .set_load(name, &TelemetryValue { value })
+                .unwrap()
+        };
+        let num_contexts = |name: &str| kvs.lock().unwrap().contexts(name).len();
+
+        // all the contexts are assigned to p0
+        kvs.lock().unwrap().add_app(example_app("", ""))?;
+        set_load("p1", 0.9);
+        set_load("p2", 0.9);
+        let mut context_ids = vec![];
+        for _ in 0..4 {
+            let mut a = request();
+            meo.new_context(&mut a)?;
+            assert_eq!("http://p0/apps", reference_uri(&a));
+            context_ids.push(a.contextId.unwrap());
+        }
+        assert_eq!(4, num_contexts("p0"));
+
+        // no overloaded platform: nothing happens
+        assert!(meo.optimize(&conf)?.is_empty());
+
+        // p0 is overloaded and only p1 is a valid target, because of hysteresis
+        set_load("p0", 0.95);
+        set_load("p1", 0.1);
+        set_load("p2", 0.75);
+
+        // the contexts are moved when the app has been deployed on p1
+        assert!(meo.optimize(&conf)?.is_empty());
+        assert_eq!(4, num_contexts("p0"));
+        assert_eq!(3, meo.references(&example_app("", "").key(), "p1"));
+        meo.flush();
+        let migrated = meo.optimize(&conf)?;
+        assert_eq!(3, migrated.len());
+        assert_eq!(1, num_contexts("p0"));
+        assert_eq!(3, num_contexts("p1"));
+        for context_id in &migrated {
+            assert_eq!(
+                "http://p1/apps",
+                reference_uri(meo.get_context(context_id)?)
+            );
+        }
+
+        // the last context is migrated in the next round, immediately since
+        // the app is already deployed on p1
+        assert_eq!(1, meo.optimize(&conf)?.len());
+        assert_eq!(0, num_contexts("p0"));
+        assert_eq!(4, num_contexts("p1"));
+
+        // the app has been deployed on p1 and deleted from p0
+        meo.flush();
+        assert_eq!((1, 1), fakes[0].operations());
+        assert_eq!((1, 0), fakes[1].operations());
+        assert_eq!(vec!["my_action"], fakes[1].list_actions()?);
+
+        // all platforms are overloaded: no migration possible
+        set_load("p1", 0.95);
+        set_load("p2", 0.95);
+        assert!(meo.optimize(&conf)?.is_empty());
+        assert_eq!(4, num_contexts("p1"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_meo_optimize_spread() -> Result<(), String> {
+        let kvs = example_kvs(4);
+        let mut meo = MeoAppContextServer::from_params(kvs.clone(), "seed=1")?;
+        let conf = OptimizationConf {
+            period: Duration::from_secs(1),
+            max_load: 0.8,
+            min_key: 0.2,
+            hysteresis: 0.1,
+            max_migrations: 10,
+        };
+        let set_telemetry = |name: &str, load: f64, key: f64| {
+            let mut kvs = kvs.lock().unwrap();
+            kvs.set_load(name, &TelemetryValue { value: load }).unwrap();
+            kvs.set_key(name, &TelemetryValue { value: key }).unwrap();
+        };
+        let num_contexts = |name: &str| kvs.lock().unwrap().contexts(name).len();
+
+        // all the contexts are assigned to p0
+        kvs.lock().unwrap().add_app(example_app("", ""))?;
+        for name in ["p1", "p2", "p3"] {
+            set_telemetry(name, 1.0, 1.0);
+        }
+        for _ in 0..3 {
+            let mut a = request();
+            meo.new_context(&mut a)?;
+            assert_eq!("http://p0/apps", reference_uri(&a));
+        }
+
+        // p0 is overloaded: each context is estimated to add 1/3 to the
+        // load of its target, which can then accept only one context
+        set_telemetry("p0", 1.0, 1.0);
+        for name in ["p1", "p2", "p3"] {
+            set_telemetry(name, 0.5, 1.0);
+        }
+        assert_eq!(3, meo.optimize(&conf)?.len());
+        for name in ["p0", "p1", "p2", "p3"] {
+            assert_eq!(usize::from(name != "p0"), num_contexts(name));
+        }
+
+        // p1 and p2 have no keys left: each context is estimated to consume
+        // all the keys of its target, so only one context can be moved to p0
+        set_telemetry("p0", 0.0, 0.7);
+        set_telemetry("p1", 0.5, 0.0);
+        set_telemetry("p2", 0.5, 0.0);
+        set_telemetry("p3", 0.9, 1.0);
+        assert_eq!(1, meo.optimize(&conf)?.len());
+        assert_eq!(1, num_contexts("p0"));
+        assert_eq!(1, num_contexts("p1") + num_contexts("p2"));
+        assert_eq!(1, num_contexts("p3"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_meo_deployments() -> Result<(), String> {
+        let kvs = example_kvs(2);
+        let (faas, fakes) = fake_faas(2);
+        let mut meo =
+            MeoAppContextServer::from_params(kvs.clone(), "seed=1")?.with_faas(Some(faas));
+        let app = example_app("p1", "");
+        let app_key = app.key();
+        kvs.lock().unwrap().add_app(app)?;
+
+        // the app is deployed with the first context only
+        let mut context_ids = vec![];
+        for i in 0..3 {
+            let mut a = request();
+            meo.new_context(&mut a)?;
+            context_ids.push(a.contextId.unwrap());
+            assert_eq!(i + 1, meo.references(&app_key, "p0"));
+        }
+        assert_eq!((1, 0), fakes[0].operations());
+        assert_eq!(vec!["my_action"], fakes[0].list_actions()?);
+
+        // the app is deleted with the last context only
+        meo.del_context(&context_ids[0])?;
+        meo.del_context(&context_ids[1])?;
+        assert_eq!(1, meo.references(&app_key, "p0"));
+        assert_eq!((1, 0), fakes[0].operations());
+        meo.del_context(&context_ids[2])?;
+        assert_eq!(0, meo.references(&app_key, "p0"));
+        meo.flush();
+        assert_eq!((1, 1), fakes[0].operations());
+        assert!(fakes[0].list_actions()?.is_empty());
+
+        // deployment failure: the context is rejected and nothing is left behind
+        fakes[0].set_failing(true);
+        assert!(meo.new_context(&mut request()).is_err());
+        assert_eq!(0, meo.references(&app_key, "p0"));
+        assert!(meo.list_contexts()?.is_empty());
+        assert!(kvs.lock().unwrap().contexts("p0").is_empty());
+
+        // the platform works again
+        fakes[0].set_failing(false);
+        let mut a = request();
+        meo.new_context(&mut a)?;
+        assert_eq!(1, meo.references(&app_key, "p0"));
+        assert_eq!((2, 1), fakes[0].operations());
+
+        // delete failures do not prevent the context from being deleted
+        fakes[0].set_failing(true);
+        meo.del_context(a.contextId.as_ref().unwrap())?;
+        assert_eq!(0, meo.references(&app_key, "p0"));
+        assert!(kvs.lock().unwrap().contexts("p0").is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_meo_deploy_info() -> Result<(), String> {
+        let kvs = example_kvs(2);
+        let (faas, fakes) = fake_faas(2);
+        let mut meo = MeoAppContextServer::from_params(kvs.clone(), "max_load=0.8;seed=1")?
+            .with_faas(Some(faas));
+        let mut app = example_app("", "p0");
+        app.platforms[0].deployInfo = serde_json::json!({ "name": "action_p0" });
+        app.platforms.push(AppPlatform {
+            platformType: "openwhisk".to_string(),
+            blacklist: vec![],
+            whitelist: vec![],
+            deployInfo: serde_json::json!({ "name": "action_other" }),
+        });
+        kvs.lock().unwrap().add_app(app)?;
+        let set_load = |name: &str, value: f64| {
+            kvs.lock()
+                .unwrap()
+                .set_load(name, &TelemetryValue { value })
+                .unwrap()
+        };
+
+        // the deployInfo is that of the element allowing the platform selected
+        set_load("p0", 1.0);
+        meo.new_context(&mut request())?;
+        assert_eq!(vec!["action_other"], fakes[1].list_actions()?);
+        set_load("p0", 0.0);
+        set_load("p1", 1.0);
+        meo.new_context(&mut request())?;
+        assert_eq!(vec!["action_p0"], fakes[0].list_actions()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_meo_evacuate_platform() -> Result<(), String> {
+        let kvs = example_kvs(3);
+        let (faas, fakes) = fake_faas(3);
+        let mut meo = MeoAppContextServer::from_params(kvs.clone(), "max_load=0.8;seed=1")?
+            .with_faas(Some(faas));
+        let app = example_app("p2", "");
+        let app_key = app.key();
+        kvs.lock().unwrap().add_app(app)?;
+        kvs.lock()
+            .unwrap()
+            .set_load("p1", &TelemetryValue { value: 1.0 })?;
+        let mut context_ids = vec![];
+        for _ in 0..2 {
+            let mut a = request();
+            meo.new_context(&mut a)?;
+            assert_eq!("http://p0/apps", reference_uri(&a));
+            context_ids.push(a.contextId.unwrap());
+        }
+        context_ids.sort();
+
+        // no other candidate platform: the contexts are not migrated
+        kvs.lock().unwrap().del_platform("p0")?;
+        assert!(meo.evacuate_platform("p0")?.is_empty());
+        assert_eq!(2, meo.references(&app_key, "p0"));
+        assert_eq!(2, kvs.lock().unwrap().contexts("p0").len());
+
+        // the contexts are migrated at the next optimization round, even if
+        // the load of the removed platform is unknown
+        kvs.lock()
+            .unwrap()
+            .set_load("p1", &TelemetryValue { value: 0.0 })?;
+        let conf = OptimizationConf::default();
+        assert!(meo.optimize(&conf)?.is_empty());
+        meo.flush();
+        assert_eq!(context_ids, meo.optimize(&conf)?);
+        assert_eq!(0, meo.references(&app_key, "p0"));
+        assert_eq!(2, meo.references(&app_key, "p1"));
+        assert!(kvs.lock().unwrap().contexts("p0").is_empty());
+        assert_eq!(2, kvs.lock().unwrap().contexts("p1").len());
+
+        // the action is not deleted from the removed platform
+        assert_eq!((1, 0), fakes[0].operations());
+        assert_eq!((1, 0), fakes[1].operations());
+
+        // the contexts are migrated as soon as the platform is removed if the
+        // app is already deployed on the target platform
+        kvs.lock().unwrap().add_platform(Platform::example("p0"))?;
+        kvs.lock()
+            .unwrap()
+            .set_load("p1", &TelemetryValue { value: 1.0 })?;
+        let mut b = request();
+        meo.new_context(&mut b)?;
+        assert_eq!("http://p0/apps", reference_uri(&b));
+        kvs.lock().unwrap().del_platform("p1")?;
+        let mut migrated = meo.evacuate_platform("p1")?;
+        migrated.sort();
+        assert_eq!(context_ids, migrated);
+        assert_eq!(0, meo.references(&app_key, "p1"));
+        assert_eq!(3, meo.references(&app_key, "p0"));
+        for context_id in &context_ids {
+            assert_eq!(
+                "http://p0/apps",
+                reference_uri(meo.get_context(context_id)?)
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_meo_migration_deploy_failure() -> Result<(), String> {
+        let kvs = example_kvs(2);
+        let (faas, fakes) = fake_faas(2);
+        let mut meo =
+            MeoAppContextServer::from_params(kvs.clone(), "seed=1")?.with_faas(Some(faas));
+        let app = example_app("", "");
+        let app_key = app.key();
+        kvs.lock().unwrap().add_app(app)?;
+        kvs.lock()
+            .unwrap()
+            .set_load("p1", &TelemetryValue { value: 1.0 })?;
+        let mut a = request();
+        meo.new_context(&mut a)?;
+        assert_eq!("http://p0/apps", reference_uri(&a));
+
+        // the context cannot be migrated to p1 because the deployment fails
+        kvs.lock()
+            .unwrap()
+            .set_load("p0", &TelemetryValue { value: 1.0 })?;
+        kvs.lock()
+            .unwrap()
+            .set_load("p1", &TelemetryValue { value: 0.0 })?;
+        // and the deployment is tried again in the next rounds
+        fakes[1].set_failing(true);
+        let conf = OptimizationConf::default();
+        assert!(meo.optimize(&conf)?.is_empty());
+        meo.flush();
+        assert!(meo.optimize(&conf)?.is_empty());
+        meo.flush();
+        assert_eq!(1, meo.references(&app_key, "p0"));
+        assert_eq!(1, meo.references(&app_key, "p1"));
+        assert_eq!(1, kvs.lock().unwrap().contexts("p0").len());
+        assert_eq!((1, 0), fakes[0].operations());
+        assert_eq!((0, 0), fakes[1].operations());
+
+        // now it can
+        fakes[1].set_failing(false);
+        assert!(meo.optimize(&conf)?.is_empty());
+        meo.flush();
+        assert_eq!(1, meo.optimize(&conf)?.len());
+        meo.flush();
+        assert_eq!(0, meo.references(&app_key, "p0"));
+        assert_eq!(1, meo.references(&app_key, "p1"));
+        assert_eq!((1, 1), fakes[0].operations());
+        assert_eq!((1, 0), fakes[1].operations());
+
+        // a context deleted while being migrated is not migrated
+        kvs.lock()
+            .unwrap()
+            .set_load("p1", &TelemetryValue { value: 1.0 })?;
+        kvs.lock()
+            .unwrap()
+            .set_load("p0", &TelemetryValue { value: 0.0 })?;
+        assert!(meo.optimize(&conf)?.is_empty());
+        assert_eq!(1, meo.references(&app_key, "p0"));
+        meo.del_context(a.contextId.as_ref().unwrap())?;
+        assert_eq!(0, meo.references(&app_key, "p0"));
+        assert_eq!(0, meo.references(&app_key, "p1"));
+        meo.flush();
+        assert!(meo.optimize(&conf)?.is_empty());
+        assert!(fakes[0].list_actions()?.is_empty());
+        assert!(fakes[1].list_actions()?.is_empty());
+
+        Ok(())
+    }
+}
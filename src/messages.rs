@@ -5,12 +5,11 @@
 #![allow(non_snake_case)]
 
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 use std::collections::HashSet;
 use std::fmt::{Display, Formatter};
 use std::fs::File;
-use std::io::{Read, Write};
-use std::path::Path;
+use std::io::Read;
+use std::time::{SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
 /// Validate a message (or element thereof).
@@ -44,10 +43,19 @@ where
 /// in ETSI GS MEC 009 V2.2.1 (2020-10) Table 6.15.3-1.
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ProblemDetails {
+    /// A URI reference that identifies the problem type.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub r#type: Option<String>,
+    /// A short, human-readable summary of the problem type.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
     /// The HTTP status code for this occurrence of the problem.
     pub status: usize,
     /// A human-readable explanation specific to this occurrence of the problem.
     pub detail: String,
+    /// A URI reference that identifies the specific occurrence of the problem.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
 }
 
 /// Polygon as defined in RFC 7946.
@@ -174,6 +182,13 @@ pub struct UserAppInstanceInfo {
     /// For a user application not provided by the requesting device application
     /// it shall match one of the appLocations in ApplicationList.
     pub appLocation: Option<LocationConstraints>,
+    /// IDs of the QKD keys shared by the device application and the MEC host
+    /// of the user application instance, which can be used as pre-shared keys
+    /// to secure their communication, if the MEC host is configured for key
+    /// pairs. It shall only be included in the response; ignored in updates.
+    /// This is a vendor extension, *not* ETSI MEC standard.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qkdKeyIds: Option<Vec<String>>,
 }
 
 impl UserAppInstanceInfo {
@@ -182,6 +197,7 @@ impl UserAppInstanceInfo {
             appInstanceId: Some(Uuid::simple(Uuid::new_v4()).to_string()),
             referenceURI: Some(reference_uri.to_string()),
             appLocation: None,
+            qkdKeyIds: None,
         }
     }
 }
@@ -292,31 +308,221 @@ pub struct AppContext {
     /// Used by the device application to request to receive notifications at
     /// the callbackReference URI relating to location availability for user
     /// application instantiation.
-    appLocationUpdates: Option<bool>,
+    pub appLocationUpdates: Option<bool>,
     /// Provides indication to the MEC system that instantiation of the requested
     /// application is desired should a requested appLocation become available
     /// that was not at the time of the request.
     appAutoInstantiation: Option<bool>,
     // application information
     pub appInfo: AppInfoContext,
+    /// Time when the application context expires, unless renewed by the device
+    /// application with a GET or PUT. Only included in responses with leases enabled.
+    /// This is a vendor extension, *not* ETSI MEC standard.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiresAt: Option<TimeStamp>,
+    /// Identity of the device application that created the context, as
+    /// authenticated by the LCMP, which assigns it ignoring the value in requests.
+    /// If absent, only the admins can access the context.
+    /// It is persisted with the context, but never returned to the device apps.
+    /// This is a vendor extension, *not* ETSI MEC standard.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
 }
 
-impl AppContext {
+/// TimeStamp data type, as defined in ETSI GS MEC 009.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeStamp {
+    /// The seconds part of the time. Time is defined as Unix-time since January 1, 1970, 00:00:00 UTC.
+    pub seconds: u64,
+    /// The nanoseconds part of the time.
+    pub nanoSeconds: u32,
+}
+
+impl TimeStamp {
+    pub fn from_system_time(time: SystemTime) -> Self {
+        let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Self {
+            seconds: elapsed.as_secs(),
+            nanoSeconds: elapsed.subsec_nanos(),
+        }
+    }
+}
+
+/// ApplicationContextUpdateNotification message, sent by the LCMP to the
+/// callbackReference of a device application when the user application
+/// instance information of an application context changes.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApplicationContextUpdateNotification {
+    /// Shall be set to "ApplicationContextUpdateNotification".
+    pub notificationType: String,
+    /// Uniquely identifies the application context in the MEC system.
+    pub contextId: String,
+    /// List of user application instance information.
+    pub userAppInstanceInfo: Vec<UserAppInstanceInfo>,
+}
+
+impl ApplicationContextUpdateNotification {
+    /// Create a notification with the current content of an application context.
+    pub fn from_app_context(app_context: &AppContext) -> Self {
+        Self {
+            notificationType: "ApplicationContextUpdateNotification".to_string(),
+            contextId: app_context.contextId.clone().unwrap_or_default(),
+            userAppInstanceInfo: app_context.appInfo.userAppInstanceInfo.clone(),
+        }
+    }
+}
+
+/// Location where an application is available, used in the ApplicationLocationAvailability message.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct AvailableLocation {
+    /// Location of the application.
+    pub appLocation: LocationConstraints,
+}
+
+/// ApplicationLocationAvailabilityNotification message, sent by the LCMP to
+/// the callbackReference of a device application that requested
+/// appLocationUpdates when new locations become available for the application.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApplicationLocationAvailabilityNotification {
+    /// Shall be set to "ApplicationLocationAvailabilityNotification".
+    pub notificationType: String,
+    /// Uniquely identifies the application context in the MEC system.
+    pub contextId: String,
+    /// Locations available to the MEC application.
+    pub availableLocations: Vec<AvailableLocation>,
+}
+
+impl ApplicationLocationAvailabilityNotification {
+    /// Create a notification of the locations available for an application context.
+    pub fn from_app_context(app_context: &AppContext, locations: &[LocationConstraints]) -> Self {
+        Self {
+            notificationType: "ApplicationLocationAvailabilityNotification".to_string(),
+            contextId: app_context.contextId.clone().unwrap_or_default(),
+            availableLocations: locations
+                .iter()
+                .map(|x| AvailableLocation {
+                    appLocation: x.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// appInfo field used in the ApplicationLocationAvailability message
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AppInfoLocationAvailability {
+    /// Name of the MEC application.
+    /// The length of the value shall not exceed 32 characters.
+    appName: String,
+    /// Provider of the MEC application.
+    /// The length of the value shall not exceed 32 characters.
+    appProvider: String,
+    /// Software version of the MEC application.
+    /// The length of the value shall not exceed 32 characters.
+    appSoftVersion: Option<String>,
+    /// Identifies the version of the application descriptor.
+    /// It is equivalent to the appDVersion defined in clause 6.2.1.2 of ETSI GS MEC 010-2
+    appDVersion: String,
+    /// Human readable description of the MEC application.
+    /// The length of the value shall not exceed 128 characters.
+    appDescription: String,
+    /// MEC application location constraints.
+    /// It shall only be included in the response.
+    #[serde(default)]
+    pub availableLocations: Vec<AvailableLocation>,
+    /// URI of the application package.
+    /// Included in the request if the application is not one in the ApplicationList.
+    appPackageSource: Option<String>,
+}
+
+/// ApplicationLocationAvailability message, used by the device application to
+/// obtain the locations available for the instantiation of an application.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApplicationLocationAvailability {
+    /// Uniquely identifies the device application.
+    /// The length of the value shall not exceed 32 characters.
+    associateDevAppId: String,
+    /// Application information.
+    pub appInfo: AppInfoLocationAvailability,
+}
+
+impl ApplicationLocationAvailability {
     pub fn valid_request(&self) -> Result<(), String> {
-        if let Err(x) = self.validate() {
-            return Err(x);
+        self.validate()?;
+        if !self.appInfo.availableLocations.is_empty() {
+            return Err(
+                "availableLocations cannot be present in a request ApplicationLocationAvailability"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    pub fn request_from_name_provider_version(name: &str, provider: &str, version: &str) -> Self {
+        Self {
+            associateDevAppId: Uuid::simple(Uuid::new_v4()).to_string(),
+            appInfo: AppInfoLocationAvailability {
+                appName: name.to_string(),
+                appProvider: provider.to_string(),
+                appSoftVersion: None,
+                appDVersion: version.to_string(),
+                appDescription: "".to_owned(),
+                availableLocations: vec![],
+                appPackageSource: None,
+            },
         }
+    }
+}
+
+impl AppContext {
+    pub fn valid_request(&self) -> Result<(), String> {
+        self.validate()?;
         if self.contextId.is_some() {
             return Err("contextId cannot be present in a request AppContext".to_string());
         }
-        if !self.appInfo.userAppInstanceInfo.is_empty() {
+        if self
+            .appInfo
+            .userAppInstanceInfo
+            .iter()
+            .any(|x| x.appInstanceId.is_some() || x.referenceURI.is_some() || x.qkdKeyIds.is_some())
+        {
             return Err(
-                "userAppInstanceInfo cannot be present in a request AppContext".to_string(),
+                "userAppInstanceInfo cannot be present in a request AppContext except for appLocation"
+                    .to_string(),
             );
         }
+        if self.expiresAt.is_some() {
+            return Err("expiresAt cannot be present in a request AppContext".to_string());
+        }
         Ok(())
     }
 
+    /// Return the location requested for the user application instance, if any.
+    pub fn requested_location(&self) -> Option<&LocationConstraints> {
+        self.appInfo
+            .userAppInstanceInfo
+            .iter()
+            .find_map(|x| x.appLocation.as_ref())
+    }
+
+    /// Assign a new user application instance with the given reference URI.
+    /// The location requested, if any, is retained in the response.
+    pub fn assign_reference_uri(&mut self, reference_uri: &str) {
+        let mut info = UserAppInstanceInfo::from_reference_uri(reference_uri);
+        info.appLocation = self.requested_location().cloned();
+        self.appInfo.userAppInstanceInfo = vec![info];
+    }
+
+    /// Set the IDs of the QKD keys of the user application instances, in
+    /// the same order, leaving unchanged those for which none are given.
+    pub fn assign_qkd_key_ids(&mut self, key_ids: &[Option<Vec<String>>]) {
+        for (info, ids) in self.appInfo.userAppInstanceInfo.iter_mut().zip(key_ids) {
+            if ids.is_some() {
+                info.qkdKeyIds = ids.clone();
+            }
+        }
+    }
+
     pub fn request_from_name_provider(name: &str, provider: &str) -> Self {
         Self {
             contextId: None,
@@ -334,15 +540,37 @@ impl AppContext {
                 userAppInstanceInfo: vec![],
                 appPackageSource: None,
             },
+            expiresAt: None,
+            owner: None,
         }
     }
 
+    /// Return the identifier of the device application.
+    pub fn associate_dev_app_id(&self) -> &str {
+        &self.associateDevAppId
+    }
+
+    /// Return the provider of the MEC application.
+    pub fn app_provider(&self) -> &str {
+        &self.appInfo.appProvider
+    }
+
+    /// Return true if the two contexts are identical, except for the
+    /// callbackReference and the fields that are filled by the server and
+    /// not part of ETSI MEC, i.e., expiresAt, owner, and qkdKeyIds.
     pub fn identical_except_callback_reference(&self, another: &Self) -> bool {
+        let without_keys = |x: &AppInfoContext| {
+            let mut x = x.clone();
+            for info in &mut x.userAppInstanceInfo {
+                info.qkdKeyIds = None;
+            }
+            x
+        };
         self.contextId == another.contextId
             && self.associateDevAppId == another.associateDevAppId
             && self.appLocationUpdates == another.appLocationUpdates
             && self.appAutoInstantiation == another.appAutoInstantiation
-            && self.appInfo == another.appInfo
+            && without_keys(&self.appInfo) == without_keys(&another.appInfo)
     }
 }
 
@@ -419,12 +647,8 @@ impl ApplicationList {
                     None => true,
                 }
                 && (vendor_ids.is_empty()
-                    || vendor_ids.contains(
-                        &x.vendorSpecificExt
-                            .as_ref()
-                            .unwrap_or_else(|| &empty)
-                            .vendorId,
-                    ))
+                    || vendor_ids
+                        .contains(&x.vendorSpecificExt.as_ref().unwrap_or(&empty).vendorId))
             {
                 ret.push(x.clone());
             }
@@ -434,7 +658,139 @@ impl ApplicationList {
     }
 }
 
-impl Validate for ProblemDetails {}
+impl ApplicationList {
+    /// Return the response to an ApplicationLocationAvailability request, with
+    /// the locations of the first matching application as available locations,
+    /// or None if no application matches.
+    pub fn location_availability(
+        &self,
+        request: &ApplicationLocationAvailability,
+    ) -> Option<ApplicationLocationAvailability> {
+        let info = &request.appInfo;
+        let app = self.appList.iter().find(|x| {
+            x.appInfo.appName == info.appName
+                && x.appInfo.appProvider == info.appProvider
+                && x.appInfo.appDVersion == info.appDVersion
+                && match &info.appSoftVersion {
+                    Some(v) => *v == x.appInfo.appSoftVersion,
+                    None => true,
+                }
+        })?;
+        let mut response = request.clone();
+        response.appInfo.availableLocations = app
+            .appInfo
+            .appLocation
+            .iter()
+            .map(|x| AvailableLocation {
+                appLocation: x.clone(),
+            })
+            .collect();
+        Some(response)
+    }
+}
+
+impl AppList {
+    /// Create an element of the ApplicationList without vendor specific information.
+    pub fn from_app_info(app_info: AppInfoList) -> Self {
+        Self {
+            appInfo: app_info,
+            vendorSpecificExt: None,
+        }
+    }
+}
+
+impl AppInfoList {
+    /// Return true if the application information in an AppContext refers to this application.
+    /// If present, the appDId is used, otherwise all the other mandatory fields must match.
+    pub fn matches_context(&self, info: &AppInfoContext) -> bool {
+        match &info.appDId {
+            Some(x) => *x == self.appDId,
+            None => {
+                info.appName == self.appName
+                    && info.appProvider == self.appProvider
+                    && info.appDVersion == self.appDVersion
+                    && match &info.appSoftVersion {
+                        Some(x) => *x == self.appSoftVersion,
+                        None => true,
+                    }
+            }
+        }
+    }
+
+    /// Return the key that uniquely identifies the application in the system,
+    /// obtained as the concatenation of appDId, appName, appProvider,
+    /// appSoftVersion, and appDVersion.
+    pub fn app_key(&self) -> String {
+        format!(
+            "{}{}{}{}{}",
+            self.appDId, self.appName, self.appProvider, self.appSoftVersion, self.appDVersion
+        )
+    }
+}
+
+impl Validate for ProblemDetails {
+    fn validate(&self) -> Result<(), String> {
+        let mut problems: Vec<String> = vec![];
+        if !(100..600).contains(&self.status) {
+            problems.push(format!("invalid status: {}", self.status));
+        }
+        if self.r#type.as_deref().is_some_and(|x| x.is_empty()) {
+            problems.push("type cannot be empty".to_string());
+        }
+        check(problems)
+    }
+}
+
+/// Return true if the point (x, y) is inside the ring, using the even-odd rule.
+fn ring_contains(ring: &[Vec<f64>], x: f64, y: f64) -> bool {
+    let mut inside = false;
+    for (i, p) in ring.iter().enumerate() {
+        let q = &ring[(i + ring.len() - 1) % ring.len()];
+        if (p[1] > y) != (q[1] > y) && x < (q[0] - p[0]) * (y - p[1]) / (q[1] - p[1]) + p[0] {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+impl Polygon {
+    /// Return true if the point, given as longitude and latitude, is inside
+    /// the exterior ring and outside all the holes.
+    pub fn contains(&self, point: &[f64]) -> bool {
+        match (self.coordinates.split_first(), point) {
+            (Some((exterior, holes)), [x, y]) => {
+                ring_contains(exterior, *x, *y)
+                    && !holes.iter().any(|hole| ring_contains(hole, *x, *y))
+            }
+            _ => false,
+        }
+    }
+}
+
+impl LocationConstraints {
+    /// Return true if a location, e.g., that of a platform, satisfies these constraints:
+    /// - with an area, all the points of the exterior ring of the area of
+    ///   the location must be inside the area, where a location at a given
+    ///   position can be expressed as an area with a single point;
+    /// - otherwise, the location must have the same countryCode and all the
+    ///   civicAddressElement items of the constraints.
+    pub fn contains(&self, location: &LocationConstraints) -> bool {
+        match (&self.area, &location.area) {
+            (Some(area), Some(other)) => other
+                .coordinates
+                .first()
+                .is_some_and(|ring| !ring.is_empty() && ring.iter().all(|x| area.contains(x))),
+            (None, None) => {
+                self.countryCode == location.countryCode
+                    && self
+                        .civicAddressElement
+                        .iter()
+                        .all(|x| location.civicAddressElement.contains(x))
+            }
+            _ => false,
+        }
+    }
+}
 
 impl Validate for Polygon {
     fn validate(&self) -> Result<(), String> {
@@ -477,10 +833,7 @@ impl Validate for LocationConstraints {
                     Err("Empty civicAddressElement in LocalConstraints".to_string())
                 } else {
                     for c in &self.civicAddressElement {
-                        let v = c.validate();
-                        if v.is_err() {
-                            return v;
-                        }
+                        c.validate()?;
                     }
                     Ok(())
                 }
@@ -519,9 +872,8 @@ impl Validate for AppInfoList {
         for c in &self.appLocation {
             add_problem(c, &mut problems);
         }
-        match &self.appCharcs {
-            Some(appCharcs) => add_problem(appCharcs, &mut problems),
-            None => (),
+        if let Some(appCharcs) = &self.appCharcs {
+            add_problem(appCharcs, &mut problems);
         }
 
         check(problems)
@@ -530,10 +882,10 @@ impl Validate for AppInfoList {
 
 impl Validate for UserAppInstanceInfo {
     fn validate(&self) -> Result<(), String> {
-        return match &self.appLocation {
+        match &self.appLocation {
             Some(x) => x.validate(),
             None => Ok(()),
-        };
+        }
     }
 }
 
@@ -578,9 +930,8 @@ impl Validate for AppList {
     fn validate(&self) -> Result<(), String> {
         let mut problems: Vec<String> = vec![];
         add_problem(&self.appInfo, &mut problems);
-        match &self.vendorSpecificExt {
-            Some(x) => add_problem(x, &mut problems),
-            None => (),
+        if let Some(x) = &self.vendorSpecificExt {
+            add_problem(x, &mut problems);
         }
         check(problems)
     }
@@ -612,12 +963,88 @@ impl Validate for AppContext {
     }
 }
 
+impl Validate for AvailableLocation {
+    fn validate(&self) -> Result<(), String> {
+        self.appLocation.validate()
+    }
+}
+
+impl Validate for AppInfoLocationAvailability {
+    fn validate(&self) -> Result<(), String> {
+        let mut problems: Vec<String> = vec![];
+        if self.appName.len() > 32 {
+            problems.push("appName is too long".to_string());
+        }
+        if self.appProvider.len() > 32 {
+            problems.push("appProvider is too long".to_string());
+        }
+        if let Some(x) = &self.appSoftVersion {
+            if x.len() > 32 {
+                problems.push("appSoftVersion is too long".to_string());
+            }
+        }
+        if self.appDescription.len() > 128 {
+            problems.push("appDescription is too long".to_string());
+        }
+        for l in &self.availableLocations {
+            add_problem(l, &mut problems);
+        }
+        check(problems)
+    }
+}
+
+impl Validate for ApplicationLocationAvailability {
+    fn validate(&self) -> Result<(), String> {
+        let mut problems: Vec<String> = vec![];
+        if self.associateDevAppId.len() > 32 {
+            problems.push("associateDevAppId is too long".to_string());
+        }
+        add_problem(&self.appInfo, &mut problems);
+        check(problems)
+    }
+}
+
+impl Validate for ApplicationContextUpdateNotification {
+    fn validate(&self) -> Result<(), String> {
+        let mut problems: Vec<String> = vec![];
+        if self.notificationType != "ApplicationContextUpdateNotification" {
+            problems.push(format!(
+                "invalid notificationType: {}",
+                self.notificationType
+            ));
+        }
+        if self.contextId.is_empty() || self.contextId.len() > 32 {
+            problems.push("invalid contextId".to_string());
+        }
+        for i in &self.userAppInstanceInfo {
+            add_problem(i, &mut problems);
+        }
+        check(problems)
+    }
+}
+
+impl Validate for ApplicationLocationAvailabilityNotification {
+    fn validate(&self) -> Result<(), String> {
+        let mut problems: Vec<String> = vec![];
+        if self.notificationType != "ApplicationLocationAvailabilityNotification" {
+            problems.push(format!(
+                "invalid notificationType: {}",
+                self.notificationType
+            ));
+        }
+        if self.contextId.is_empty() || self.contextId.len() > 32 {
+            problems.push("invalid contextId".to_string());
+        }
+        for l in &self.availableLocations {
+            add_problem(l, &mut problems);
+        }
+        check(problems)
+    }
+}
+
 fn service_cont_valid(s: Option<u32>) -> bool {
     match s {
-        Some(x) => match x {
-            0 | 1 => true,
-            _ => false,
-        },
+        Some(x) => matches!(x, 0 | 1),
         None => true,
     }
 }
@@ -663,7 +1090,10 @@ fn service_cont_to_string(s: Option<u32>) -> String {
 
 impl Display for ProblemDetails {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} ({})", &self.status, &self.detail)
+        match &self.title {
+            Some(title) => write!(f, "{} {} ({})", &self.status, title, &self.detail),
+            None => write!(f, "{} ({})", &self.status, &self.detail),
+        }
     }
 }
 
@@ -793,6 +1223,7 @@ impl Display for AppInfoContext {
     }
 }
 
+#[cfg(test)]
 impl AppInfoList {
     fn empty() -> Self {
         Self {
@@ -808,6 +1239,7 @@ impl AppInfoList {
     }
 }
 
+#[cfg(test)]
 impl AppInfoContext {
     fn empty() -> Self {
         Self {
@@ -864,10 +1296,42 @@ impl Display for ApplicationListInfo {
     }
 }
 
+impl Display for ApplicationLocationAvailabilityNotification {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let locations: Vec<String> = self
+            .availableLocations
+            .iter()
+            .map(|x| x.appLocation.to_string())
+            .collect();
+        write!(
+            f,
+            "{} contextId {}, availableLocations: {}",
+            self.notificationType,
+            self.contextId,
+            locations.join(",")
+        )
+    }
+}
+
+impl Display for ApplicationLocationAvailability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "associateDevAppId {}, appName {}, appProvider {}, appSoftVersion {}, appDVersion {}, {} available locations",
+            self.associateDevAppId,
+            self.appInfo.appName,
+            self.appInfo.appProvider,
+            value_or_not_specified(&self.appInfo.appSoftVersion),
+            self.appInfo.appDVersion,
+            self.appInfo.availableLocations.len()
+        )
+    }
+}
+
 impl Display for AppContext {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         if let Some(x) = &self.contextId {
-            write!(f, "contextId {}, ", x.to_string())?;
+            write!(f, "contextId {}, ", x)?;
         }
         write!(f, "associatedDevAppId {}, ", self.associateDevAppId)?;
         if let Some(x) = &self.callbackReference {
@@ -887,6 +1351,23 @@ impl Display for AppContext {
     }
 }
 
+impl Display for ApplicationContextUpdateNotification {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let user_app_instance_info: Vec<String> = self
+            .userAppInstanceInfo
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        write!(
+            f,
+            "{} contextId {}, userAppInstanceInfo: {}",
+            self.notificationType,
+            self.contextId,
+            user_app_instance_info.join(",")
+        )
+    }
+}
+
 pub fn application_list_from_file(file: &mut File) -> std::io::Result<ApplicationList> {
     let mut content = String::new();
     file.read_to_string(&mut content)?;
@@ -897,17 +1378,16 @@ pub fn application_list_from_file(file: &mut File) -> std::io::Result<Applicatio
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
+    use std::io::Write;
+    use std::path::Path;
 
     fn open_file(filename: &str) -> Result<File, String> {
         match Path::new(filename).exists() {
             true => Err(format!("will not overwrite: {}", filename)),
             false => match File::create(filename) {
                 Ok(x) => Ok(x),
-                Err(err) => Err(format!(
-                    "could not open file '{}': {}",
-                    filename,
-                    err.to_string()
-                )),
+                Err(err) => Err(format!("could not open file '{}': {}", filename, err)),
             },
         }
     }
@@ -964,6 +1444,7 @@ mod tests {
                     civicAddressElement: vec![],
                     area: Some(default_polygon()),
                 }),
+                qkdKeyIds: None,
             }],
             appPackageSource: Some("test_appPackageSource".to_owned()),
         }
@@ -979,6 +1460,75 @@ mod tests {
         assert!(polygon.validate().is_err());
     }
 
+    #[test]
+    fn test_message_polygon_contains() {
+        // exterior ring with one hole, as in the example of RFC 7946
+        let polygon: Polygon = serde_json::from_str(
+            r#"{"coordinates":[
+                [[100.0,0.0],[101.0,0.0],[101.0,1.0],[100.0,1.0],[100.0,0.0]],
+                [[100.8,0.8],[100.8,0.2],[100.2,0.2],[100.2,0.8],[100.8,0.8]]]}"#,
+        )
+        .expect("could not deserialize");
+        assert!(polygon.contains(&[100.1, 0.5]));
+        assert!(polygon.contains(&[100.9, 0.9]));
+        assert!(!polygon.contains(&[100.5, 0.5]));
+        assert!(!polygon.contains(&[101.5, 0.5]));
+        assert!(!polygon.contains(&[100.5, -0.1]));
+        assert!(!polygon.contains(&[100.1]));
+
+        // non-convex exterior ring
+        let polygon = Polygon {
+            coordinates: vec![vec![
+                vec![0.0, 0.0],
+                vec![2.0, 0.0],
+                vec![2.0, 2.0],
+                vec![1.0, 1.0],
+                vec![0.0, 2.0],
+            ]],
+        };
+        assert!(polygon.contains(&[0.5, 1.2]));
+        assert!(!polygon.contains(&[1.0, 1.5]));
+        assert!(!Polygon {
+            coordinates: vec![]
+        }
+        .contains(&[0.0, 0.0]));
+    }
+
+    #[test]
+    fn test_message_location_constraints_contains() {
+        let civic = |country: &str, elements: &[(i32, &str)]| LocationConstraints {
+            countryCode: Some(country.to_string()),
+            civicAddressElement: elements
+                .iter()
+                .map(|x| CivicAddressElement {
+                    caType: x.0,
+                    caValue: x.1.to_string(),
+                })
+                .collect(),
+            area: None,
+        };
+        let area = |coordinates: Vec<Vec<Vec<f64>>>| LocationConstraints {
+            countryCode: None,
+            civicAddressElement: vec![],
+            area: Some(Polygon { coordinates }),
+        };
+
+        let pisa = civic("IT", &[(1, "Tuscany"), (3, "Pisa")]);
+        assert!(civic("IT", &[(3, "Pisa")]).contains(&pisa));
+        assert!(civic("IT", &[]).contains(&pisa));
+        assert!(!civic("IT", &[(3, "Florence")]).contains(&pisa));
+        assert!(!civic("FR", &[(3, "Pisa")]).contains(&pisa));
+        assert!(!pisa.contains(&civic("IT", &[(3, "Pisa")])));
+
+        let default_area = area(default_polygon().coordinates);
+        assert!(default_area.contains(&area(vec![vec![vec![0.8, 0.9]]])));
+        assert!(default_area.contains(&area(vec![vec![vec![0.8, 0.9], vec![0.9, 0.95]]])));
+        assert!(!default_area.contains(&area(vec![vec![vec![0.8, 0.9], vec![0.05, 0.05]]])));
+        assert!(!default_area.contains(&area(vec![])));
+        assert!(!default_area.contains(&pisa));
+        assert!(!pisa.contains(&default_area));
+    }
+
     #[test]
     fn test_message_civic_address_element() {
         let mut c = CivicAddressElement {
@@ -1136,6 +1686,8 @@ mod tests {
             appLocationUpdates: None,
             appAutoInstantiation: None,
             appInfo: default_app_info_context(),
+            expiresAt: None,
+            owner: None,
         };
         assert_eq!(Ok(()), context.validate());
         println!("{}", context);
@@ -1157,16 +1709,128 @@ mod tests {
                 .validate()
                 .is_ok()
         );
+
+        // only the appLocation can be present in the userAppInstanceInfo of a request
+        let mut request = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        let mut info = default_app_info_context().userAppInstanceInfo[0].clone();
+        request.appInfo.userAppInstanceInfo.push(info.clone());
+        assert!(request.valid_request().is_err());
+        info.appInstanceId = None;
+        info.referenceURI = None;
+        request.appInfo.userAppInstanceInfo = vec![info];
+        assert_eq!(Ok(()), request.valid_request());
+        request.appInfo.userAppInstanceInfo[0].qkdKeyIds = Some(vec![]);
+        assert!(request.valid_request().is_err());
+        request.appInfo.userAppInstanceInfo[0].qkdKeyIds = None;
+        assert!(request.requested_location().is_some());
+        request.assign_reference_uri("test_referenceURI");
+        assert!(request.valid_request().is_err());
+        assert_eq!(1, request.appInfo.userAppInstanceInfo.len());
+        assert!(request.requested_location().is_some());
+    }
+
+    #[test]
+    fn test_application_context_update_notification() {
+        let mut context = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        context.contextId = Some("test_contextId".to_owned());
+        context
+            .appInfo
+            .userAppInstanceInfo
+            .push(UserAppInstanceInfo::from_reference_uri("test_referenceURI"));
+        let mut n = ApplicationContextUpdateNotification::from_app_context(&context);
+        assert_eq!(Ok(()), n.validate());
+        assert_eq!(
+            Some("test_referenceURI".to_string()),
+            n.userAppInstanceInfo[0].referenceURI
+        );
+        println!("{}", n);
+
+        n.notificationType = "AnotherNotification".to_string();
+        assert!(n.validate().is_err());
+    }
+
+    #[test]
+    fn test_application_location_availability() {
+        let a = ApplicationList {
+            appList: vec![AppList {
+                appInfo: default_app_info_list(),
+                vendorSpecificExt: None,
+            }],
+        };
+        let mut req = ApplicationLocationAvailability::request_from_name_provider_version(
+            "test_appName",
+            "test_appProvider",
+            "test_appDVersion",
+        );
+        assert_eq!(Ok(()), req.valid_request());
+        println!("{}", req);
+
+        let res = a.location_availability(&req).expect("no matching app");
+        assert_eq!(Ok(()), res.validate());
+        assert!(res.valid_request().is_err());
+        assert_eq!(1, res.appInfo.availableLocations.len());
+        assert!(res.appInfo.availableLocations[0].appLocation.area.is_some());
+        println!("{}", res);
+
+        req.appInfo.appSoftVersion = Some("test_appSoftVersion".to_owned());
+        assert!(a.location_availability(&req).is_some());
+        req.appInfo.appSoftVersion = Some("another_appSoftVersion".to_owned());
+        assert!(a.location_availability(&req).is_none());
+
+        let mut long = "".to_string();
+        (0..33).for_each(|_| long.push('a'));
+        req.associateDevAppId = long;
+        assert!(req.validate().is_err());
+
+        let res: ApplicationLocationAvailability = serde_json::from_str(
+            r#"{"associateDevAppId":"1234","appInfo":{"appName":"n","appProvider":"p","appDVersion":"v","appDescription":"d"}}"#,
+        )
+        .expect("could not deserialize");
+        assert_eq!(Ok(()), res.valid_request());
+    }
+
+    #[test]
+    fn test_application_location_availability_notification() {
+        let mut context = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        context.contextId = Some("test_contextId".to_owned());
+        let location = default_app_info_list().appLocation[0].clone();
+        let mut n =
+            ApplicationLocationAvailabilityNotification::from_app_context(&context, &[location]);
+        assert_eq!(Ok(()), n.validate());
+        assert_eq!(1, n.availableLocations.len());
+        println!("{}", n);
+
+        n.notificationType = "ApplicationContextUpdateNotification".to_string();
+        assert!(n.validate().is_err());
     }
 
     #[test]
     fn test_message_problem_details() {
-        let p = ProblemDetails {
+        let mut p = ProblemDetails {
+            r#type: None,
+            title: None,
             status: 401,
             detail: "not authorized".to_owned(),
+            instance: None,
         };
         assert_eq!(Ok(()), p.validate());
         println!("{}", p);
+        assert_eq!(
+            r#"{"status":401,"detail":"not authorized"}"#,
+            serde_json::to_string(&p).unwrap()
+        );
+
+        p.r#type = Some("https://example.com/problems#unauthorized".to_owned());
+        p.title = Some("Unauthorized".to_owned());
+        p.instance = Some("/dev_app/v1/app_contexts".to_owned());
+        assert_eq!(Ok(()), p.validate());
+        println!("{}", p);
+        let j = serde_json::to_value(&p).unwrap();
+        assert_eq!("https://example.com/problems#unauthorized", j["type"]);
+        assert_eq!("/dev_app/v1/app_contexts", j["instance"]);
+
+        p.status = 42;
+        assert!(p.validate().is_err());
     }
 
     #[test]
@@ -1182,7 +1846,7 @@ mod tests {
         match open_file("application_list.json") {
             Ok(mut f) => {
                 let j = serde_json::to_string(&a).expect("could not serialize");
-                f.write(j.as_bytes()).expect("could not write to file");
+                f.write_all(j.as_bytes()).expect("could not write to file");
                 println!("written:\n{}", a);
             }
             Err(err) => println!("{}", err),
@@ -1208,13 +1872,15 @@ mod tests {
                 userAppInstanceInfo: vec![],
                 appPackageSource: None,
             },
+            expiresAt: None,
+            owner: None,
         };
         assert_eq!(Ok(()), context.validate());
 
         match open_file("app_context.json") {
             Ok(mut f) => {
                 let j = serde_json::to_string(&context).expect("could not serialize");
-                f.write(j.as_bytes()).expect("could not write to file");
+                f.write_all(j.as_bytes()).expect("could not write to file");
                 println!("written:\n{}", context);
             }
             Err(err) => println!("{}", err),
@@ -1250,7 +1916,7 @@ mod tests {
                 let mut fields = String::from("");
                 self.fields
                     .iter()
-                    .for_each(|x| fields.push_str(format!("\n{}", x.to_string()).as_str()));
+                    .for_each(|x| fields.push_str(format!("\n{}", x).as_str()));
                 write!(f, "type {}, fields: {}", self.mtype, fields)
             }
         }
@@ -1267,7 +1933,7 @@ mod tests {
         println!("original:\n{}\n", msg_ser);
         let msg_out: ExampleMessage =
             serde_json::from_str(&msg_ser.to_string()).expect("could not deserialize");
-        println!("structure:\n{}\n", msg_out.to_string());
+        println!("structure:\n{}\n", msg_out);
         println!(
             "serialized:\n{}\n",
             serde_json::to_string(&msg_out).expect("could not serialize")
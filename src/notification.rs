@@ -0,0 +1,331 @@
+//! Notifications sent by the LCMP to the device apps.
+
+use log::{debug, warn};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Interface of a Notifier, which delivers messages to the callbackReference URI
+/// of device apps.
+pub trait Notifier {
+    /// Send asynchronously a JSON-encoded message to the given URI.
+    /// Return an error if the message cannot be queued for sending.
+    fn notify(&self, callback_reference: &str, body: String) -> Result<(), String>;
+}
+
+/// Policy to retry sending a notification that failed.
+#[derive(Clone, Copy)]
+pub struct RetryPolicy {
+    /// Maximum number of retries after the first attempt.
+    pub max_retries: u32,
+    /// Waiting time before the first retry.
+    pub initial_backoff: Duration,
+    /// Maximum waiting time between consecutive retries.
+    /// The waiting time is doubled after every failed attempt, up to this value.
+    pub max_backoff: Duration,
+    /// Timeout of every single attempt.
+    pub timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Notification waiting to be sent.
+struct PendingNotification {
+    callback_reference: String,
+    body: String,
+    /// Number of attempts already made.
+    attempts: u32,
+    /// Time when the next attempt is due.
+    due: Instant,
+}
+
+/// Number of worker threads of an HttpNotifier.
+const NOTIFIER_WORKERS: usize = 4;
+
+/// Maximum number of notifications queued for every worker thread of an HttpNotifier.
+const NOTIFIER_QUEUE_SIZE: usize = 1000;
+
+/// Number of notifications queued or waiting to be retried, which can be waited for.
+type Outstanding = Arc<(Mutex<usize>, Condvar)>;
+
+/// Notifier that POSTs the messages via HTTP from a small pool of worker threads.
+/// The notifications to the same callbackReference are always handled by the
+/// same worker, in order, so that a slow or unreachable end-point only delays
+/// those assigned to its worker.
+/// A notification is retried with exponential backoff until the remote
+/// end-point returns a success status code or the maximum number of
+/// retries is reached.
+pub struct HttpNotifier {
+    senders: Vec<SyncSender<PendingNotification>>,
+    outstanding: Outstanding,
+}
+
+impl HttpNotifier {
+    /// Create a notifier and start its worker threads, which terminate when
+    /// the notifier is dropped and all the pending notifications have been handled.
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self::with_workers(policy, NOTIFIER_WORKERS, NOTIFIER_QUEUE_SIZE)
+    }
+
+    /// Create a notifier with the given number of worker threads, each with
+    /// a queue of at most queue_size notifications.
+    pub fn with_workers(policy: RetryPolicy, workers: usize, queue_size: usize) -> Self {
+        let outstanding = Outstanding::default();
+        let senders = (0..workers.max(1))
+            .map(|_| {
+                let (sender, receiver) = sync_channel::<PendingNotification>(queue_size);
+                let outstanding = outstanding.clone();
+                std::thread::spawn(move || Self::work(policy, receiver, outstanding));
+                sender
+            })
+            .collect();
+        Self {
+            senders,
+            outstanding,
+        }
+    }
+
+    /// Wait until all the notifications have been sent or given up.
+    /// Return false if the timeout expires.
+    pub fn wait_idle(&self, timeout: Duration) -> bool {
+        let (count, cvar) = self.outstanding.as_ref();
+        !cvar
+            .wait_timeout_while(count.lock().unwrap(), timeout, |x| *x > 0)
+            .unwrap()
+            .1
+            .timed_out()
+    }
+
+    /// Return the index of the worker handling the notifications to a callbackReference.
+    fn worker(&self, callback_reference: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        callback_reference.hash(&mut hasher);
+        (hasher.finish() % self.senders.len() as u64) as usize
+    }
+
+    /// Decrease the number of outstanding notifications.
+    fn done(outstanding: &Outstanding) {
+        let (count, cvar) = outstanding.as_ref();
+        let mut count = count.lock().unwrap();
+        *count = count.saturating_sub(1);
+        cvar.notify_all();
+    }
+
+    /// Body of a worker thread.
+    fn work(
+        policy: RetryPolicy,
+        receiver: Receiver<PendingNotification>,
+        outstanding: Outstanding,
+    ) {
+        let agent = ureq::AgentBuilder::new().timeout(policy.timeout).build();
+        let mut pending: Vec<PendingNotification> = vec![];
+        let mut connected = true;
+        while connected || !pending.is_empty() {
+            // Wait for a new notification or until the next one is due.
+            let now = Instant::now();
+            let next_due = pending.iter().map(|x| x.due).min();
+            let res = match next_due {
+                Some(due) if !connected => {
+                    std::thread::sleep(due.saturating_duration_since(now));
+                    Err(RecvTimeoutError::Timeout)
+                }
+                Some(due) => receiver.recv_timeout(due.saturating_duration_since(now)),
+                None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match res {
+                Ok(x) => pending.push(x),
+                Err(RecvTimeoutError::Disconnected) => connected = false,
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+
+            // Send all the notifications that are due.
+            let now = Instant::now();
+            let (due, not_due): (Vec<_>, Vec<_>) = pending.into_iter().partition(|x| x.due <= now);
+            pending = not_due;
+            for mut x in due {
+                match Self::send(&agent, &x) {
+                    Ok(_) => {
+                        debug!("notification sent to {}", x.callback_reference);
+                        Self::done(&outstanding);
+                    }
+                    Err(err) => {
+                        x.attempts += 1;
+                        if x.attempts > policy.max_retries {
+                            warn!(
+                                "could not send notification to {}, giving up: {}",
+                                x.callback_reference, err
+                            );
+                            Self::done(&outstanding);
+                        } else {
+                            let backoff = policy
+                                .initial_backoff
+                                .saturating_mul(1 << (x.attempts - 1).min(16))
+                                .min(policy.max_backoff);
+                            debug!(
+                                "could not send notification to {}, retrying in {:?}: {}",
+                                x.callback_reference, backoff, err
+                            );
+                            x.due = Instant::now() + backoff;
+                            pending.push(x);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn send(agent: &ureq::Agent, notification: &PendingNotification) -> Result<(), String> {
+        match agent
+            .post(&notification.callback_reference)
+            .set("Content-Type", "application/json")
+            .send_string(&notification.body)
+        {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+}
+
+impl Notifier for HttpNotifier {
+    /// Queue a notification, failing if the queue of its worker is full.
+    fn notify(&self, callback_reference: &str, body: String) -> Result<(), String> {
+        *self.outstanding.0.lock().unwrap() += 1;
+        let res = self.senders[self.worker(callback_reference)].try_send(PendingNotification {
+            callback_reference: callback_reference.to_string(),
+            body,
+            attempts: 0,
+            due: Instant::now(),
+        });
+        match res {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                Self::done(&self.outstanding);
+                match err {
+                    TrySendError::Full(_) => Err("notification queue full".to_string()),
+                    TrySendError::Disconnected(_) => Err("notifier terminated".to_string()),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::httpserver::stand_in::HttpStandIn;
+
+    fn fast_policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(40),
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    #[test]
+    fn test_http_notifier() {
+        let server = HttpStandIn::start(vec![]);
+        let notifier = HttpNotifier::new(fast_policy(0));
+        assert!(notifier
+            .notify(
+                format!("{}/callback", server.url()).as_str(),
+                "{}".to_string(),
+            )
+            .is_ok());
+        assert!(server.wait_for(1, Duration::from_secs(5)));
+        let requests = server.requests();
+        assert_eq!("POST", requests[0].method);
+        assert_eq!("/callback", requests[0].path);
+        assert_eq!("{}", requests[0].body);
+        assert_eq!(Some("application/json"), requests[0].header("content-type"));
+    }
+
+    #[test]
+    fn test_http_notifier_retries() {
+        // two failures then success
+        let server = HttpStandIn::start(vec![
+            (500, String::new()),
+            (503, String::new()),
+            (204, String::new()),
+        ]);
+        let notifier = HttpNotifier::new(fast_policy(5));
+        assert!(notifier
+            .notify(server.url().as_str(), "{\"a\":1}".to_string())
+            .is_ok());
+        assert!(notifier.wait_idle(Duration::from_secs(5)));
+        assert_eq!(3, server.requests().len());
+
+        // always failing: the notifier gives up after the maximum number of retries
+        let server = HttpStandIn::start(vec![(500, String::new()); 10]);
+        let notifier = HttpNotifier::new(fast_policy(2));
+        assert!(notifier
+            .notify(server.url().as_str(), "{}".to_string())
+            .is_ok());
+        assert!(notifier.wait_idle(Duration::from_secs(5)));
+        assert_eq!(3, server.requests().len());
+    }
+
+    #[test]
+    fn test_http_notifier_pending_on_drop() {
+        // pending notifications are delivered even if the notifier is dropped
+        let server = HttpStandIn::start(vec![(500, String::new())]);
+        let notifier = HttpNotifier::new(fast_policy(1));
+        assert!(notifier
+            .notify(server.url().as_str(), "{}".to_string())
+            .is_ok());
+        drop(notifier);
+        assert!(server.wait_for(2, Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_http_notifier_slow_destination() {
+        // a callback end-point that accepts the connections but never answers
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let slow = format!("http://{}/slow", listener.local_addr().unwrap());
+        let server = HttpStandIn::start(vec![]);
+        let notifier = HttpNotifier::with_workers(fast_policy(0), 2, 10);
+
+        // pick a fast destination handled by another worker
+        let fast = (0..)
+            .map(|i| format!("{}/callback{}", server.url(), i))
+            .find(|x| notifier.worker(x) != notifier.worker(&slow))
+            .unwrap();
+
+        assert!(notifier.notify(&slow, "{}".to_string()).is_ok());
+        let _stream = listener.accept().unwrap();
+        assert!(notifier.notify(&fast, "{}".to_string()).is_ok());
+        assert!(server.wait_for(1, Duration::from_millis(500)));
+        assert!(!notifier.wait_idle(Duration::from_millis(10)));
+
+        // the slow notification is given up after the timeout
+        assert!(notifier.wait_idle(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_http_notifier_queue_full() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let slow = format!("http://{}/slow", listener.local_addr().unwrap());
+        let notifier = HttpNotifier::with_workers(fast_policy(0), 1, 1);
+
+        // the worker is busy with the first notification, the second one is queued
+        assert!(notifier.notify(&slow, "{}".to_string()).is_ok());
+        let _stream = listener.accept().unwrap();
+        assert!(notifier.notify(&slow, "{}".to_string()).is_ok());
+        assert_eq!(
+            Err("notification queue full".to_string()),
+            notifier.notify(&slow, "{}".to_string())
+        );
+    }
+}
@@ -0,0 +1,661 @@
+//! Messages and REST client according to the following specifications:
+//! ETSI GS QKD 014 V1.1.1 (2019-02)
+
+#![allow(non_snake_case)]
+
+use crate::kme::SAE_ID_HEADER;
+use crate::kvs::{Platform, PlatformQkd, SharedKvs, TelemetryValue};
+use crate::messages::Validate;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+/// Status data format, returned by the Get status method.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Status {
+    /// KME ID of the KME.
+    pub source_KME_ID: String,
+    /// KME ID of the target KME.
+    pub target_KME_ID: String,
+    /// SAE ID of the calling master SAE.
+    pub master_SAE_ID: String,
+    /// SAE ID of the specified slave SAE.
+    pub slave_SAE_ID: String,
+    /// Default size of key the KME can deliver to the SAE (in bit).
+    pub key_size: u32,
+    /// Number of stored keys KME can deliver to the SAE.
+    pub stored_key_count: u32,
+    /// Maximum number of stored_key_count.
+    pub max_key_count: u32,
+    /// Maximum number of keys per request.
+    pub max_key_per_request: u32,
+    /// Maximum size of key the KME can deliver to the SAE (in bit).
+    pub max_key_size: u32,
+    /// Minimum size of key the KME can deliver to the SAE (in bit).
+    pub min_key_size: u32,
+    /// Maximum number of additional_slave_SAE_IDs the KME allows. "0" when
+    /// the KME does not support key multicast.
+    pub max_SAE_ID_count: u32,
+    /// For future use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_extension: Option<serde_json::Value>,
+}
+
+/// Key request data format, used for the Get key method.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct KeyRequest {
+    /// Number of keys requested, default value is 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub number: Option<u32>,
+    /// Size of each key in bits, default value is defined as key_size in Status.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u32>,
+    /// Array of IDs of slave SAEs, used for specifying two or more slave SAEs
+    /// to share identical keys.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub additional_slave_SAE_IDs: Option<Vec<String>>,
+    /// Array of extension parameters specified as name/value pairs that KME
+    /// shall handle or return an error.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extension_mandatory: Option<Vec<serde_json::Value>>,
+    /// Array of extension parameters specified as name/value pairs that KME
+    /// may ignore.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extension_optional: Option<Vec<serde_json::Value>>,
+}
+
+/// Element of the keys array in the key container data format.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Key {
+    /// ID of the key: UUID format.
+    pub key_ID: String,
+    /// For future use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_ID_extension: Option<serde_json::Value>,
+    /// Key data encoded by base64. The key size is specified by the "size"
+    /// parameter in Get key.
+    pub key: String,
+    /// For future use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_extension: Option<serde_json::Value>,
+}
+
+/// Key container data format, returned by the Get key and Get key with key IDs methods.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct KeyContainer {
+    /// Array of keys. The number of keys is specified by the "number"
+    /// parameter in Get key.
+    pub keys: Vec<Key>,
+    /// For future use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_container_extension: Option<serde_json::Value>,
+}
+
+/// Element of the key_IDs array in the key IDs data format.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct KeyId {
+    /// ID of the key: UUID format.
+    pub key_ID: String,
+    /// For future use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_ID_extension: Option<serde_json::Value>,
+}
+
+/// Key IDs data format, used for the Get key with key IDs method.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct KeyIds {
+    /// Array of key IDs.
+    pub key_IDs: Vec<KeyId>,
+    /// For future use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_IDs_extension: Option<serde_json::Value>,
+}
+
+/// Error data format, returned by all methods in case of failure.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Error {
+    /// Error message.
+    pub message: String,
+    /// Array of objects containing details.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<Vec<serde_json::Value>>,
+}
+
+impl Status {
+    /// Return the availability of keys as a value in [0,1], i.e., the ratio
+    /// between the number of stored keys and the maximum number of keys.
+    pub fn key_availability(&self) -> f64 {
+        match self.max_key_count {
+            0 => 0.0,
+            max => (self.stored_key_count as f64 / max as f64).clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl KeyIds {
+    /// Create a message from a list of key IDs.
+    pub fn from_ids(ids: &[String]) -> Self {
+        Self {
+            key_IDs: ids
+                .iter()
+                .map(|x| KeyId {
+                    key_ID: x.clone(),
+                    key_ID_extension: None,
+                })
+                .collect(),
+            key_IDs_extension: None,
+        }
+    }
+}
+
+impl KeyContainer {
+    /// Return the IDs of the keys in the container.
+    pub fn ids(&self) -> Vec<String> {
+        self.keys.iter().map(|x| x.key_ID.clone()).collect()
+    }
+}
+
+impl Validate for Status {
+    fn validate(&self) -> Result<(), String> {
+        if self.stored_key_count > self.max_key_count {
+            return Err(format!(
+                "stored_key_count ({}) greater than max_key_count ({})",
+                self.stored_key_count, self.max_key_count
+            ));
+        }
+        if self.min_key_size > self.max_key_size {
+            return Err(format!(
+                "min_key_size ({}) greater than max_key_size ({})",
+                self.min_key_size, self.max_key_size
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Validate for KeyRequest {
+    fn validate(&self) -> Result<(), String> {
+        if self.number == Some(0) {
+            return Err("the number of keys requested cannot be zero".to_string());
+        }
+        if let Some(size) = self.size {
+            if size == 0 || size % 8 != 0 {
+                return Err(format!("invalid key size: {}", size));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Validate for KeyIds {
+    fn validate(&self) -> Result<(), String> {
+        match self.key_IDs.iter().any(|x| x.key_ID.is_empty()) {
+            true => Err("empty key_ID".to_string()),
+            false => Ok(()),
+        }
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "source KME {}, target KME {}, master SAE {}, slave SAE {}, keys {}/{} of {} bits",
+            self.source_KME_ID,
+            self.target_KME_ID,
+            self.master_SAE_ID,
+            self.slave_SAE_ID,
+            self.stored_key_count,
+            self.max_key_count,
+            self.key_size
+        )
+    }
+}
+
+/// Client of the REST API of an ETSI GS QKD 014 KME.
+pub struct Qkd014Client {
+    /// Base URL of the KME, e.g., https://kme.example.com:8443
+    base_url: String,
+    agent: ureq::Agent,
+    /// SAE ID of the caller, if any.
+    sae_id: Option<String>,
+}
+
+impl Qkd014Client {
+    /// Create a client of the KME at the given base URL.
+    pub fn new(base_url: &str, timeout: Duration) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            agent: ureq::AgentBuilder::new().timeout(timeout).build(),
+            sae_id: None,
+        }
+    }
+
+    /// Identify the caller SAE in the X-SAE-ID header of the requests, as
+    /// expected by the mock KME, since real KMEs identify the SAEs from their
+    /// TLS client certificates.
+    pub fn with_sae_id(mut self, sae_id: &str) -> Self {
+        self.sae_id = Some(sae_id.to_string());
+        self
+    }
+
+    /// Get status: return the status of the keys shared with the given slave SAE.
+    pub fn status(&self, slave_sae_id: &str) -> Result<Status, String> {
+        let res = self.request("GET", slave_sae_id, "status").call();
+        Self::parse(res)
+    }
+
+    /// Get key: return new keys shared with the given slave SAE, called by the master SAE.
+    pub fn enc_keys(
+        &self,
+        slave_sae_id: &str,
+        request: &KeyRequest,
+    ) -> Result<KeyContainer, String> {
+        request.validate()?;
+        let res = self
+            .request("POST", slave_sae_id, "enc_keys")
+            .set("Content-Type", "application/json")
+            .send_string(&serde_json::to_string(request).unwrap_or_default());
+        Self::parse(res)
+    }
+
+    /// Get key with key IDs: return the keys with the given IDs shared with
+    /// the given master SAE, called by the slave SAE.
+    pub fn dec_keys(&self, master_sae_id: &str, key_ids: &KeyIds) -> Result<KeyContainer, String> {
+        key_ids.validate()?;
+        let res = self
+            .request("POST", master_sae_id, "dec_keys")
+            .set("Content-Type", "application/json")
+            .send_string(&serde_json::to_string(key_ids).unwrap_or_default());
+        Self::parse(res)
+    }
+
+    /// Return a request with the given HTTP method to a method of the API.
+    fn request(&self, http_method: &str, sae_id: &str, method: &str) -> ureq::Request {
+        let url = format!("{}/api/v1/keys/{}/{}", self.base_url, sae_id, method);
+        let request = self.agent.request(http_method, &url);
+        match &self.sae_id {
+            Some(x) => request.set(SAE_ID_HEADER, x),
+            None => request,
+        }
+    }
+
+    /// Parse the response of the KME, also in case of errors.
+    fn parse<T: serde::de::DeserializeOwned>(
+        res: Result<ureq::Response, ureq::Error>,
+    ) -> Result<T, String> {
+        match res {
+            Ok(response) => {
+                let body = response.into_string().unwrap_or_default();
+                serde_json::from_str::<T>(&body)
+                    .map_err(|x| format!("invalid response from KME: {}", x))
+            }
+            Err(ureq::Error::Status(code, response)) => {
+                let body = response.into_string().unwrap_or_default();
+                match serde_json::from_str::<Error>(&body) {
+                    Ok(err) => Err(format!("KME error {}: {}", code, err.message)),
+                    Err(_) => Err(format!("KME error {}", code)),
+                }
+            }
+            Err(err) => Err(format!("could not reach the KME: {}", err)),
+        }
+    }
+}
+
+/// Obtain a pair of keys shared by the master SAE of a platform and the slave
+/// SAE on the device apps' side: the keys are delivered to the master SAE via
+/// Get key by the KME of the platform, then to the slave SAE via Get key with
+/// key IDs by its KME, checking that they match, and finally the key container
+/// of the master SAE is posted to the key delivery endpoint of the platform.
+/// Return the IDs of the keys.
+pub fn fetch_key_pair(
+    qkd: &PlatformQkd,
+    request: &KeyRequest,
+    timeout: Duration,
+) -> Result<Vec<String>, String> {
+    let (master_sae_id, slave_kme_endpoint, key_delivery_endpoint) = match (
+        &qkd.masterSaeId,
+        &qkd.slaveKmeEndpoint,
+        &qkd.keyDeliveryEndpoint,
+    ) {
+        (Some(x), Some(y), Some(z)) => (x, y, z),
+        _ => {
+            return Err(
+                "masterSaeId, slaveKmeEndpoint, and keyDeliveryEndpoint required for key pairs"
+                    .to_string(),
+            )
+        }
+    };
+    let master_keys = Qkd014Client::new(&qkd.kmeEndpoint, timeout)
+        .with_sae_id(master_sae_id)
+        .enc_keys(&qkd.slaveSaeId, request)?;
+    let ids = master_keys.ids();
+    if ids.is_empty() {
+        return Err("no keys returned by the KME of the master SAE".to_string());
+    }
+    let slave_keys = Qkd014Client::new(slave_kme_endpoint, timeout)
+        .with_sae_id(&qkd.slaveSaeId)
+        .dec_keys(master_sae_id, &KeyIds::from_ids(&ids))?;
+    let matching = master_keys.keys.len() == slave_keys.keys.len()
+        && master_keys
+            .keys
+            .iter()
+            .zip(&slave_keys.keys)
+            .all(|(x, y)| x.key_ID == y.key_ID && x.key == y.key);
+    if !matching {
+        return Err(
+            "keys returned by the KMEs of the master and slave SAEs do not match".to_string(),
+        );
+    }
+    deliver_keys(key_delivery_endpoint, &master_keys, timeout)?;
+    Ok(ids)
+}
+
+/// Deliver a key container to the master SAE at the given URL of its platform.
+fn deliver_keys(url: &str, keys: &KeyContainer, timeout: Duration) -> Result<(), String> {
+    ureq::AgentBuilder::new()
+        .timeout(timeout)
+        .build()
+        .post(url)
+        .set("Content-Type", "application/json")
+        .send_string(&serde_json::to_string(keys).unwrap_or_default())
+        .map_err(|x| format!("could not deliver the keys to the platform: {}", x))?;
+    Ok(())
+}
+
+/// Return the key availability of a platform, retrieved from the status of
+/// the keys shared with its slave SAE.
+pub fn key_availability(qkd: &PlatformQkd, timeout: Duration) -> Result<f64, String> {
+    let mut client = Qkd014Client::new(&qkd.kmeEndpoint, timeout);
+    if let Some(master_sae_id) = &qkd.masterSaeId {
+        client = client.with_sae_id(master_sae_id);
+    }
+    Ok(client.status(&qkd.slaveSaeId)?.key_availability())
+}
+
+/// Return the key availability of the platforms configured with a KME, by
+/// querying the status of the keys shared with the platform's slave SAE.
+/// Platforms whose KME cannot be queried are assigned zero key availability.
+pub fn key_telemetry(platforms: &[Platform], timeout: Duration) -> Vec<(String, f64)> {
+    platforms
+        .iter()
+        .filter_map(|x| x.qkd.as_ref().map(|qkd| (&x.name, qkd)))
+        .map(|(name, qkd)| {
+            let value = match key_availability(qkd, timeout) {
+                Ok(value) => value,
+                Err(err) => {
+                    warn!(
+                        "could not retrieve the key status for platform {}: {}",
+                        name, err
+                    );
+                    0.0
+                }
+            };
+            (name.clone(), value)
+        })
+        .collect()
+}
+
+/// Update the key availability telemetry in the KVS of all the platforms
+/// configured with a KME, see key_telemetry.
+pub fn update_key_telemetry(kvs: &SharedKvs, timeout: Duration) {
+    let platforms = kvs.lock().unwrap().platforms().clone();
+
+    // The KMEs are queried without holding the lock on the KVS.
+    for (name, value) in key_telemetry(&platforms, timeout) {
+        let _ = kvs
+            .lock()
+            .unwrap()
+            .set_key(&name, &TelemetryValue { value });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::httpserver::stand_in::HttpStandIn;
+    use crate::kvs::Kvs;
+
+    fn example_status(stored_key_count: u32) -> Status {
+        Status {
+            source_KME_ID: "kme1".to_string(),
+            target_KME_ID: "kme2".to_string(),
+            master_SAE_ID: "sae1".to_string(),
+            slave_SAE_ID: "sae2".to_string(),
+            key_size: 256,
+            stored_key_count,
+            max_key_count: 1000,
+            max_key_per_request: 10,
+            max_key_size: 1024,
+            min_key_size: 64,
+            max_SAE_ID_count: 0,
+            status_extension: None,
+        }
+    }
+
+    fn example_key_container() -> KeyContainer {
+        KeyContainer {
+            keys: vec![
+                Key {
+                    key_ID: "bc490419-7d60-487f-adc1-4ddcc177c139".to_string(),
+                    key_ID_extension: None,
+                    key: "wHHVxRwDJs3/bXd38GHP3oe4svTuRpZS0yCC7x4Ly+s=".to_string(),
+                    key_extension: None,
+                },
+                Key {
+                    key_ID: "0a782fb5-3434-48fe-aa4d-14f41d46cf92".to_string(),
+                    key_ID_extension: None,
+                    key: "OeGMPxh1+2RpJpNCYixWHFLYRubpOKCw94FcCI7VdJA=".to_string(),
+                    key_extension: None,
+                },
+            ],
+            key_container_extension: None,
+        }
+    }
+
+    #[test]
+    fn test_qkd014_messages() {
+        let status = example_status(250);
+        assert_eq!(Ok(()), status.validate());
+        assert_eq!(0.25, status.key_availability());
+        println!("{}", status);
+
+        let mut status = example_status(1001);
+        assert!(status.validate().is_err());
+        status.max_key_count = 0;
+        assert_eq!(0.0, status.key_availability());
+
+        // field names as in the specifications
+        let j = serde_json::to_value(example_status(10)).unwrap();
+        assert_eq!(10, j["stored_key_count"]);
+        assert!(j.get("status_extension").is_none());
+
+        assert_eq!(Ok(()), KeyRequest::default().validate());
+        let mut request = KeyRequest {
+            number: Some(2),
+            size: Some(256),
+            ..Default::default()
+        };
+        assert_eq!(Ok(()), request.validate());
+        assert_eq!(
+            r#"{"number":2,"size":256}"#,
+            serde_json::to_string(&request).unwrap()
+        );
+        request.size = Some(100);
+        assert!(request.validate().is_err());
+        request.size = None;
+        request.number = Some(0);
+        assert!(request.validate().is_err());
+
+        let container = example_key_container();
+        let ids = KeyIds::from_ids(&container.ids());
+        assert_eq!(Ok(()), ids.validate());
+        assert_eq!(2, ids.key_IDs.len());
+        assert!(KeyIds::from_ids(&["".to_string()]).validate().is_err());
+    }
+
+    #[test]
+    fn test_qkd014_client() -> Result<(), String> {
+        let server = HttpStandIn::start(vec![
+            (200, serde_json::to_string(&example_status(100)).unwrap()),
+            (
+                200,
+                serde_json::to_string(&example_key_container()).unwrap(),
+            ),
+            (
+                200,
+                serde_json::to_string(&example_key_container()).unwrap(),
+            ),
+            (
+                400,
+                r#"{"message":"key not found","details":[]}"#.to_string(),
+            ),
+            (200, "not-json".to_string()),
+        ]);
+        let client = Qkd014Client::new(&format!("{}/", server.url()), Duration::from_secs(1));
+
+        assert_eq!(example_status(100), client.status("sae2")?);
+
+        let keys = client.enc_keys(
+            "sae2",
+            &KeyRequest {
+                number: Some(2),
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(example_key_container(), keys);
+
+        let keys = client.dec_keys("sae1", &KeyIds::from_ids(&keys.ids()))?;
+        assert_eq!(2, keys.keys.len());
+
+        let err = client.dec_keys("sae1", &KeyIds::from_ids(&["x".to_string()]));
+        assert_eq!(Err("KME error 400: key not found".to_string()), err);
+
+        assert!(client.status("sae2").is_err());
+
+        // invalid requests are not sent
+        assert!(client
+            .enc_keys(
+                "sae2",
+                &KeyRequest {
+                    number: Some(0),
+                    ..Default::default()
+                }
+            )
+            .is_err());
+
+        let requests = server.requests();
+        assert_eq!(5, requests.len());
+        assert_eq!("GET", requests[0].method);
+        assert_eq!("/api/v1/keys/sae2/status", requests[0].path);
+        assert_eq!("POST", requests[1].method);
+        assert_eq!("/api/v1/keys/sae2/enc_keys", requests[1].path);
+        assert_eq!(r#"{"number":2}"#, requests[1].body);
+        assert_eq!("/api/v1/keys/sae1/dec_keys", requests[2].path);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_qkd014_fetch_key_pair() -> Result<(), String> {
+        let container = serde_json::to_string(&example_key_container()).unwrap();
+        let mut tampered = example_key_container();
+        tampered.keys[1].key = tampered.keys[0].key.clone();
+        let master = HttpStandIn::start(vec![(200, container.clone()); 3]);
+        let slave = HttpStandIn::start(vec![
+            (200, container.clone()),
+            (200, serde_json::to_string(&tampered).unwrap()),
+            (200, container),
+        ]);
+        let platform = HttpStandIn::start(vec![(200, String::new()), (503, String::new())]);
+        let mut qkd = PlatformQkd {
+            kmeEndpoint: master.url(),
+            slaveSaeId: "sae2".to_string(),
+            masterSaeId: None,
+            slaveKmeEndpoint: Some(slave.url()),
+            keyDeliveryEndpoint: Some(format!("{}/keys", platform.url())),
+        };
+        let timeout = Duration::from_secs(1);
+        let request = KeyRequest::default();
+        assert!(fetch_key_pair(&qkd, &request, timeout).is_err());
+
+        qkd.masterSaeId = Some("sae1".to_string());
+        assert_eq!(
+            example_key_container().ids(),
+            fetch_key_pair(&qkd, &request, timeout)?
+        );
+
+        // the keys of the slave SAE do not match
+        assert!(fetch_key_pair(&qkd, &request, timeout).is_err());
+
+        // the keys cannot be delivered to the platform
+        assert!(fetch_key_pair(&qkd, &request, timeout).is_err());
+
+        let requests = master.requests();
+        assert_eq!(3, requests.len());
+        assert_eq!("/api/v1/keys/sae2/enc_keys", requests[0].path);
+        assert_eq!(Some("sae1"), requests[0].header(SAE_ID_HEADER));
+        let requests = slave.requests();
+        assert_eq!(3, requests.len());
+        assert_eq!("/api/v1/keys/sae1/dec_keys", requests[0].path);
+        assert_eq!(Some("sae2"), requests[0].header(SAE_ID_HEADER));
+        let key_ids: KeyIds = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!(example_key_container().ids().len(), key_ids.key_IDs.len());
+
+        // the platform receives the key material only if the keys match
+        let requests = platform.requests();
+        assert_eq!(2, requests.len());
+        assert_eq!("POST", requests[0].method);
+        assert_eq!("/keys", requests[0].path);
+        let keys: KeyContainer = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!(example_key_container(), keys);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_qkd014_update_key_telemetry() -> Result<(), String> {
+        let server = HttpStandIn::start(vec![(
+            200,
+            serde_json::to_string(&example_status(750)).unwrap(),
+        )]);
+        let kvs = Kvs::shared();
+        for (name, qkd) in [
+            (
+                "p0",
+                Some(PlatformQkd {
+                    kmeEndpoint: server.url(),
+                    slaveSaeId: "sae2".to_string(),
+                    masterSaeId: None,
+                    slaveKmeEndpoint: None,
+                    keyDeliveryEndpoint: None,
+                }),
+            ),
+            ("p1", None),
+            (
+                "p2",
+                Some(PlatformQkd {
+                    kmeEndpoint: "http://127.0.0.1:1".to_string(),
+                    slaveSaeId: "sae2".to_string(),
+                    masterSaeId: None,
+                    slaveKmeEndpoint: None,
+                    keyDeliveryEndpoint: None,
+                }),
+            ),
+        ] {
+            kvs.lock().unwrap().add_platform(Platform {
+                qkd,
+                ..Platform::example(name)
+            })?;
+        }
+
+        update_key_telemetry(&kvs, Duration::from_secs(1));
+        let kvs = kvs.lock().unwrap();
+        assert_eq!(Some(0.75), kvs.key("p0"));
+        assert_eq!(Some(1.0), kvs.key("p1"));
+        assert_eq!(Some(0.0), kvs.key("p2"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1250 @@
+//! KVS stored in a Redis-compatible server, so that it can be shared by
+//! multiple LCMP instances.
+//!
+//! The keys and their types follow the data model in docs/kvs.md:
+//! all the values are JSON-encoded strings, the telemetry values and the
+//! operations counters are string representations of numbers.
+//! Every modification is performed atomically with WATCH/MULTI/EXEC.
+
+use crate::appcontextserver::AppContextServer;
+use crate::applicationlistserver::ApplicationListServer;
+use crate::error::LcmpError;
+use crate::kvs::{App, Platform, TelemetryValue, DEFAULT_KEY, DEFAULT_LOAD};
+use crate::messages::{
+    AppContext, AppList, ApplicationList, ApplicationListInfo, TimeStamp, Validate,
+};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Key of the list of applications.
+const APPS: &str = "apps";
+/// Key of the list of platforms.
+const PLATFORMS: &str = "platforms";
+/// Key of the applications' operations counter.
+const APPS_OPERATIONS: &str = "apps:operations";
+/// Key of the platforms' operations counter.
+const PLATFORMS_OPERATIONS: &str = "platforms:operations";
+/// Key of the contexts' operations counter.
+const CONTEXTS_OPERATIONS: &str = "contexts:operations";
+/// Key of the vector of load values.
+const TELEMETRY_LOAD: &str = "telemetry:platforms:load";
+/// Key of the vector of key availability values.
+const TELEMETRY_KEY: &str = "telemetry:platforms:key";
+
+/// Return the key of the list of contexts assigned to a platform.
+fn contexts_key(platform: &str) -> String {
+    format!("contexts:{}", platform)
+}
+
+fn redis_error(err: redis::RedisError) -> LcmpError {
+    LcmpError::BackendUnavailable(format!("redis error: {}", err))
+}
+
+/// Decode a list of JSON-encoded values.
+fn decode<T: serde::de::DeserializeOwned>(
+    key: &str,
+    values: Vec<String>,
+) -> Result<Vec<T>, LcmpError> {
+    values
+        .iter()
+        .map(|x| {
+            serde_json::from_str(x)
+                .map_err(|err| LcmpError::Internal(format!("invalid value in {}: {}", key, err)))
+        })
+        .collect()
+}
+
+/// Encode a list of values in JSON.
+fn encode<T: serde::Serialize>(values: &[T]) -> Vec<String> {
+    values
+        .iter()
+        .map(|x| serde_json::to_string(x).unwrap_or_default())
+        .collect()
+}
+
+/// Add to a pipeline the commands to replace the content of a list.
+fn replace_list(pipe: &mut redis::Pipeline, key: &str, values: &[String]) {
+    pipe.cmd("DEL").arg(key).ignore();
+    if !values.is_empty() {
+        pipe.cmd("RPUSH").arg(key).arg(values).ignore();
+    }
+}
+
+/// Client of the KVS stored in a Redis-compatible server.
+pub struct RedisKvs {
+    client: redis::Client,
+    /// Connection reused across operations, re-established after errors.
+    connection: Mutex<Option<redis::Connection>>,
+}
+
+impl RedisKvs {
+    /// Create a client of the server at the given URL, e.g., redis://127.0.0.1:6379/0
+    /// The connection is established upon the first operation.
+    pub fn new(url: &str) -> Result<Self, LcmpError> {
+        Ok(Self {
+            client: redis::Client::open(url).map_err(redis_error)?,
+            connection: Mutex::new(None),
+        })
+    }
+
+    /// Execute a function with a connection to the server.
+    fn with_connection<T>(
+        &self,
+        f: impl FnOnce(&mut redis::Connection) -> Result<T, LcmpError>,
+    ) -> Result<T, LcmpError> {
+        let mut connection = self.connection.lock().unwrap();
+        if connection.is_none() {
+            *connection = Some(self.client.get_connection().map_err(redis_error)?);
+        }
+        let res = f(connection.as_mut().unwrap());
+        if res.is_err() {
+            *connection = None;
+        }
+        res
+    }
+
+    /// Perform a read-modify-write operation atomically.
+    /// The function reads the current values and returns the commands to be
+    /// executed in a transaction, which is retried if any of the keys
+    /// watched is modified in the meanwhile by another client.
+    fn atomic<T>(
+        &self,
+        keys: &[&str],
+        mut f: impl FnMut(&mut redis::Connection) -> Result<(T, redis::Pipeline), LcmpError>,
+    ) -> Result<T, LcmpError> {
+        self.with_connection(|con| loop {
+            redis::cmd("WATCH")
+                .arg(keys)
+                .query::<()>(con)
+                .map_err(redis_error)?;
+            let (value, mut pipe) = match f(con) {
+                Ok(x) => x,
+                Err(err) => {
+                    let _ = redis::cmd("UNWATCH").query::<()>(con);
+                    return Err(err);
+                }
+            };
+            let res: Option<()> = pipe.atomic().query(con).map_err(redis_error)?;
+            if res.is_some() {
+                return Ok(value);
+            }
+        })
+    }
+
+    fn list(con: &mut redis::Connection, key: &str) -> Result<Vec<String>, LcmpError> {
+        redis::cmd("LRANGE")
+            .arg(key)
+            .arg(0)
+            .arg(-1)
+            .query(con)
+            .map_err(redis_error)
+    }
+
+    fn counter(&self, key: &str) -> Result<u64, LcmpError> {
+        self.with_connection(|con| {
+            let value: Option<u64> = redis::cmd("GET").arg(key).query(con).map_err(redis_error)?;
+            Ok(value.unwrap_or_default())
+        })
+    }
+
+    /// Return an error if the server is not reachable.
+    pub fn ping(&self) -> Result<(), LcmpError> {
+        self.with_connection(|con| redis::cmd("PING").query::<()>(con).map_err(redis_error))
+    }
+
+    /// Return all the apps.
+    pub fn apps(&self) -> Result<Vec<App>, LcmpError> {
+        self.with_connection(|con| decode(APPS, Self::list(con, APPS)?))
+    }
+
+    /// Add a new app, which must be valid and not already present.
+    pub fn add_app(&self, app: App) -> Result<(), LcmpError> {
+        app.validate().map_err(LcmpError::InvalidRequest)?;
+        let key = app.key();
+        self.atomic(&[APPS], |con| {
+            let apps: Vec<App> = decode(APPS, Self::list(con, APPS)?)?;
+            if apps.iter().any(|x| x.key() == key) {
+                return Err(LcmpError::Conflict(format!("app already present: {}", key)));
+            }
+            let mut pipe = redis::pipe();
+            pipe.cmd("RPUSH").arg(APPS).arg(encode(&[&app])).ignore();
+            pipe.cmd("INCR").arg(APPS_OPERATIONS).ignore();
+            Ok(((), pipe))
+        })
+    }
+
+    /// Delete an existing app.
+    pub fn del_app(&self, appkey: &str) -> Result<(), LcmpError> {
+        self.atomic(&[APPS], |con| {
+            let mut apps: Vec<App> = decode(APPS, Self::list(con, APPS)?)?;
+            let ndx = apps
+                .iter()
+                .position(|x| x.key() == appkey)
+                .ok_or_else(|| LcmpError::NotFound(format!("app not found: {}", appkey)))?;
+            apps.remove(ndx);
+            let mut pipe = redis::pipe();
+            replace_list(&mut pipe, APPS, &encode(&apps));
+            pipe.cmd("INCR").arg(APPS_OPERATIONS).ignore();
+            Ok(((), pipe))
+        })
+    }
+
+    /// Return all the platforms.
+    pub fn platforms(&self) -> Result<Vec<Platform>, LcmpError> {
+        self.with_connection(|con| decode(PLATFORMS, Self::list(con, PLATFORMS)?))
+    }
+
+    /// Return a platform by name.
+    pub fn platform(&self, name: &str) -> Result<Option<Platform>, LcmpError> {
+        Ok(self.platforms()?.into_iter().find(|x| x.name == name))
+    }
+
+    /// Add a new platform, which must be valid and not already present.
+    pub fn add_platform(&self, platform: Platform) -> Result<(), LcmpError> {
+        platform.validate().map_err(LcmpError::InvalidRequest)?;
+        self.atomic(&[PLATFORMS], |con| {
+            let platforms: Vec<Platform> = decode(PLATFORMS, Self::list(con, PLATFORMS)?)?;
+            if platforms.iter().any(|x| x.name == platform.name) {
+                return Err(LcmpError::Conflict(format!(
+                    "platform already present: {}",
+                    platform.name
+                )));
+            }
+            let mut pipe = redis::pipe();
+            pipe.cmd("RPUSH")
+                .arg(PLATFORMS)
+                .arg(encode(&[&platform]))
+                .ignore();
+            pipe.cmd("RPUSH")
+                .arg(TELEMETRY_LOAD)
+                .arg(DEFAULT_LOAD.to_string())
+                .ignore();
+            pipe.cmd("RPUSH")
+                .arg(TELEMETRY_KEY)
+                .arg(DEFAULT_KEY.to_string())
+                .ignore();
+            pipe.cmd("INCR").arg(PLATFORMS_OPERATIONS).ignore();
+            Ok(((), pipe))
+        })
+    }
+
+    /// Delete an existing platform, together with its telemetry data.
+    pub fn del_platform(&self, name: &str) -> Result<(), LcmpError> {
+        self.atomic(&[PLATFORMS], |con| {
+            let mut platforms: Vec<Platform> = decode(PLATFORMS, Self::list(con, PLATFORMS)?)?;
+            let ndx = platforms
+                .iter()
+                .position(|x| x.name == name)
+                .ok_or_else(|| LcmpError::NotFound(format!("platform not found: {}", name)))?;
+            platforms.remove(ndx);
+            let mut pipe = redis::pipe();
+            replace_list(&mut pipe, PLATFORMS, &encode(&platforms));
+            for key in [TELEMETRY_LOAD, TELEMETRY_KEY] {
+                let mut values = Self::list(con, key)?;
+                if ndx < values.len() {
+                    values.remove(ndx);
+                }
+                replace_list(&mut pipe, key, &values);
+            }
+            pipe.cmd("INCR").arg(PLATFORMS_OPERATIONS).ignore();
+            Ok(((), pipe))
+        })
+    }
+
+    fn set_telemetry(
+        &self,
+        key: &str,
+        name: &str,
+        value: &TelemetryValue,
+    ) -> Result<(), LcmpError> {
+        value.validate().map_err(LcmpError::InvalidRequest)?;
+        self.atomic(&[PLATFORMS], |con| {
+            let platforms: Vec<Platform> = decode(PLATFORMS, Self::list(con, PLATFORMS)?)?;
+            let ndx = platforms
+                .iter()
+                .position(|x| x.name == name)
+                .ok_or_else(|| LcmpError::NotFound(format!("platform not found: {}", name)))?;
+            let mut pipe = redis::pipe();
+            pipe.cmd("LSET")
+                .arg(key)
+                .arg(ndx)
+                .arg(value.value.to_string())
+                .ignore();
+            Ok(((), pipe))
+        })
+    }
+
+    fn telemetry(&self, key: &str, name: &str) -> Result<Option<f64>, LcmpError> {
+        self.with_connection(|con| {
+            let platforms: Vec<Platform> = decode(PLATFORMS, Self::list(con, PLATFORMS)?)?;
+            match platforms.iter().position(|x| x.name == name) {
+                Some(ndx) => {
+                    let value: Option<String> = redis::cmd("LINDEX")
+                        .arg(key)
+                        .arg(ndx)
+                        .query(con)
+                        .map_err(redis_error)?;
+                    Ok(value.and_then(|x| x.parse::<f64>().ok()))
+                }
+                None => Ok(None),
+            }
+        })
+    }
+
+    /// Set the last load value reported by a platform.
+    pub fn set_load(&self, name: &str, value: &TelemetryValue) -> Result<(), LcmpError> {
+        self.set_telemetry(TELEMETRY_LOAD, name, value)
+    }
+
+    /// Set the last key availability value reported for a platform.
+    pub fn set_key(&self, name: &str, value: &TelemetryValue) -> Result<(), LcmpError> {
+        self.set_telemetry(TELEMETRY_KEY, name, value)
+    }
+
+    /// Return the last load value reported by a platform, if it exists.
+    pub fn load(&self, name: &str) -> Result<Option<f64>, LcmpError> {
+        self.telemetry(TELEMETRY_LOAD, name)
+    }
+
+    /// Return the last key availability value reported for a platform, if it exists.
+    pub fn key(&self, name: &str) -> Result<Option<f64>, LcmpError> {
+        self.telemetry(TELEMETRY_KEY, name)
+    }
+
+    /// Return the application contexts assigned to a platform.
+    pub fn contexts(&self, platform: &str) -> Result<Vec<AppContext>, LcmpError> {
+        let key = contexts_key(platform);
+        self.with_connection(|con| decode(&key, Self::list(con, &key)?))
+    }
+
+    /// Modify atomically the application contexts assigned to a platform.
+    /// The contexts are written back only if the function returns true.
+    fn modify_contexts<T>(
+        &self,
+        platform: &str,
+        mut f: impl FnMut(&mut Vec<AppContext>) -> Result<(T, bool), LcmpError>,
+    ) -> Result<T, LcmpError> {
+        let key = contexts_key(platform);
+        self.atomic(&[key.as_str()], |con| {
+            let mut contexts: Vec<AppContext> = decode(&key, Self::list(con, &key)?)?;
+            let (value, modified) = f(&mut contexts)?;
+            let mut pipe = redis::pipe();
+            if modified {
+                replace_list(&mut pipe, &key, &encode(&contexts));
+                pipe.cmd("INCR").arg(CONTEXTS_OPERATIONS).ignore();
+            }
+            Ok((value, pipe))
+        })
+    }
+
+    /// Return the value of the applications' operations counter.
+    pub fn apps_operations(&self) -> Result<u64, LcmpError> {
+        self.counter(APPS_OPERATIONS)
+    }
+
+    /// Return the value of the platforms' operations counter.
+    pub fn platforms_operations(&self) -> Result<u64, LcmpError> {
+        self.counter(PLATFORMS_OPERATIONS)
+    }
+
+    /// Return the value of the contexts' operations counter.
+    pub fn contexts_operations(&self) -> Result<u64, LcmpError> {
+        self.counter(CONTEXTS_OPERATIONS)
+    }
+}
+
+/// Key=value parameters.
+type Params<'a> = Vec<(&'a str, &'a str)>;
+
+/// Return the value of the parameter "url" from a string of semicolon-separated
+/// key=value parameters, together with the other parameters.
+pub(crate) fn split_url(params: &str) -> Result<(String, Params<'_>), String> {
+    let mut url = None;
+    let mut others = vec![];
+    for param in params.split(';').filter(|x| !x.is_empty()) {
+        let (key, value) = param
+            .split_once('=')
+            .ok_or(format!("invalid redis parameter: {}", param))?;
+        match key {
+            "url" => url = Some(value.to_string()),
+            _ => others.push((key, value)),
+        }
+    }
+    match url {
+        Some(x) if !x.is_empty() => Ok((x, others)),
+        _ => Err("redis parameter url is mandatory".to_string()),
+    }
+}
+
+/// ApplicationListServer returning the apps in a Redis-compatible KVS.
+pub struct RedisApplicationListServer {
+    kvs: RedisKvs,
+    /// Last ApplicationList built, together with the value of the apps' operations
+    /// counter in the KVS at the time.
+    cache: Mutex<Option<(u64, ApplicationList)>>,
+}
+
+impl RedisApplicationListServer {
+    pub fn new(kvs: RedisKvs) -> Self {
+        Self {
+            kvs,
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// Create a server from a string of semicolon-separated key=value parameters:
+    /// url (of the Redis-compatible server).
+    pub fn from_params(params: &str) -> Result<Self, String> {
+        let (url, others) = split_url(params)?;
+        if let Some((key, _)) = others.first() {
+            return Err(format!("unknown redis parameter: {}", key));
+        }
+        Ok(Self::new(RedisKvs::new(&url)?))
+    }
+}
+
+impl ApplicationListServer for RedisApplicationListServer {
+    /// Return an ApplicationList message containing only the matching query.
+    /// The list of apps is retrieved only if changed in the KVS since the last call.
+    fn application_list(&self, info: ApplicationListInfo) -> Result<ApplicationList, LcmpError> {
+        let mut cache = self.cache.lock().unwrap();
+        let operations = self.kvs.apps_operations()?;
+        let up_to_date = matches!(&*cache, Some((x, _)) if *x == operations);
+        if !up_to_date {
+            let app_list = ApplicationList {
+                appList: self
+                    .kvs
+                    .apps()?
+                    .into_iter()
+                    .map(|x| AppList::from_app_info(x.appInfo))
+                    .collect(),
+            };
+            *cache = Some((operations, app_list));
+        }
+        match &*cache {
+            Some((_, x)) => Ok(ApplicationList {
+                appList: x.matching_info(&info),
+            }),
+            None => Ok(ApplicationList::empty()),
+        }
+    }
+
+    /// Return an error if the server is not reachable.
+    fn status(&self) -> Result<(), LcmpError> {
+        self.kvs.ping()
+    }
+}
+
+/// AppContextServer accepting new contexts up to a maximum and assigning them
+/// to a given platform, whose contexts are stored in a Redis-compatible KVS.
+/// Multiple instances can share the same platform.
+pub struct RedisAppContextServer {
+    kvs: RedisKvs,
+    /// Name of the platform to which the contexts are assigned.
+    platform: String,
+    /// Maximum number of active contexts on the platform.
+    max_contexts: usize,
+    /// Reference URI assigned to the application contexts.
+    /// If None, the endpointApps of the platform in the KVS is used.
+    reference_uri: Option<String>,
+    /// Last context retrieved with get_context.
+    fetched: Option<AppContext>,
+}
+
+impl RedisAppContextServer {
+    pub fn new(
+        kvs: RedisKvs,
+        platform: &str,
+        max_contexts: usize,
+        reference_uri: Option<String>,
+    ) -> Self {
+        Self {
+            kvs,
+            platform: platform.to_string(),
+            max_contexts,
+            reference_uri,
+            fetched: None,
+        }
+    }
+
+    /// Create a server from a string of semicolon-separated key=value parameters:
+    /// url (of the Redis-compatible server), platform (name of the platform),
+    /// max (maximum number of active contexts), uri (reference URI, optional).
+    pub fn from_params(params: &str) -> Result<Self, String> {
+        let (url, others) = split_url(params)?;
+        let mut platform = None;
+        let mut max_contexts = None;
+        let mut reference_uri = None;
+        for (key, value) in others {
+            let invalid = || format!("invalid value for {}: {}", key, value);
+            match key {
+                "platform" => platform = Some(value.to_string()),
+                "max" => max_contexts = Some(value.parse::<usize>().map_err(|_| invalid())?),
+                "uri" => reference_uri = Some(value.to_string()),
+                _ => return Err(format!("unknown redis parameter: {}", key)),
+            }
+        }
+        match (platform, max_contexts) {
+            (Some(platform), Some(max_contexts)) if !platform.is_empty() => Ok(Self::new(
+                RedisKvs::new(&url)?,
+                &platform,
+                max_contexts,
+                reference_uri,
+            )),
+            _ => Err("redis parameters platform and max are mandatory".to_string()),
+        }
+    }
+
+    fn not_found(context_id: &str) -> LcmpError {
+        LcmpError::NotFound(format!("context ID not found: {}", context_id))
+    }
+}
+
+impl AppContextServer for RedisAppContextServer {
+    /// If the maximum number of contexts is exceeded, the command is rejected.
+    /// Otherwise the reference URI is returned upon accepting the next context.
+    fn new_context(&mut self, app_context: &mut AppContext) -> Result<(), LcmpError> {
+        app_context
+            .valid_request()
+            .map_err(LcmpError::InvalidRequest)?;
+        let reference_uri = match self.reference_uri(app_context)? {
+            Some(x) => x,
+            None => {
+                return Err(LcmpError::NotFound(format!(
+                    "platform not found: {}",
+                    self.platform
+                )))
+            }
+        };
+
+        let mut accepted = app_context.clone();
+        accepted.contextId = Some(Uuid::simple(Uuid::new_v4()).to_string());
+        accepted.assign_reference_uri(&reference_uri);
+
+        let max_contexts = self.max_contexts;
+        self.kvs.modify_contexts(&self.platform, |contexts| {
+            if contexts.len() >= max_contexts {
+                return Err(LcmpError::QuotaExceeded(format!(
+                    "Maximum number of active contexts reached {}",
+                    max_contexts
+                )));
+            }
+            contexts.push(accepted.clone());
+            Ok(((), true))
+        })?;
+        *app_context = accepted;
+        Ok(())
+    }
+
+    fn del_context(&mut self, context_id: &str) -> Result<(), LcmpError> {
+        self.kvs.modify_contexts(&self.platform, |contexts| {
+            let ndx = contexts
+                .iter()
+                .position(|x| x.contextId.as_deref() == Some(context_id))
+                .ok_or_else(|| Self::not_found(context_id))?;
+            contexts.remove(ndx);
+            Ok(((), true))
+        })
+    }
+
+    fn get_context(&mut self, context_id: &str) -> Result<&AppContext, LcmpError> {
+        let app_context = self
+            .kvs
+            .contexts(&self.platform)?
+            .into_iter()
+            .find(|x| x.contextId.as_deref() == Some(context_id))
+            .ok_or_else(|| Self::not_found(context_id))?;
+        Ok(self.fetched.insert(app_context))
+    }
+
+    /// Update an active context.
+    /// Only the callbackReference is allowed to be updated. If the other
+    /// fields do not match exactly, then the command is denied.
+    fn update_context(&mut self, app_context: &mut AppContext) -> Result<(), LcmpError> {
+        let context_id = app_context
+            .contextId
+            .clone()
+            .ok_or(LcmpError::InvalidRequest(
+                "context ID not specified in the request".to_string(),
+            ))?;
+        self.kvs.modify_contexts(&self.platform, |contexts| {
+            let x = contexts
+                .iter_mut()
+                .find(|x| x.contextId.as_deref() == Some(&context_id))
+                .ok_or_else(|| Self::not_found(&context_id))?;
+            if !x.identical_except_callback_reference(app_context) {
+                return Err(LcmpError::Conflict(
+                    "AppContext in the request does not match that in the server".to_string(),
+                ));
+            }
+            x.callbackReference = app_context.callbackReference.clone();
+            Ok(((), true))
+        })
+    }
+
+    fn list_contexts(&mut self) -> Result<Vec<String>, LcmpError> {
+        Ok(self
+            .kvs
+            .contexts(&self.platform)?
+            .into_iter()
+            .filter_map(|x| x.contextId)
+            .collect())
+    }
+
+    /// Return an error if the server is not reachable.
+    fn status(&self) -> Result<(), LcmpError> {
+        self.kvs.ping()
+    }
+
+    /// Return the static reference URI, if any, otherwise the endpointApps
+    /// of the platform, if it exists in the KVS.
+    fn reference_uri(&mut self, _app_context: &AppContext) -> Result<Option<String>, LcmpError> {
+        match &self.reference_uri {
+            Some(x) => Ok(Some(x.clone())),
+            None => Ok(self.kvs.platform(&self.platform)?.map(|x| x.endpointApps)),
+        }
+    }
+
+    /// Change the reference URI of all the user application instances of the context.
+    fn update_reference_uri(
+        &mut self,
+        context_id: &str,
+        reference_uri: &str,
+    ) -> Result<bool, LcmpError> {
+        self.kvs.modify_contexts(&self.platform, |contexts| {
+            let x = contexts
+                .iter_mut()
+                .find(|x| x.contextId.as_deref() == Some(context_id))
+                .ok_or_else(|| Self::not_found(context_id))?;
+            let mut changed = false;
+            for info in &mut x.appInfo.userAppInstanceInfo {
+                if info.referenceURI.as_deref() != Some(reference_uri) {
+                    info.referenceURI = Some(reference_uri.to_string());
+                    changed = true;
+                }
+            }
+            Ok((changed, changed))
+        })
+    }
+
+    fn set_qkd_key_ids(
+        &mut self,
+        context_id: &str,
+        key_ids: &[Option<Vec<String>>],
+    ) -> Result<(), LcmpError> {
+        self.kvs.modify_contexts(&self.platform, |contexts| {
+            let x = contexts
+                .iter_mut()
+                .find(|x| x.contextId.as_deref() == Some(context_id))
+                .ok_or_else(|| Self::not_found(context_id))?;
+            x.assign_qkd_key_ids(key_ids);
+            Ok(((), true))
+        })
+    }
+
+    fn set_expires_at(
+        &mut self,
+        context_id: &str,
+        expires_at: Option<TimeStamp>,
+    ) -> Result<(), LcmpError> {
+        self.kvs.modify_contexts(&self.platform, |contexts| {
+            let x = contexts
+                .iter_mut()
+                .find(|x| x.contextId.as_deref() == Some(context_id))
+                .ok_or_else(|| Self::not_found(context_id))?;
+            x.expiresAt = expires_at;
+            Ok(((), true))
+        })
+    }
+}
+
+/// In-process server implementing the subset of the Redis protocol (RESP)
+/// used by RedisKvs, for testing.
+#[cfg(test)]
+pub mod stand_in {
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// Value stored.
+    enum Entry {
+        Str(String),
+        List(Vec<String>),
+    }
+
+    /// Data stored, with a version per key incremented at every modification.
+    #[derive(Default)]
+    struct State {
+        data: HashMap<String, Entry>,
+        versions: HashMap<String, u64>,
+    }
+
+    enum Reply {
+        Status(&'static str),
+        Error(String),
+        Int(i64),
+        Bulk(Option<String>),
+        Array(Option<Vec<Reply>>),
+    }
+
+    impl Reply {
+        fn write(&self, out: &mut Vec<u8>) {
+            match self {
+                Reply::Status(x) => out.extend(format!("+{}\r\n", x).as_bytes()),
+                Reply::Error(x) => out.extend(format!("-{}\r\n", x).as_bytes()),
+                Reply::Int(x) => out.extend(format!(":{}\r\n", x).as_bytes()),
+                Reply::Bulk(None) => out.extend(b"$-1\r\n"),
+                Reply::Bulk(Some(x)) => out.extend(format!("${}\r\n{}\r\n", x.len(), x).as_bytes()),
+                Reply::Array(None) => out.extend(b"*-1\r\n"),
+                Reply::Array(Some(x)) => {
+                    out.extend(format!("*{}\r\n", x.len()).as_bytes());
+                    x.iter().for_each(|x| x.write(out));
+                }
+            }
+        }
+    }
+
+    fn wrong_type() -> Reply {
+        Reply::Error("WRONGTYPE Operation against a key holding the wrong kind of value".into())
+    }
+
+    /// Return the index in a list of a possibly negative position.
+    fn index(len: usize, pos: i64) -> i64 {
+        match pos < 0 {
+            true => len as i64 + pos,
+            false => pos,
+        }
+    }
+
+    impl State {
+        fn touch(&mut self, key: &str) {
+            *self.versions.entry(key.to_string()).or_default() += 1;
+        }
+
+        fn version(&self, key: &str) -> u64 {
+            self.versions.get(key).cloned().unwrap_or_default()
+        }
+
+        fn list(&mut self, key: &str) -> Result<&mut Vec<String>, Reply> {
+            match self
+                .data
+                .entry(key.to_string())
+                .or_insert(Entry::List(vec![]))
+            {
+                Entry::List(x) => Ok(x),
+                Entry::Str(_) => Err(wrong_type()),
+            }
+        }
+
+        /// Remove a key if it is an empty list, as Redis does.
+        fn cleanup(&mut self, key: &str) {
+            if matches!(self.data.get(key), Some(Entry::List(x)) if x.is_empty()) {
+                self.data.remove(key);
+            }
+        }
+
+        fn execute(&mut self, args: &[String]) -> Reply {
+            let int = |ndx: usize| args.get(ndx).and_then(|x| x.parse::<i64>().ok());
+            let command = args[0].to_uppercase();
+            let res = match (command.as_str(), args.len()) {
+                ("PING", _) => Ok(Reply::Status("PONG")),
+                ("CLIENT", _) | ("SELECT", _) => Ok(Reply::Status("OK")),
+                ("FLUSHDB", _) | ("FLUSHALL", _) => {
+                    let keys: Vec<String> = self.data.keys().cloned().collect();
+                    keys.iter().for_each(|x| self.touch(x));
+                    self.data.clear();
+                    Ok(Reply::Status("OK"))
+                }
+                ("GET", 2) => match self.data.get(&args[1]) {
+                    Some(Entry::Str(x)) => Ok(Reply::Bulk(Some(x.clone()))),
+                    Some(Entry::List(_)) => Err(wrong_type()),
+                    None => Ok(Reply::Bulk(None)),
+                },
+                ("SET", 3) => {
+                    self.data
+                        .insert(args[1].clone(), Entry::Str(args[2].clone()));
+                    self.touch(&args[1]);
+                    Ok(Reply::Status("OK"))
+                }
+                ("DEL", n) if n >= 2 => {
+                    let mut deleted = 0;
+                    for key in &args[1..] {
+                        if self.data.remove(key).is_some() {
+                            self.touch(key);
+                            deleted += 1;
+                        }
+                    }
+                    Ok(Reply::Int(deleted))
+                }
+                ("INCR", 2) => {
+                    let value = match self.data.get(&args[1]) {
+                        Some(Entry::Str(x)) => x.parse::<i64>().ok(),
+                        Some(Entry::List(_)) => None,
+                        None => Some(0),
+                    };
+                    match value {
+                        Some(x) => {
+                            self.data
+                                .insert(args[1].clone(), Entry::Str((x + 1).to_string()));
+                            self.touch(&args[1]);
+                            Ok(Reply::Int(x + 1))
+                        }
+                        None => Err(Reply::Error("ERR value is not an integer".into())),
+                    }
+                }
+                ("RPUSH", n) if n >= 3 => {
+                    let len = self.list(&args[1]).map(|list| {
+                        list.extend(args[2..].iter().cloned());
+                        list.len()
+                    });
+                    self.touch(&args[1]);
+                    len.map(|x| Reply::Int(x as i64))
+                }
+                ("LRANGE", 4) => {
+                    let (start, stop) = (int(2).unwrap_or(0), int(3).unwrap_or(-1));
+                    let res = self.list(&args[1]).map(|list| {
+                        let len = list.len();
+                        let start = index(len, start).max(0) as usize;
+                        let stop = index(len, stop).min(len as i64 - 1);
+                        match stop < start as i64 {
+                            true => vec![],
+                            false => list[start..=stop as usize].to_vec(),
+                        }
+                    });
+                    self.cleanup(&args[1]);
+                    res.map(|x| {
+                        Reply::Array(Some(x.into_iter().map(|x| Reply::Bulk(Some(x))).collect()))
+                    })
+                }
+                ("LINDEX", 3) => {
+                    let pos = int(2).unwrap_or(0);
+                    let res = self.list(&args[1]).map(|list| {
+                        let ndx = index(list.len(), pos);
+                        match ndx >= 0 && (ndx as usize) < list.len() {
+                            true => Some(list[ndx as usize].clone()),
+                            false => None,
+                        }
+                    });
+                    self.cleanup(&args[1]);
+                    res.map(Reply::Bulk)
+                }
+                ("LSET", 4) => {
+                    let pos = int(2).unwrap_or(0);
+                    let res = match self.list(&args[1]) {
+                        Ok(list) => {
+                            let ndx = index(list.len(), pos);
+                            match ndx >= 0 && (ndx as usize) < list.len() {
+                                true => {
+                                    list[ndx as usize] = args[3].clone();
+                                    Ok(Reply::Status("OK"))
+                                }
+                                false => Err(Reply::Error("ERR index out of range".into())),
+                            }
+                        }
+                        Err(err) => Err(err),
+                    };
+                    self.cleanup(&args[1]);
+                    if res.is_ok() {
+                        self.touch(&args[1]);
+                    }
+                    res
+                }
+                ("LLEN", 2) => {
+                    let res = self.list(&args[1]).map(|x| Reply::Int(x.len() as i64));
+                    self.cleanup(&args[1]);
+                    res
+                }
+                _ => Err(Reply::Error(format!("ERR unknown command '{}'", args[0]))),
+            };
+            match res {
+                Ok(x) => x,
+                Err(x) => x,
+            }
+        }
+    }
+
+    /// RESP server on a random port of the loopback interface, stopped when dropped.
+    pub struct RespStandIn {
+        address: String,
+        stop: Arc<AtomicBool>,
+    }
+
+    impl RespStandIn {
+        pub fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").expect("could not bind");
+            let address = listener.local_addr().unwrap().to_string();
+            let state = Arc::new(Mutex::new(State::default()));
+            let stop = Arc::new(AtomicBool::new(false));
+            let thread_stop = stop.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if thread_stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let state = state.clone();
+                        std::thread::spawn(move || Self::serve(stream, state));
+                    }
+                }
+            });
+            Self { address, stop }
+        }
+
+        /// Return the URL of the server.
+        pub fn url(&self) -> String {
+            format!("redis://{}/", self.address)
+        }
+
+        /// Read a command, i.e., an array of bulk strings.
+        fn read_command(reader: &mut BufReader<TcpStream>) -> Option<Vec<String>> {
+            let mut line = String::new();
+            reader.read_line(&mut line).ok()?;
+            let num = line.trim_end().strip_prefix('*')?.parse::<usize>().ok()?;
+            let mut args = vec![];
+            for _ in 0..num {
+                line.clear();
+                reader.read_line(&mut line).ok()?;
+                let len = line.trim_end().strip_prefix('$')?.parse::<usize>().ok()?;
+                let mut buf = vec![0_u8; len + 2];
+                std::io::Read::read_exact(reader, &mut buf).ok()?;
+                buf.truncate(len);
+                args.push(String::from_utf8(buf).ok()?);
+            }
+            match args.is_empty() {
+                true => None,
+                false => Some(args),
+            }
+        }
+
+        fn serve(stream: TcpStream, state: Arc<Mutex<State>>) {
+            let mut writer = match stream.try_clone() {
+                Ok(x) => x,
+                Err(_) => return,
+            };
+            let mut reader = BufReader::new(stream);
+            let mut watched: Vec<(String, u64)> = vec![];
+            let mut queued: Option<Vec<Vec<String>>> = None;
+            while let Some(args) = Self::read_command(&mut reader) {
+                let command = args[0].to_uppercase();
+                let mut state = state.lock().unwrap();
+                let reply = match (command.as_str(), &mut queued) {
+                    ("MULTI", None) => {
+                        queued = Some(vec![]);
+                        Reply::Status("OK")
+                    }
+                    ("EXEC", Some(_)) => {
+                        let commands = queued.take().unwrap();
+                        let aborted = watched.iter().any(|(key, v)| state.version(key) != *v);
+                        watched.clear();
+                        match aborted {
+                            true => Reply::Array(None),
+                            false => Reply::Array(Some(
+                                commands.iter().map(|x| state.execute(x)).collect(),
+                            )),
+                        }
+                    }
+                    ("DISCARD", Some(_)) => {
+                        queued = None;
+                        watched.clear();
+                        Reply::Status("OK")
+                    }
+                    (_, Some(commands)) => {
+                        commands.push(args);
+                        Reply::Status("QUEUED")
+                    }
+                    ("WATCH", None) => {
+                        for key in &args[1..] {
+                            watched.push((key.clone(), state.version(key)));
+                        }
+                        Reply::Status("OK")
+                    }
+                    ("UNWATCH", None) => {
+                        watched.clear();
+                        Reply::Status("OK")
+                    }
+                    _ => state.execute(&args),
+                };
+                drop(state);
+                let mut out = vec![];
+                reply.write(&mut out);
+                if writer.write_all(&out).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    impl Drop for RespStandIn {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::Relaxed);
+            let _ = TcpStream::connect(&self.address);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::stand_in::RespStandIn;
+    use super::*;
+
+    /// Return the URL of a server for testing, with the stand-in keeping it alive.
+    /// If the environment variable REDIS_URL is set, e.g., to redis://127.0.0.1:6379,
+    /// then that server is used instead, with the given database, which is flushed.
+    fn test_server(db: u8) -> (String, Option<RespStandIn>) {
+        match std::env::var("REDIS_URL") {
+            Ok(url) => {
+                let url = format!("{}/{}", url.trim_end_matches('/'), db);
+                let mut con = redis::Client::open(url.as_str())
+                    .unwrap()
+                    .get_connection()
+                    .expect("could not connect to REDIS_URL");
+                redis::cmd("FLUSHDB").query::<()>(&mut con).unwrap();
+                (url, None)
+            }
+            Err(_) => {
+                let server = RespStandIn::start();
+                (server.url(), Some(server))
+            }
+        }
+    }
+
+    fn request() -> AppContext {
+        AppContext::request_from_name_provider("my_app_name", "my_app_provider")
+    }
+
+    fn raw_list(url: &str, key: &str) -> Vec<String> {
+        let mut con = redis::Client::open(url).unwrap().get_connection().unwrap();
+        redis::cmd("LRANGE")
+            .arg(key)
+            .arg(0)
+            .arg(-1)
+            .query(&mut con)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_redis_kvs() -> Result<(), String> {
+        let (url, _server) = test_server(1);
+        let kvs = RedisKvs::new(&url)?;
+        kvs.ping()?;
+
+        // apps
+        assert!(kvs.apps()?.is_empty());
+        kvs.add_app(App::example("a1"))?;
+        kvs.add_app(App::example("a2"))?;
+        assert!(kvs.add_app(App::example("a1")).is_err());
+        assert_eq!(2, kvs.apps()?.len());
+        kvs.del_app(&App::example("a1").key())?;
+        assert!(kvs.del_app(&App::example("a1").key()).is_err());
+        assert_eq!(
+            vec![App::example("a2").key()],
+            kvs.apps()?.iter().map(|x| x.key()).collect::<Vec<_>>()
+        );
+        assert_eq!(3, kvs.apps_operations()?);
+
+        // platforms and telemetry
+        for name in ["p0", "p1", "p2"] {
+            kvs.add_platform(Platform::example(name))?;
+        }
+        assert!(kvs.add_platform(Platform::example("p0")).is_err());
+        kvs.set_load("p1", &TelemetryValue { value: 0.5 })?;
+        kvs.set_key("p2", &TelemetryValue { value: 0.25 })?;
+        assert!(kvs.set_load("p9", &TelemetryValue { value: 0.5 }).is_err());
+        assert!(kvs.set_load("p1", &TelemetryValue { value: 1.5 }).is_err());
+        assert_eq!(Some(0.5), kvs.load("p1")?);
+        assert_eq!(Some(0.0), kvs.load("p2")?);
+        assert_eq!(Some(0.25), kvs.key("p2")?);
+        assert_eq!(None, kvs.load("p9")?);
+
+        kvs.del_platform("p1")?;
+        assert!(kvs.del_platform("p1").is_err());
+        assert_eq!(
+            vec!["p0", "p2"],
+            kvs.platforms()?
+                .iter()
+                .map(|x| x.name.clone())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(0.25), kvs.key("p2")?);
+        assert_eq!(4, kvs.platforms_operations()?);
+
+        // keys and types as in docs/kvs.md
+        assert_eq!(vec!["0", "0"], raw_list(&url, "telemetry:platforms:load"));
+        assert_eq!(vec!["1", "0.25"], raw_list(&url, "telemetry:platforms:key"));
+        let platforms = raw_list(&url, "platforms");
+        assert_eq!(2, platforms.len());
+        let platform: Platform = serde_json::from_str(&platforms[0]).unwrap();
+        assert_eq!("p0", platform.name);
+        assert_eq!(1, raw_list(&url, "apps").len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_redis_application_list_server() -> Result<(), String> {
+        let (url, _server) = test_server(2);
+        assert!(RedisApplicationListServer::from_params("").is_err());
+        assert!(RedisApplicationListServer::from_params(&format!("url={};x=1", url)).is_err());
+        let server = RedisApplicationListServer::from_params(&format!("url={}", url))?;
+        server.status()?;
+        assert!(server
+            .application_list(ApplicationListInfo::empty())?
+            .appList
+            .is_empty());
+
+        // apps added by another client
+        let kvs = RedisKvs::new(&url)?;
+        kvs.add_app(App::example("a1"))?;
+        kvs.add_app(App::example("a2"))?;
+        assert_eq!(
+            2,
+            server
+                .application_list(ApplicationListInfo::empty())?
+                .appList
+                .len()
+        );
+        kvs.del_app(&App::example("a1").key())?;
+        assert_eq!(
+            1,
+            server
+                .application_list(ApplicationListInfo::empty())?
+                .appList
+                .len()
+        );
+
+        // unreachable server
+        let server = RedisApplicationListServer::from_params("url=redis://127.0.0.1:1/")?;
+        assert!(server.status().is_err());
+        assert!(server
+            .application_list(ApplicationListInfo::empty())
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_redis_app_context_server() -> Result<(), String> {
+        let (url, _server) = test_server(3);
+        assert!(RedisAppContextServer::from_params(&format!("url={};max=2", url)).is_err());
+        assert!(RedisAppContextServer::from_params(&format!("url={};platform=p0", url)).is_err());
+        assert!(RedisAppContextServer::from_params("platform=p0;max=2").is_err());
+
+        let params = format!("url={};platform=p0;max=2", url);
+        let mut server1 = RedisAppContextServer::from_params(&params)?;
+        let mut server2 = RedisAppContextServer::from_params(&params)?;
+        server1.status()?;
+
+        // the platform must exist to retrieve the reference URI
+        assert!(server1.new_context(&mut request()).is_err());
+        RedisKvs::new(&url)?.add_platform(Platform::example("p0"))?;
+
+        let mut a = request();
+        server1.new_context(&mut a)?;
+        let context_id = a.contextId.clone().unwrap();
+        assert_eq!(
+            Some("http://p0/apps".to_string()),
+            a.appInfo.userAppInstanceInfo[0].referenceURI
+        );
+
+        // the context is visible to the other instance
+        assert_eq!(vec![context_id.clone()], server2.list_contexts()?);
+        a.callbackReference = Some("http://callback".to_string());
+        server2.update_context(&mut a)?;
+        assert_eq!(
+            Some("http://callback".to_string()),
+            server1.get_context(&context_id)?.callbackReference
+        );
+
+        // the maximum number of contexts is shared
+        server2.new_context(&mut request())?;
+        assert!(server1.new_context(&mut request()).is_err());
+
+        // reference URI
+        assert!(server1.update_reference_uri(&context_id, "http://other")?);
+        assert!(!server2.update_reference_uri(&context_id, "http://other")?);
+        assert_eq!(
+            Some("http://other".to_string()),
+            server2
+                .get_context(&context_id)?
+                .appInfo
+                .userAppInstanceInfo[0]
+                .referenceURI
+        );
+
+        // QKD key IDs
+        server1.set_qkd_key_ids(&context_id, &[Some(vec!["key1".to_string()])])?;
+        assert_eq!(
+            Some(vec!["key1".to_string()]),
+            server2
+                .get_context(&context_id)?
+                .appInfo
+                .userAppInstanceInfo[0]
+                .qkdKeyIds
+        );
+
+        // expiration time of the lease
+        let expires_at = TimeStamp {
+            seconds: 10,
+            nanoSeconds: 0,
+        };
+        server1.set_expires_at(&context_id, Some(expires_at))?;
+        assert_eq!(
+            Some(expires_at),
+            server2.get_context(&context_id)?.expiresAt
+        );
+
+        // stored in the list of contexts of the platform
+        let contexts = raw_list(&url, "contexts:p0");
+        assert_eq!(2, contexts.len());
+        let kvs = RedisKvs::new(&url)?;
+        assert_eq!(2, kvs.contexts("p0")?.len());
+        let operations = kvs.contexts_operations()?;
+        assert_eq!(6, operations);
+
+        // delete
+        server2.del_context(&context_id)?;
+        assert!(server1.del_context(&context_id).is_err());
+        assert!(server1.get_context(&context_id).is_err());
+        assert_eq!(1, server1.list_contexts()?.len());
+        assert_eq!(operations + 1, kvs.contexts_operations()?);
+
+        // static reference URI
+        let mut server3 = RedisAppContextServer::from_params(&format!(
+            "url={};platform=p1;max=2;uri=http://static",
+            url
+        ))?;
+        let mut a = request();
+        server3.new_context(&mut a)?;
+        assert_eq!(
+            Some("http://static".to_string()),
+            a.appInfo.userAppInstanceInfo[0].referenceURI
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_redis_concurrent_contexts() -> Result<(), String> {
+        let (url, _server) = test_server(4);
+        RedisKvs::new(&url)?.add_platform(Platform::example("p0"))?;
+        let params = format!("url={};platform=p0;max=1000", url);
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let params = params.clone();
+                std::thread::spawn(move || {
+                    let mut server = RedisAppContextServer::from_params(&params).unwrap();
+                    for _ in 0..10 {
+                        server.new_context(&mut request()).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(40, RedisKvs::new(&url)?.contexts("p0")?.len());
+        Ok(())
+    }
+}
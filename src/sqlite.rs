@@ -0,0 +1,499 @@
+//! AppContextServer storing the active contexts in an embedded SQLite database.
+//!
+//! Every context is stored as a JSON document, together with indexed columns
+//! used to look up the contexts by associateDevAppId, appDId, and referenceURI.
+
+use crate::appcontextserver::AppContextServer;
+use crate::error::LcmpError;
+use crate::messages::{AppContext, TimeStamp};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Schema of the database, created if not existing.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS contexts (
+        contextId TEXT PRIMARY KEY NOT NULL,
+        associateDevAppId TEXT NOT NULL,
+        appDId TEXT,
+        context TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS contexts_associateDevAppId ON contexts (associateDevAppId);
+    CREATE INDEX IF NOT EXISTS contexts_appDId ON contexts (appDId);
+    CREATE TABLE IF NOT EXISTS reference_uris (
+        contextId TEXT NOT NULL REFERENCES contexts (contextId) ON DELETE CASCADE,
+        referenceURI TEXT NOT NULL,
+        PRIMARY KEY (contextId, referenceURI)
+    );
+    CREATE INDEX IF NOT EXISTS reference_uris_referenceURI ON reference_uris (referenceURI);
+";
+
+fn db_error(err: rusqlite::Error) -> LcmpError {
+    LcmpError::BackendUnavailable(format!("database error: {}", err))
+}
+
+fn not_found(context_id: &str) -> LcmpError {
+    LcmpError::NotFound(format!("context ID not found: {}", context_id))
+}
+
+/// AppContextServer with the same behavior as the single one, i.e., accepting
+/// new contexts up to a maximum and always returning the same referenceURI,
+/// which stores the active contexts in SQLite.
+pub struct SqliteAppContextServer {
+    connection: Mutex<Connection>,
+    /// Maximum number of active contexts.
+    max_contexts: usize,
+    /// Reference URI assigned to all the application contexts.
+    reference_uri: String,
+    /// Last context retrieved with get_context.
+    fetched: Option<AppContext>,
+}
+
+impl SqliteAppContextServer {
+    /// Create a server using the database at the given path, which is created
+    /// if not existing. The path ":memory:" is used for a transient in-memory database.
+    pub fn new(path: &str, max_contexts: usize, reference_uri: &str) -> Result<Self, String> {
+        let connection = match path {
+            ":memory:" => Connection::open_in_memory(),
+            _ => Connection::open(path),
+        }
+        .map_err(|x| format!("could not open the database {}: {}", path, x))?;
+        connection
+            .execute_batch("PRAGMA foreign_keys = ON;")
+            .and_then(|_| connection.execute_batch(SCHEMA))
+            .map_err(|x| format!("could not initialize the database {}: {}", path, x))?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+            max_contexts,
+            reference_uri: reference_uri.to_string(),
+            fetched: None,
+        })
+    }
+
+    /// Create a server from a string of semicolon-separated key=value parameters:
+    /// path (database file), max (maximum number of active contexts), uri (reference URI).
+    pub fn from_params(params: &str) -> Result<Self, String> {
+        let mut path = None;
+        let mut max_contexts = None;
+        let mut reference_uri = None;
+        for param in params.split(';').filter(|x| !x.is_empty()) {
+            let (key, value) = param
+                .split_once('=')
+                .ok_or(format!("invalid sqlite parameter: {}", param))?;
+            let invalid = || format!("invalid value for {}: {}", key, value);
+            match key {
+                "path" => path = Some(value.to_string()),
+                "max" => max_contexts = Some(value.parse::<usize>().map_err(|_| invalid())?),
+                "uri" => reference_uri = Some(value.to_string()),
+                _ => return Err(format!("unknown sqlite parameter: {}", key)),
+            }
+        }
+        match (path, max_contexts, reference_uri) {
+            (Some(path), Some(max_contexts), Some(reference_uri))
+                if !path.is_empty() && !reference_uri.is_empty() =>
+            {
+                Self::new(&path, max_contexts, &reference_uri)
+            }
+            _ => Err("sqlite parameters path, max, and uri are mandatory".to_string()),
+        }
+    }
+
+    /// Return the context with the given identifier, if any.
+    fn load(connection: &Connection, context_id: &str) -> Result<Option<AppContext>, LcmpError> {
+        let content: Option<String> = connection
+            .query_row(
+                "SELECT context FROM contexts WHERE contextId = ?1",
+                params![context_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error)?;
+        match content {
+            Some(x) => serde_json::from_str(&x).map(Some).map_err(|x| {
+                LcmpError::Internal(format!("invalid context in the database: {}", x))
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// Insert or replace a context, together with its reference URIs.
+    fn store(transaction: &Transaction, app_context: &AppContext) -> Result<(), LcmpError> {
+        let context_id = app_context.contextId.clone().unwrap_or_default();
+        let content =
+            serde_json::to_string(app_context).map_err(|x| LcmpError::Internal(x.to_string()))?;
+        transaction
+            .execute(
+                "DELETE FROM reference_uris WHERE contextId = ?1",
+                params![context_id],
+            )
+            .and_then(|_| {
+                transaction.execute(
+                    "INSERT OR REPLACE INTO contexts (contextId, associateDevAppId, appDId, context)
+                    VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        context_id,
+                        app_context.associate_dev_app_id(),
+                        app_context.appInfo.appDId,
+                        content
+                    ],
+                )
+            })
+            .map_err(db_error)?;
+        for info in &app_context.appInfo.userAppInstanceInfo {
+            if let Some(reference_uri) = &info.referenceURI {
+                transaction
+                    .execute(
+                        "INSERT OR IGNORE INTO reference_uris (contextId, referenceURI) VALUES (?1, ?2)",
+                        params![context_id, reference_uri],
+                    )
+                    .map_err(db_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Store a context in a new transaction.
+    fn store_one(&self, app_context: &AppContext) -> Result<(), LcmpError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction().map_err(db_error)?;
+        Self::store(&transaction, app_context)?;
+        transaction.commit().map_err(db_error)
+    }
+
+    /// Return the identifiers of the contexts returned by a query with one parameter.
+    fn query_ids(&self, query: &str, value: &str) -> Result<Vec<String>, LcmpError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(query).map_err(db_error)?;
+        let rows = statement
+            .query_map(params![value], |row| row.get(0))
+            .map_err(db_error)?;
+        rows.collect::<Result<Vec<String>, _>>().map_err(db_error)
+    }
+}
+
+impl AppContextServer for SqliteAppContextServer {
+    /// If the maximum number of contexts is exceeded, the command is rejected.
+    /// Otherwise the static reference URI is returned upon accepting the next context.
+    fn new_context(&mut self, app_context: &mut AppContext) -> Result<(), LcmpError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction().map_err(db_error)?;
+        let num_contexts: usize = transaction
+            .query_row("SELECT COUNT(*) FROM contexts", [], |row| row.get(0))
+            .map_err(db_error)?;
+        if num_contexts >= self.max_contexts {
+            return Err(LcmpError::QuotaExceeded(format!(
+                "Maximum number of active contexts reached {}",
+                self.max_contexts
+            )));
+        }
+        app_context
+            .valid_request()
+            .map_err(LcmpError::InvalidRequest)?;
+
+        let mut accepted = app_context.clone();
+        accepted.contextId = Some(Uuid::simple(Uuid::new_v4()).to_string());
+        accepted.assign_reference_uri(&self.reference_uri);
+        Self::store(&transaction, &accepted)?;
+        transaction.commit().map_err(db_error)?;
+        *app_context = accepted;
+        Ok(())
+    }
+
+    fn del_context(&mut self, context_id: &str) -> Result<(), LcmpError> {
+        let deleted = self
+            .connection
+            .lock()
+            .unwrap()
+            .execute(
+                "DELETE FROM contexts WHERE contextId = ?1",
+                params![context_id],
+            )
+            .map_err(db_error)?;
+        match deleted {
+            0 => Err(not_found(context_id)),
+            _ => Ok(()),
+        }
+    }
+
+    fn get_context(&mut self, context_id: &str) -> Result<&AppContext, LcmpError> {
+        let app_context = Self::load(&self.connection.lock().unwrap(), context_id)?;
+        match app_context {
+            Some(x) => Ok(self.fetched.insert(x)),
+            None => Err(not_found(context_id)),
+        }
+    }
+
+    /// Update an active context.
+    /// Only the callbackReference is allowed to be updated. If the other
+    /// fields do not match exactly, then the command is denied.
+    fn update_context(&mut self, app_context: &mut AppContext) -> Result<(), LcmpError> {
+        let context_id = app_context
+            .contextId
+            .clone()
+            .ok_or(LcmpError::InvalidRequest(
+                "context ID not specified in the request".to_string(),
+            ))?;
+        let mut stored = Self::load(&self.connection.lock().unwrap(), &context_id)?
+            .ok_or_else(|| not_found(&context_id))?;
+        if !stored.identical_except_callback_reference(app_context) {
+            return Err(LcmpError::Conflict(
+                "AppContext in the request does not match that in the server".to_string(),
+            ));
+        }
+        stored.callbackReference = app_context.callbackReference.clone();
+        self.store_one(&stored)
+    }
+
+    fn list_contexts(&mut self) -> Result<Vec<String>, LcmpError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT contextId FROM contexts")
+            .map_err(db_error)?;
+        let rows = statement
+            .query_map([], |row| row.get(0))
+            .map_err(db_error)?;
+        rows.collect::<Result<Vec<String>, _>>().map_err(db_error)
+    }
+
+    /// Return an error if the database cannot be queried.
+    fn status(&self) -> Result<(), LcmpError> {
+        self.connection
+            .lock()
+            .unwrap()
+            .query_row("SELECT 1", [], |_| Ok(()))
+            .map_err(db_error)
+    }
+
+    fn reference_uri(&mut self, _app_context: &AppContext) -> Result<Option<String>, LcmpError> {
+        Ok(Some(self.reference_uri.clone()))
+    }
+
+    /// Change the reference URI of all the user application instances of the context.
+    fn update_reference_uri(
+        &mut self,
+        context_id: &str,
+        reference_uri: &str,
+    ) -> Result<bool, LcmpError> {
+        let mut stored = Self::load(&self.connection.lock().unwrap(), context_id)?
+            .ok_or_else(|| not_found(context_id))?;
+        let mut changed = false;
+        for info in &mut stored.appInfo.userAppInstanceInfo {
+            if info.referenceURI.as_deref() != Some(reference_uri) {
+                info.referenceURI = Some(reference_uri.to_string());
+                changed = true;
+            }
+        }
+        if changed {
+            self.store_one(&stored)?;
+        }
+        Ok(changed)
+    }
+
+    fn set_qkd_key_ids(
+        &mut self,
+        context_id: &str,
+        key_ids: &[Option<Vec<String>>],
+    ) -> Result<(), LcmpError> {
+        let mut stored = Self::load(&self.connection.lock().unwrap(), context_id)?
+            .ok_or_else(|| not_found(context_id))?;
+        stored.assign_qkd_key_ids(key_ids);
+        self.store_one(&stored)
+    }
+
+    fn set_expires_at(
+        &mut self,
+        context_id: &str,
+        expires_at: Option<TimeStamp>,
+    ) -> Result<(), LcmpError> {
+        let mut stored = Self::load(&self.connection.lock().unwrap(), context_id)?
+            .ok_or_else(|| not_found(context_id))?;
+        stored.expiresAt = expires_at;
+        self.store_one(&stored)
+    }
+
+    fn contexts_by_associate_dev_app_id(
+        &mut self,
+        associate_dev_app_id: &str,
+    ) -> Result<Vec<String>, LcmpError> {
+        self.query_ids(
+            "SELECT contextId FROM contexts WHERE associateDevAppId = ?1",
+            associate_dev_app_id,
+        )
+    }
+
+    fn contexts_by_appdid(&mut self, appdid: &str) -> Result<Vec<String>, LcmpError> {
+        self.query_ids("SELECT contextId FROM contexts WHERE appDId = ?1", appdid)
+    }
+
+    fn contexts_by_reference_uri(&mut self, reference_uri: &str) -> Result<Vec<String>, LcmpError> {
+        self.query_ids(
+            "SELECT contextId FROM reference_uris WHERE referenceURI = ?1",
+            reference_uri,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(appdid: Option<&str>) -> AppContext {
+        let mut a = AppContext::request_from_name_provider("my_app_name", "my_app_provider");
+        a.appInfo.appDId = appdid.map(|x| x.to_string());
+        a
+    }
+
+    fn sorted(mut x: Vec<String>) -> Vec<String> {
+        x.sort();
+        x
+    }
+
+    #[test]
+    fn test_sqlite_from_params() {
+        assert!(SqliteAppContextServer::from_params("path=:memory:;max=10;uri=URI").is_ok());
+        assert!(SqliteAppContextServer::from_params("path=:memory:;max=10").is_err());
+        assert!(SqliteAppContextServer::from_params("max=10;uri=URI").is_err());
+        assert!(SqliteAppContextServer::from_params("path=:memory:;max=x;uri=URI").is_err());
+        assert!(SqliteAppContextServer::from_params("path=:memory:;max=1;uri=U;x=1").is_err());
+        assert!(SqliteAppContextServer::from_params("path=/non/existing/db;max=1;uri=U").is_err());
+    }
+
+    #[test]
+    fn test_sqlite_app_context_server() -> Result<(), String> {
+        let mut server = SqliteAppContextServer::new(":memory:", 2, "URI")?;
+        server.status()?;
+
+        let mut a = request(Some("appdid1"));
+        server.new_context(&mut a)?;
+        let context_id = a.contextId.clone().unwrap();
+        assert_eq!(
+            Some("URI".to_string()),
+            a.appInfo.userAppInstanceInfo[0].referenceURI
+        );
+        assert_eq!(vec![context_id.clone()], server.list_contexts()?);
+        assert!(a.identical_except_callback_reference(server.get_context(&context_id)?));
+
+        // invalid request
+        assert!(server.new_context(&mut a.clone()).is_err());
+
+        // maximum number of contexts
+        server.new_context(&mut request(None))?;
+        assert!(server.new_context(&mut request(None)).is_err());
+
+        // update the callback reference, but not the other fields
+        a.callbackReference = Some("http://callback".to_string());
+        server.update_context(&mut a)?;
+        assert_eq!(
+            Some("http://callback".to_string()),
+            server.get_context(&context_id)?.callbackReference
+        );
+        let mut b = a.clone();
+        b.appInfo.appDId = Some("another".to_string());
+        assert!(server.update_context(&mut b).is_err());
+
+        // update the reference URI
+        assert!(server.update_reference_uri(&context_id, "URI2")?);
+        assert!(!server.update_reference_uri(&context_id, "URI2")?);
+        assert_eq!(
+            Some("URI2".to_string()),
+            server.get_context(&context_id)?.appInfo.userAppInstanceInfo[0].referenceURI
+        );
+
+        // the QKD key IDs are stored, and ignored in the updates
+        let mut a = server.get_context(&context_id)?.clone();
+        server.set_qkd_key_ids(&context_id, &[Some(vec!["key1".to_string()])])?;
+        server.update_context(&mut a)?;
+        assert_eq!(
+            Some(vec!["key1".to_string()]),
+            server.get_context(&context_id)?.appInfo.userAppInstanceInfo[0].qkdKeyIds
+        );
+
+        // the expiration time of the lease is stored
+        let expires_at = TimeStamp {
+            seconds: 10,
+            nanoSeconds: 0,
+        };
+        server.set_expires_at(&context_id, Some(expires_at))?;
+        assert_eq!(Some(expires_at), server.get_context(&context_id)?.expiresAt);
+
+        // delete
+        server.del_context(&context_id)?;
+        assert!(server.del_context(&context_id).is_err());
+        assert!(server.get_context(&context_id).is_err());
+        assert!(server.update_reference_uri(&context_id, "URI").is_err());
+        assert!(server.set_qkd_key_ids(&context_id, &[None]).is_err());
+        assert!(server.set_expires_at(&context_id, None).is_err());
+        assert_eq!(1, server.list_contexts()?.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sqlite_lookups() -> Result<(), String> {
+        let mut server = SqliteAppContextServer::new(":memory:", 10, "URI")?;
+        let mut ids = vec![];
+        for appdid in [Some("appdid1"), Some("appdid1"), Some("appdid2"), None] {
+            let mut a = request(appdid);
+            server.new_context(&mut a)?;
+            ids.push(a.contextId.unwrap());
+        }
+        let dev_app_id = server
+            .get_context(&ids[2])?
+            .associate_dev_app_id()
+            .to_string();
+
+        assert_eq!(
+            sorted(ids[0..2].to_vec()),
+            sorted(server.contexts_by_appdid("appdid1")?)
+        );
+        assert!(server.contexts_by_appdid("unknown")?.is_empty());
+        assert_eq!(
+            vec![ids[2].clone()],
+            server.contexts_by_associate_dev_app_id(&dev_app_id)?
+        );
+        assert_eq!(
+            sorted(ids.clone()),
+            sorted(server.contexts_by_reference_uri("URI")?)
+        );
+
+        server.update_reference_uri(&ids[3], "URI2")?;
+        assert_eq!(
+            vec![ids[3].clone()],
+            server.contexts_by_reference_uri("URI2")?
+        );
+        assert_eq!(3, server.contexts_by_reference_uri("URI")?.len());
+
+        // the reference URIs are removed with the context
+        server.del_context(&ids[3])?;
+        assert!(server.contexts_by_reference_uri("URI2")?.is_empty());
+
+        // same results with the default implementation scanning all the contexts
+        let mut default = |f: &dyn Fn(&AppContext) -> bool| -> Result<Vec<String>, LcmpError> {
+            server.filter_contexts(f).map(sorted)
+        };
+        assert_eq!(
+            sorted(ids[0..2].to_vec()),
+            default(&|x| x.appInfo.appDId.as_deref() == Some("appdid1"))?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sqlite_persistence() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("lcmp-{}.db", Uuid::new_v4().simple()));
+        let path = path.to_str().unwrap().to_string();
+        let mut a = request(Some("appdid1"));
+        {
+            let mut server = SqliteAppContextServer::new(&path, 10, "URI")?;
+            server.new_context(&mut a)?;
+        }
+        let mut server = SqliteAppContextServer::new(&path, 10, "URI")?;
+        let context_id = a.contextId.clone().unwrap();
+        assert_eq!(vec![context_id.clone()], server.list_contexts()?);
+        assert_eq!(vec![context_id], server.contexts_by_appdid("appdid1")?);
+        drop(server);
+        let _ = std::fs::remove_file(&path);
+        Ok(())
+    }
+}